@@ -108,4 +108,256 @@ pub struct Args {
     /// was done at.
     #[arg(long, default_value_t = false)]
     pub validate_match: bool,
+
+    /// Search a single position (see `--position`) once at
+    /// `--search-depth`, print the best move and eval as one JSON line
+    /// and exit. Exit code is 0 on success and 2 if the position can't
+    /// be parsed.
+    #[arg(long, default_value_t = false)]
+    pub oneshot: bool,
+
+    /// Position to analyse, either as a book key
+    /// (`"<black>,<white>,<white_to_move 0|1>"`) or as a 64-cell board
+    /// diagram (`x` black, `o` white, `.` empty, rank 8 first) with an
+    /// optional trailing side-to-move character (`x`/`o`).
+    #[arg(long, default_value_t = String::new())]
+    pub position: String,
+
+    /// Memoize leaf evaluations in a lockless cache shared by all search
+    /// threads. Off by default so its effect can be measured with
+    /// `--benchmark` (which also reports the cache hit rate).
+    #[arg(long, default_value_t = false)]
+    pub eval_cache: bool,
+
+    /// Format `--generate-book` writes the book in: `json` or `binary`
+    /// (compact, quicker to load). Books are read in either format.
+    #[arg(long, default_value_t = String::from("json"))]
+    pub book_format: String,
+
+    /// Skip book positions whose heaviest move weighs less than this when
+    /// loading the book for play, and with `--prune-book`.
+    #[arg(long, default_value_t = 0)]
+    pub book_min_weight: u32,
+
+    /// Remove the positions below `--book-min-weight` from the book at
+    /// `--book-path` and write it back in `--book-format`. A book that
+    /// would be left empty is not written.
+    #[arg(long, default_value_t = false)]
+    pub prune_book: bool,
+
+    /// Print a summary of the book at `--book-path`: position counts by
+    /// side to move and disc count, and moves per position.
+    #[arg(long, default_value_t = false)]
+    pub book_stats: bool,
+
+    /// Write the lines of the book at `--book-path` to this file, one
+    /// transcript (`f5d6c3...`) per line.
+    #[arg(long, default_value_t = String::new())]
+    pub export_book: String,
+
+    /// Check that every move in the book at `--book-path` is legal in its
+    /// position, listing the positions that fail; exits 1 if any do.
+    #[arg(long, default_value_t = false)]
+    pub validate_book: bool,
+
+    /// Book generation: rewrite the book file after this many new
+    /// positions. In between, new positions are appended to
+    /// `<book-path>.log`, which the next run replays if this one stops.
+    #[arg(long, default_value_t = 1000)]
+    pub book_save_interval: u32,
+
+    /// When generating an opening book, start the search from the
+    /// position reached by this transcript (e.g. `f5d6c3`) instead of
+    /// the standard position, to deepen the book along a chosen line.
+    /// Depths are counted from the end of the line.
+    #[arg(long, default_value_t = String::new())]
+    pub book_line: String,
+
+    /// Run a round-robin tournament between eval configs given as a
+    /// `;`-separated list of coefficient strings (same format as
+    /// `--tune-initial-coefs`; `default` means `DEFAULT_CFG`). Each
+    /// pairing is a two-colour match at `--search-depth` over the
+    /// `--tune-ply` position set.
+    #[arg(long, default_value_t = String::new())]
+    pub tournament: String,
+
+    /// Tournament: append each finished pairing to this file (JSON
+    /// lines). Re-running with the same file skips pairings already
+    /// recorded, so an interrupted tournament can be resumed.
+    #[arg(long, default_value_t = String::new())]
+    pub tournament_results: String,
+
+    /// Multiplayer: log evals from our assigned colour's perspective
+    /// (positive = good for us) instead of Black's.
+    #[arg(long, default_value_t = false)]
+    pub eval_our_perspective: bool,
+
+    /// Ignore the opening book in local and multiplayer games even when
+    /// `--book-path` is set, so every move comes from search.
+    #[arg(long, default_value_t = false)]
+    pub no_book: bool,
+
+    /// Endgame selectivity: 0 solves exactly; 1-3 let ProbCut-style
+    /// shallow searches cut nodes once the search reaches the end of the
+    /// game, trading a small error rate for speed. With
+    /// `--benchmark-endgame`, a level above 0 also reports node counts and
+    /// win/draw/loss disagreements against the exact search.
+    #[arg(long, default_value_t = 0)]
+    pub endgame_selectivity: u8,
+
+    /// Book generation: beyond `--full-depth`, also expand moves whose
+    /// eval is within this margin of the best one (0 = best move only).
+    #[arg(long, default_value_t = 0)]
+    pub book_margin: i32,
+
+    /// Local and multiplayer games: rewrite this file (JSON) after every
+    /// move with the board, the move list and, once over, the result.
+    #[arg(long, default_value_t = String::new())]
+    pub state_file: String,
+
+    /// Continue the game recorded in this `--state-file` output. Local
+    /// games replay its moves; multiplayer games also rejoin its game id.
+    #[arg(long, default_value_t = String::new())]
+    pub resume_game: String,
+
+    /// Animate a finished game in the terminal: either a transcript
+    /// (e.g. `f5d6c3...`) or a file written by `--state-file`.
+    #[arg(long, default_value_t = String::new())]
+    pub replay: String,
+
+    /// Delay between plies of an animated replay, in milliseconds. A
+    /// local game is replayed once finished when this is set; `--replay`
+    /// defaults to 500 ms.
+    #[arg(long, default_value_t = 0)]
+    pub replay_speed: u64,
+
+    /// Local and multiplayer games: total thinking time per side for the
+    /// whole game, in milliseconds. Replaces the fixed `--search-depth`
+    /// with timed iterative deepening (0 = off).
+    #[arg(long, default_value_t = 0)]
+    pub time_budget: u64,
+
+    /// Time budget: weight of a midgame move relative to an opening or
+    /// endgame move.
+    #[arg(long, default_value_t = 2.0)]
+    pub time_midgame_weight: f64,
+
+    /// Time budget: fraction of the remaining time held back for the
+    /// endgame solve.
+    #[arg(long, default_value_t = 0.25)]
+    pub time_endgame_reserve: f64,
+
+    /// Time budget: empties from which the search solves to the end.
+    #[arg(long, default_value_t = 14)]
+    pub time_solve_empties: u32,
+
+    /// Search `--position` (or a few benchmark positions) several times at
+    /// `--search-depth` from a cold TT and check the results agree. Exits
+    /// non-zero if the serial search is not deterministic.
+    #[arg(long, default_value_t = false)]
+    pub verify_determinism: bool,
+
+    /// Benchmark move generation: allocating vs. iterator vs. raw mask.
+    #[arg(long, default_value_t = false)]
+    pub benchmark_movegen: bool,
+
+    /// How to choose among root moves with equal evals: `first-found`,
+    /// `lowest-square`, `most-mobility-for-us` or
+    /// `least-mobility-for-opponent`.
+    #[arg(long, default_value_t = String::from("first-found"))]
+    pub tiebreak: String,
+
+    /// Disable the transposition table, e.g. to compare `--benchmark`
+    /// node counts and chosen moves against a plain alpha-beta search.
+    /// Also bypasses `--eval-cache`.
+    #[arg(long, default_value_t = false)]
+    pub no_tt: bool,
+
+    /// Transposition table size in megabytes, rounded down to a power of
+    /// two number of slots.
+    #[arg(long, default_value_t = crate::tt::DEFAULT_TT_MB)]
+    pub tt_size_mb: usize,
+
+    /// Print nodes, leaves, time and NPS after every engine move. The
+    /// fixed-depth search then runs serially so the counts are exact.
+    #[arg(long, default_value_t = false)]
+    pub search_stats: bool,
+
+    /// Disable late move reductions, e.g. to check that a move or
+    /// `--benchmark` node count changes only because of them.
+    #[arg(long, default_value_t = false)]
+    pub no_lmr: bool,
+
+    /// `--oneshot`: also list this many best moves, each with its own
+    /// full-window eval.
+    #[arg(long, default_value_t = 1)]
+    pub multipv: usize,
+
+    /// Hard cap in milliseconds on a fixed-depth engine move (0 = none).
+    /// A search still running then is stopped and the deepest completed
+    /// iteration played.
+    #[arg(long, default_value_t = 0)]
+    pub move_time_limit: u64,
+
+    /// Plies of the parallel search whose moves are split among threads
+    /// (1 = root moves only); deeper plies run serially per subtree.
+    #[arg(long, default_value_t = 2)]
+    pub parallel_depth: u32,
+
+    /// Iterate fixed-depth engine moves with MTD(f) null-window searches
+    /// instead of aspiration windows.
+    #[arg(long, default_value_t = false)]
+    pub mtdf: bool,
+
+    /// Multiplayer: while waiting for the opponent, search the position
+    /// after their expected reply so our next search starts from a warm
+    /// transposition table.
+    #[arg(long, default_value_t = false)]
+    pub ponder: bool,
+
+    /// Vary the opening: play a book move drawn in proportion to its
+    /// weight, and out of book pick at random among the root moves within
+    /// `--random-margin` of the best until the midgame.
+    #[arg(long, default_value_t = false)]
+    pub randomize_opening: bool,
+
+    /// Seed for `--randomize-opening`; the same seed replays the same
+    /// choices.
+    #[arg(long, default_value_t = 0)]
+    pub random_seed: u64,
+
+    /// `--randomize-opening`: eval margin below the best move within
+    /// which root moves are still candidates.
+    #[arg(long, default_value_t = 20)]
+    pub random_margin: i32,
+
+    /// `--oneshot`: also list every root move with its eval and the nodes
+    /// its subtree took (serial search).
+    #[arg(long, default_value_t = false)]
+    pub root_nodes: bool,
+
+    /// `--oneshot`: also report the static eval blended between two
+    /// configs by disc count, given as `<opening coefs>;<endgame coefs>`
+    /// (empty = default config).
+    #[arg(long, default_value_t = String::new())]
+    pub phase_configs: String,
+
+    /// Disc count at which `--phase-configs` weighs both configs equally.
+    #[arg(long, default_value_t = 34)]
+    pub phase_crossover: u32,
+
+    /// JSON file of eval weights (field names as in `EvalCfg`) the engine
+    /// plays with in local and multiplayer games instead of the defaults.
+    #[arg(long, default_value_t = String::new())]
+    pub eval_config: String,
+
+    /// `--oneshot`: also report the positional score from a 64-square
+    /// weight table read from this file (rank 8 first, like a diagram).
+    #[arg(long, default_value_t = String::new())]
+    pub square_weights: String,
+
+    /// `--oneshot`: also report the static eval term by term for each
+    /// colour, to see which term drives it.
+    #[arg(long, default_value_t = false)]
+    pub eval_breakdown: bool,
 }
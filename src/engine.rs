@@ -1,10 +1,17 @@
 use rayon::prelude::*;
 use reversi_tools::position::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
+use crate::evalcache::{eval_cache, eval_cache_enabled};
 use crate::tt::{
-    hash_position, tt, BOUND_EXACT, BOUND_LOWER, BOUND_NONE, BOUND_UPPER, NO_MOVE_SQ,
+    hash_position, tt, tt_enabled, TranspositionTable, BOUND_EXACT, BOUND_LOWER, BOUND_UPPER,
+    NO_MOVE_SQ,
 };
-use crate::utils::splitmix64;
+use crate::utils::{splitmix64, Rng64};
 
 // --------------------------------------------------------------------------
 // Constants shared across the engine
@@ -12,18 +19,68 @@ use crate::utils::splitmix64;
 
 const CORNER_MASK: u64 = 0x8100_0000_0000_0081;
 const EDGE_MASK: u64 = 0x42C3_0000_0000_C342;
-const ANTIEDGE_MASK: u64 = 4_792_111_478_498_951_490;
-const ANTICORNER_MASK: u64 = 18_577_348_462_920_192;
+const ANTIEDGE_MASK: u64 = 0x4281_0000_0000_8142;
+const ANTICORNER_MASK: u64 = 0x0042_0000_0000_4200;
+
+// Each corner with its X-square (diagonally inside it) and its two
+// C-squares (beside it on the edges). The eval only penalises those
+// squares while their corner is still empty: that is when occupying them
+// can hand the corner to the opponent.
+const CORNER_NEIGHBOURS: [(u64, u64, u64); 4] = [
+    (1 << 0, 1 << 9, (1 << 1) | (1 << 8)),
+    (1 << 7, 1 << 14, (1 << 6) | (1 << 15)),
+    (1 << 56, 1 << 49, (1 << 48) | (1 << 57)),
+    (1 << 63, 1 << 54, (1 << 55) | (1 << 62)),
+];
 
 // Mate magnitude: scores whose absolute value exceeds this threshold are
 // mate-distance scores that get shrunk by one each ply as they propagate up.
 const MATE_THRESHOLD: i32 = 5000;
 
+// Score of a finished game for the side that won it, before mate-distance
+// shrinking. Heuristic evals stay well below MATE_THRESHOLD.
+const WIN_SCORE: i32 = 10_000;
+
+/// Largest static eval magnitude. The default coefficients come nowhere
+/// near it, but a tuned or hand-written config with large weights could;
+/// evals are clamped to it so such a score is never mistaken for a won
+/// game and shrunk (or reported) as one. A config can lower the bound
+/// with `EvalCfg::max_heuristic_eval` but not raise it: past
+/// `MATE_THRESHOLD` a score reads as a won game.
+pub const MAX_HEURISTIC_EVAL: i32 = MATE_THRESHOLD;
+
+/// Bounds of the full search window. Every score the search can return -
+/// heuristic evals and (mate-adjusted) `WIN_SCORE`s alike - lies strictly
+/// inside `(MIN_EVAL, MAX_EVAL)`, so a search called with them never
+/// fails high or low.
+pub const MAX_EVAL: i32 = 2 * WIN_SCORE;
+pub const MIN_EVAL: i32 = -MAX_EVAL;
+
+// Longest run of corner captures `quiesce` follows past the horizon -
+// with four corners on the board, all of them.
+const QUIESCENCE_PLIES: u32 = 4;
+
 // Below this remaining depth the branching factor is small enough that the
 // `compute_moves`-per-candidate cost of mobility-based ordering exceeds the
 // pruning savings, so we fall back to the cheap bucket ordering.
 const MOBILITY_ORDER_MIN_DEPTH: u32 = 3;
 
+// `search_timed`: assumed ratio between the time of the next iterative-
+// deepening iteration and the last one. Reversi's effective branching
+// factor with this move ordering is typically 3-5.
+const TIMED_GROWTH_ESTIMATE: f64 = 4.0;
+
+// `effective_depth`: once the end of the game is at most this many plies
+// beyond the requested depth, search to the end instead. Endgame trees
+// are narrow (few empties, forced moves and passes), so the extra plies
+// are cheap and turn a heuristic score into an exact one.
+const AUTO_SOLVE_EXTRA_PLIES: u32 = 4;
+
+// Half-width of the aspiration window `search_iterative` opens around the
+// previous iteration's score. Most iterations move the score by less;
+// larger swings cost a re-search.
+const ASPIRATION_WINDOW: i32 = 50;
+
 // Killer-move table: two slots per ply, remembering the moves that most
 // recently caused a beta cutoff at that ply in a sibling subtree. After
 // the TT move (which is per-position), killers are the next candidates
@@ -32,6 +89,134 @@ const MOBILITY_ORDER_MIN_DEPTH: u32 = 3;
 // the engine is ever asked to run - 64 is more than enough for 8x8 Othello.
 const KILLER_PLIES: usize = 64;
 
+// Endgame selectivity (ProbCut). Once the remaining search reaches the end
+// of the game its scores are won/drawn/lost, so a node at `depth >=
+// PROBCUT_MIN_DEPTH` predicts the final disc margin with a search of
+// `depth / PROBCUT_DEPTH_DIVISOR` plies scored by disc difference
+// (`shallow_margin`, the scale `margin_to_eval` maps onto the search's).
+// If the prediction, less the level's margin, still reaches the result
+// beta asks for (or, plus the margin, stays under the one alpha allows)
+// the node is cut without the full solve. Level 0 disables it (exact);
+// higher levels use smaller margins, in discs, and so cut more often at
+// a higher risk of a wrong result.
+const PROBCUT_MIN_DEPTH: u32 = 6;
+const PROBCUT_DEPTH_DIVISOR: u32 = 3;
+const PROBCUT_MARGINS: [i32; 4] = [0, 16, 10, 6];
+
+static ENDGAME_SELECTIVITY: AtomicU8 = AtomicU8::new(0);
+
+/// Set the endgame selectivity level (`--endgame-selectivity`). Levels
+/// above the highest tabulated one behave like it.
+pub fn set_endgame_selectivity(level: u8) {
+    ENDGAME_SELECTIVITY.store(level, Ordering::Relaxed);
+}
+
+pub fn endgame_selectivity() -> u8 {
+    ENDGAME_SELECTIVITY.load(Ordering::Relaxed)
+}
+
+// Late move reductions. At a node with `depth >= LMR_MIN_DEPTH`, once
+// `LMR_FULL_MOVES` moves have been searched, the remaining non-corner
+// moves are first tried with a null window at a reduced depth: one ply
+// less, two from the `LMR_DEEP_MOVES`-th move on, since a well-ordered
+// node rarely has its best move that late. Only a move that beats alpha
+// there gets the normal full-depth search. Never applied once the search
+// reaches the end of the game, so endgame solves stay exact.
+const LMR_MIN_DEPTH: u32 = 3;
+const LMR_FULL_MOVES: usize = 3;
+const LMR_DEEP_MOVES: usize = 8;
+
+// Off unless `main` turns it on (`--no-lmr` keeps it off), so library
+// callers and the tests get the plain, order-independent search.
+static LMR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_lmr_enabled(enabled: bool) {
+    LMR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn lmr_enabled() -> bool {
+    LMR_ENABLED.load(Ordering::Relaxed)
+}
+
+// `--mtdf`: fixed-depth engine moves iterate with `search_iterative_mtdf`
+// instead of aspiration windows. Off by default, like LMR.
+static MTDF_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_mtdf_enabled(enabled: bool) {
+    MTDF_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn mtdf_enabled() -> bool {
+    MTDF_ENABLED.load(Ordering::Relaxed)
+}
+
+// Root searches hand small endgames to the exact solver (see
+// `root_solve`). Off unless `main` turns it on, like LMR, so library
+// callers and the tests get the search's own won/lost scores.
+static ROOT_SOLVE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_root_solve_enabled(enabled: bool) {
+    ROOT_SOLVE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn root_solve_enabled() -> bool {
+    ROOT_SOLVE_ENABLED.load(Ordering::Relaxed)
+}
+
+// Corner extension: with `EvalCfg::corner_extension` a move onto a corner
+// is searched at the node's own depth rather than one ply less, since
+// corner lines are where a ply more or less most often flips the result.
+// Each corner can be taken only once, so no line is extended more than
+// four times. Root moves are never extended (the root's children must
+// stay below `orig_depth`), and like LMR it stops once the search
+// reaches the end of the game, where every line is searched out anyway.
+#[inline(always)]
+fn extends_corners(us: u64, them: u64, depth: u32, orig_depth: u32, cfg: &EvalCfg) -> bool {
+    cfg.corner_extension && depth < orig_depth && (!(us | them)).count_ones() > depth
+}
+
+// Plies whose moves `par_search` splits among threads; below them each
+// subtree runs the serial `nega_search`. 2 = the root and its replies.
+static PARALLEL_DEPTH: AtomicU32 = AtomicU32::new(2);
+
+/// Set the parallel split depth (`--parallel-depth`). 0 behaves like 1:
+/// the root moves are always searched in parallel.
+pub fn set_parallel_depth(plies: u32) {
+    PARALLEL_DEPTH.store(plies, Ordering::Relaxed);
+}
+
+pub fn parallel_depth() -> u32 {
+    PARALLEL_DEPTH.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+fn lmr_reduction(tried: usize) -> u32 {
+    if tried >= LMR_DEEP_MOVES {
+        2
+    } else {
+        1
+    }
+}
+
+// Move-ordering diagnostics. Counting searches record, for every beta
+// cutoff, how many moves had already been searched at that node (0 = the
+// first move cut). The last bucket collects everything from that rank on.
+// Per-search counts live in `SearchCtx` and are folded into this total
+// once the search returns, so the hot path never touches shared state.
+pub const CUTOFF_RANKS: usize = 8;
+
+static CUTOFF_RANK_COUNTS: Mutex<[u64; CUTOFF_RANKS]> = Mutex::new([0; CUTOFF_RANKS]);
+
+/// Cutoff-rank histogram accumulated by counting searches since the last
+/// `reset_cutoff_stats`.
+pub fn cutoff_stats() -> [u64; CUTOFF_RANKS] {
+    *CUTOFF_RANK_COUNTS.lock().unwrap()
+}
+
+pub fn reset_cutoff_stats() {
+    *CUTOFF_RANK_COUNTS.lock().unwrap() = [0; CUTOFF_RANKS];
+}
+
 #[derive(Copy, Clone)]
 pub struct KillerTable([[u64; 2]; KILLER_PLIES]);
 
@@ -54,7 +239,7 @@ impl Default for KillerTable {
 // arguments (us, them, depth, alpha, beta, ctx) - all sysv-abi register
 // candidates - and spares each recursive call from re-shuffling four
 // extra values onto the stack frame.
-pub struct SearchCtx {
+pub struct SearchCtx<'a> {
     pub orig_depth: u32,
     pub cfg: EvalCfg,
     /// Full-avalanche hash of the active `EvalCfg`. XORed into every
@@ -68,10 +253,45 @@ pub struct SearchCtx {
     /// search) this is a no-op since `cfg_key` is constant.
     pub cfg_key: u64,
     pub node_count: u64,
+    /// Static evaluations at depth 0; only maintained by counting searches.
+    pub leaf_count: u64,
+    /// Beta cutoffs produced by a killer move; counting searches only.
+    pub killer_cutoffs: u64,
+    /// Corner captures searched without losing a ply; counting searches
+    /// only.
+    pub corner_extensions: u64,
+    /// TT probes, hits and misses on a slot held by another position;
+    /// counting searches only.
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_collisions: u64,
     pub killers: KillerTable,
+    /// Snapshot of the `--eval-cache` switch, read once per search so
+    /// leaves don't touch the global flag.
+    pub use_eval_cache: bool,
+    /// Snapshot of the endgame selectivity level; 0 = exact.
+    pub selectivity: u8,
+    /// Snapshot of the `--no-tt` switch (inverted).
+    pub use_tt: bool,
+    /// Snapshot of the late-move-reduction switch.
+    pub use_lmr: bool,
+    /// Cutoff-rank histogram; only maintained by counting searches.
+    pub cutoff_ranks: [u64; CUTOFF_RANKS],
+    /// `(move, score, nodes)` of every root move searched, in search
+    /// order; only maintained by counting searches.
+    pub root_nodes: Vec<(u64, i32, u64)>,
+    /// Raised by another thread to abort the search; checked at every
+    /// node (see `search_iterative_stop`).
+    pub stop: Option<&'a StopSignal<'a>>,
+    /// Scores leaves instead of `cfg`'s built-in eval when set (see
+    /// `search_with_evaluator`).
+    pub evaluator: Option<&'a dyn Evaluator>,
+    /// Table the search probes and fills: the global `tt()` unless the
+    /// caller brings its own (see `search_moves_opt_in`).
+    pub table: &'a TranspositionTable,
 }
 
-impl SearchCtx {
+impl SearchCtx<'_> {
     #[inline(always)]
     pub fn new(orig_depth: u32, cfg: EvalCfg) -> Self {
         Self {
@@ -79,8 +299,66 @@ impl SearchCtx {
             cfg,
             cfg_key: eval_cfg_key(&cfg),
             node_count: 0,
+            leaf_count: 0,
+            killer_cutoffs: 0,
+            corner_extensions: 0,
+            tt_probes: 0,
+            tt_hits: 0,
+            tt_collisions: 0,
             killers: KillerTable::new(),
+            use_eval_cache: eval_cache_enabled() && tt_enabled(),
+            selectivity: endgame_selectivity(),
+            use_tt: tt_enabled(),
+            use_lmr: lmr_enabled(),
+            cutoff_ranks: [0; CUTOFF_RANKS],
+            root_nodes: Vec::new(),
+            stop: None,
+            evaluator: None,
+            table: tt(),
+        }
+    }
+
+    #[inline(always)]
+    fn stopped(&self) -> bool {
+        stop_requested(self.stop)
+    }
+}
+
+#[inline(always)]
+fn stop_requested(stop: Option<&StopSignal>) -> bool {
+    matches!(stop, Some(s) if s.requested())
+}
+
+/// A caller's stop flag as the search sees it. A search that finds the
+/// flag raised unwinds with a meaningless result and notes that here, so
+/// an iteration it cut short can be told from one that finished just
+/// before the flag went up.
+pub struct StopSignal<'a> {
+    flag: &'a AtomicBool,
+    seen: AtomicBool,
+}
+
+impl<'a> StopSignal<'a> {
+    pub fn new(flag: &'a AtomicBool) -> Self {
+        Self {
+            flag,
+            seen: AtomicBool::new(false),
+        }
+    }
+
+    #[inline(always)]
+    fn requested(&self) -> bool {
+        let raised = self.flag.load(Ordering::Relaxed);
+        if raised {
+            self.seen.store(true, Ordering::Relaxed);
         }
+        raised
+    }
+
+    /// Whether a search has given up on the flag. The flag stays raised,
+    /// so every search after that one is cut short as well.
+    pub fn interrupted(&self) -> bool {
+        self.seen.load(Ordering::Relaxed)
     }
 }
 
@@ -96,7 +374,7 @@ pub fn eval_cfg_key(cfg: &EvalCfg) -> u64 {
     // every output bit. The exact pack order doesn't matter as long
     // as every field contributes.
     let mut h: u64 = 0xA2A8_8E47_2F35_8101;
-    let fields: [i32; 10] = [
+    let fields: [i32; 22] = [
         cfg.corner_value,
         cfg.edge_value,
         cfg.antiedge_value,
@@ -107,6 +385,18 @@ pub fn eval_cfg_key(cfg: &EvalCfg) -> u64 {
         cfg.mobility_values[0],
         cfg.mobility_values[1],
         cfg.mobility_values[2],
+        cfg.potential_mobility_value,
+        cfg.parity_value,
+        cfg.parity_empties,
+        cfg.frontier_value,
+        cfg.stable_value,
+        cfg.disc_only_empties,
+        cfg.tempo_value,
+        cfg.wipeout_discs,
+        cfg.wipeout_value,
+        cfg.corner_extension as i32,
+        cfg.pvs as i32,
+        cfg.max_heuristic_eval,
     ];
     for f in fields {
         h = splitmix64(h.wrapping_add((f as u32) as u64));
@@ -149,10 +439,58 @@ fn adjust_mate_distance(v: i32) -> i32 {
     }
 }
 
+/// Moves to the end of the game behind a won or lost score, i.e. how many
+/// times `adjust_mate_distance` shrank it (passes don't count). `None`
+/// for a heuristic eval or an exact solver margin (see `margin_to_eval`).
+pub fn mate_distance(eval: i32) -> Option<u32> {
+    let v = eval.abs();
+    if v > MATE_THRESHOLD && v <= WIN_SCORE {
+        Some((WIN_SCORE - v) as u32)
+    } else {
+        None
+    }
+}
+
 // --------------------------------------------------------------------------
 // Legal move enumeration
 // --------------------------------------------------------------------------
 
+/// Allocation-free iterator over the single-bit moves of a move mask,
+/// lowest square first.
+#[derive(Clone, Copy, Debug)]
+pub struct MoveIter(u64);
+
+impl Iterator for MoveIter {
+    type Item = u64;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<u64> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(pop_lsb(&mut self.0))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.0.count_ones() as usize;
+        (n, Some(n))
+    }
+}
+
+impl ExactSizeIterator for MoveIter {}
+
+/// Legal moves for the side to move, without allocating. Yields the same
+/// moves in the same order as `find_legal_moves_alt`.
+pub fn legal_moves(white: u64, black: u64, is_white_to_move: bool) -> MoveIter {
+    let (me, opp) = if is_white_to_move {
+        (white, black)
+    } else {
+        (black, white)
+    };
+    MoveIter(compute_moves(me, opp))
+}
+
 pub fn find_legal_moves_alt(white: u64, black: u64, is_white_to_move: bool) -> Vec<u64> {
     let (me, opp) = if is_white_to_move {
         (white, black)
@@ -181,12 +519,20 @@ pub fn find_legal_moves_alt(white: u64, black: u64, is_white_to_move: bool) -> V
 /// get one value per phase; purely geometric features (corner vs
 /// X-square) share a single value across all phases since the board
 /// itself doesn't change.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///
+/// Serialized as a JSON object keyed by field name (`--eval-config`).
+/// Fields missing from a file keep their `DEFAULT_CFG` values, like
+/// omitted trailing terms of a coefficient string; unknown fields are
+/// an error rather than a silently ignored typo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct EvalCfg {
     // ---- Phase-independent positional coefficients ---------------
     // The value of a square derives from its structural role
     // (corner=untakeable, X-square=dangerous-next-to-empty-corner)
-    // and this role doesn't change with move number.
+    // and this role doesn't change with move number. `antiedge_value`
+    // (C-squares) and `anticorner_value` (X-squares) only count while
+    // the neighbouring corner is empty.
     pub corner_value: i32,
     pub edge_value: i32,
     pub antiedge_value: i32,
@@ -200,6 +546,46 @@ pub struct EvalCfg {
     // branching factor is high and whole position families diverge.
     pub disc_values: [i32; 3],
     pub mobility_values: [i32; 3],
+
+    // ---- Later additions -----------------------------------------
+    // Zero in `DEFAULT_CFG` until the tuner has had a go at them, so
+    // adding a term doesn't change how the default engine plays.
+    /// Weight of potential mobility (empty squares next to opponent
+    /// discs), see [`potential_mobility`].
+    pub potential_mobility_value: i32,
+    /// Weight of region parity for the side to move, see
+    /// [`region_parity`]. Only applied from `parity_empties` empty
+    /// squares down: earlier the regions are still one open board.
+    pub parity_value: i32,
+    pub parity_empties: i32,
+    /// Penalty per frontier disc (own discs next to an empty square), see
+    /// [`frontier_discs`]: they give the opponent squares to move to.
+    pub frontier_value: i32,
+    /// Weight of stable discs (ones that can never be flipped), see
+    /// [`stable_discs`].
+    pub stable_value: i32,
+    /// From this many empty squares down the eval is the bare disc
+    /// difference, see [`eval_disc_difference`]; 0 = never.
+    pub disc_only_empties: i32,
+    /// Bonus for having the move, given to whichever side is to move.
+    pub tempo_value: i32,
+    /// Bonus per disc the opponent is short of `wipeout_discs`, see
+    /// [`wipeout_bonus`]: a side about to be wiped out is lost whatever
+    /// the disc count says.
+    pub wipeout_discs: i32,
+    pub wipeout_value: i32,
+
+    // ---- Search shape --------------------------------------------
+    // Not eval terms, but they change the scores a search returns, so
+    // they live here to get their own TT partition via `eval_cfg_key`.
+    /// Search corner captures one ply deeper, see `extends_corners`.
+    pub corner_extension: bool,
+    /// Principal Variation Search: moves after the first get a null
+    /// window first. Off = plain alpha-beta, for comparison.
+    pub pvs: bool,
+    /// Static evals are clamped to `±max_heuristic_eval`, see
+    /// `EvalCfg::clamp_heuristic`.
+    pub max_heuristic_eval: i32,
 }
 
 /// Game-phase bucketing by empty-square count. Three buckets balance
@@ -218,6 +604,51 @@ fn phase_index(empties: u32) -> usize {
     }
 }
 
+/// Game phase as used for display purposes; mirrors the buckets of
+/// `phase_index`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Opening,
+    Midgame,
+    Endgame,
+}
+
+impl Phase {
+    pub fn of(white: u64, black: u64) -> Phase {
+        Phase::from_empties((!(white | black)).count_ones())
+    }
+
+    pub fn from_empties(empties: u32) -> Phase {
+        match phase_index(empties) {
+            0 => Phase::Opening,
+            1 => Phase::Midgame,
+            _ => Phase::Endgame,
+        }
+    }
+}
+
+// Logistic scale per phase for `win_probability`: the eval at which
+// Black's winning chances reach ~73% (1 / (1 + e^-1)). Early evals are
+// dominated by positional terms that swing a lot before the game is
+// decided, so the curve is flattest there; in the endgame the eval is
+// close to a disc margin and a smaller lead is already decisive. These
+// are starting defaults - refit them against self-play outcomes when
+// the eval changes substantially.
+const WIN_PROB_SCALE: [f64; 3] = [250.0, 160.0, 80.0];
+
+/// Map an eval (Black's perspective) to Black's winning probability in
+/// `[0, 1]`. Mate-distance scores are certain results.
+pub fn win_probability(eval: i32, phase: Phase) -> f64 {
+    if eval > MATE_THRESHOLD {
+        return 1.0;
+    }
+    if eval < -MATE_THRESHOLD {
+        return 0.0;
+    }
+    let scale = WIN_PROB_SCALE[phase as usize];
+    1.0 / (1.0 + (-(eval as f64) / scale).exp())
+}
+
 pub static DEFAULT_CFG: EvalCfg = EvalCfg {
     // These coefficients were obtained by the (1+1)-ES tuner in
     // `src/tune.rs` at d7 over ~1300 symmetry-reduced training
@@ -247,17 +678,278 @@ pub static DEFAULT_CFG: EvalCfg = EvalCfg {
     anticorner_value: -30,
     disc_values: [-7, -1, 1],
     mobility_values: [7, 4, 16],
+    potential_mobility_value: 0,
+    parity_value: 0,
+    parity_empties: 14,
+    frontier_value: 0,
+    stable_value: 0,
+    disc_only_empties: 0,
+    tempo_value: 0,
+    wipeout_discs: 5,
+    wipeout_value: 0,
+    corner_extension: true,
+    pvs: true,
+    max_heuristic_eval: MAX_HEURISTIC_EVAL,
 };
 
+impl Default for EvalCfg {
+    fn default() -> Self {
+        DEFAULT_CFG
+    }
+}
+
+impl EvalCfg {
+    pub fn load_from_file(path: &str) -> io::Result<EvalCfg> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save_to_file(&self, path: &str) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// `score` clamped to `±max_heuristic_eval`, itself capped at
+    /// `MAX_HEURISTIC_EVAL` whatever the config asks for.
+    #[inline(always)]
+    pub fn clamp_heuristic(&self, score: i32) -> i32 {
+        let cap = self.max_heuristic_eval.clamp(0, MAX_HEURISTIC_EVAL);
+        score.clamp(-cap, cap)
+    }
+}
+
+// The config the engine plays with in games (`--eval-config`); analysis
+// and tuning commands keep passing their own. Set once at startup.
+static PLAY_CFG: OnceLock<EvalCfg> = OnceLock::new();
+
+pub fn set_play_cfg(cfg: EvalCfg) {
+    let _ = PLAY_CFG.set(cfg);
+}
+
+pub fn play_cfg() -> EvalCfg {
+    PLAY_CFG.get().copied().unwrap_or(DEFAULT_CFG)
+}
+
+const NOT_A_FILE: u64 = 0xFEFE_FEFE_FEFE_FEFE;
+const NOT_H_FILE: u64 = 0x7F7F_7F7F_7F7F_7F7F;
+
+/// Union of the eight one-square shifts of `bb` (its king-move
+/// neighbourhood, excluding `bb` itself unless a bit neighbours another).
+/// East-going shifts drop the A file and west-going ones the H file so
+/// nothing wraps around the board edge.
+#[inline(always)]
+pub fn dilate(bb: u64) -> u64 {
+    ((bb << 1) & NOT_A_FILE)
+        | ((bb >> 1) & NOT_H_FILE)
+        | (bb << 8)
+        | (bb >> 8)
+        | ((bb << 9) & NOT_A_FILE)
+        | ((bb << 7) & NOT_H_FILE)
+        | ((bb >> 7) & NOT_A_FILE)
+        | ((bb >> 9) & NOT_H_FILE)
+}
+
+/// Potential mobility `(white, black)`: for each side, the number of
+/// empty squares adjacent to at least one opponent disc - the squares
+/// it may be able to move to later.
+#[inline(always)]
+pub fn potential_mobility(white: u64, black: u64) -> (u32, u32) {
+    let empty = !(white | black);
+    (
+        (dilate(black) & empty).count_ones(),
+        (dilate(white) & empty).count_ones(),
+    )
+}
+
+/// Frontier discs of `player`: those next to at least one of the `empty`
+/// squares. `dilate` masks the shifts at the A and H files, so nothing
+/// wraps around to the other side of the board.
+#[inline(always)]
+pub fn frontier_discs(player: u64, empty: u64) -> u32 {
+    (player & dilate(empty)).count_ones()
+}
+
+const BORDER_MASK: u64 = 0xFF81_8181_8181_81FF;
+const EDGE_FILES: u64 = 0x8181_8181_8181_8181;
+const EDGE_RANKS: u64 = 0xFF00_0000_0000_00FF;
+
+/// Masks of the lines along one axis: ranks (`axis` 0), files (1),
+/// diagonals with `r - c` constant (2) or anti-diagonals, `r + c` (3).
+const fn line_masks<const N: usize>(axis: u8) -> [u64; N] {
+    let mut masks = [0u64; N];
+    let mut sq = 0;
+    while sq < 64 {
+        let (r, c) = (sq / 8, sq % 8);
+        let line = match axis {
+            0 => r,
+            1 => c,
+            2 => r + 7 - c,
+            _ => r + c,
+        };
+        masks[line] |= 1 << sq;
+        sq += 1;
+    }
+    masks
+}
+
+const RANKS: [u64; 8] = line_masks::<8>(0);
+const FILES: [u64; 8] = line_masks::<8>(1);
+const DIAGONALS: [u64; 15] = line_masks::<15>(2);
+const ANTI_DIAGONALS: [u64; 15] = line_masks::<15>(3);
+
+/// Union of the `lines` that are completely filled.
+#[inline(always)]
+fn full_lines(occupied: u64, lines: &[u64]) -> u64 {
+    lines
+        .iter()
+        .filter(|&&line| occupied & line == line)
+        .fold(0, |full, &line| full | line)
+}
+
+/// Discs of `player` that can never be flipped. A disc is safe along an
+/// axis when that line is full (no move can land on it), when it sits on
+/// the board edge across the axis, or when a neighbour along the axis is
+/// already one of `player`'s stable discs; it is stable when it is safe
+/// along all four axes. Grown from nothing to a fixed point, so it
+/// starts at owned corners and spreads along edges and filled lines.
+/// Conservative: a disc kept safe only by stable opponent discs is not
+/// found.
+pub fn stable_discs(player: u64, opponent: u64) -> u64 {
+    let occupied = player | opponent;
+    let full_rank = full_lines(occupied, &RANKS) | EDGE_FILES;
+    let full_file = full_lines(occupied, &FILES) | EDGE_RANKS;
+    let full_diagonal = full_lines(occupied, &DIAGONALS) | BORDER_MASK;
+    let full_anti = full_lines(occupied, &ANTI_DIAGONALS) | BORDER_MASK;
+    let mut stable = 0u64;
+    loop {
+        let along_rank = ((stable << 1) & NOT_A_FILE) | ((stable >> 1) & NOT_H_FILE);
+        let along_file = (stable << 8) | (stable >> 8);
+        let along_diagonal = ((stable << 9) & NOT_A_FILE) | ((stable >> 9) & NOT_H_FILE);
+        let along_anti = ((stable << 7) & NOT_H_FILE) | ((stable >> 7) & NOT_A_FILE);
+        let next = player
+            & (full_rank | along_rank)
+            & (full_file | along_file)
+            & (full_diagonal | along_diagonal)
+            & (full_anti | along_anti);
+        if next == stable {
+            return stable;
+        }
+        stable = next;
+    }
+}
+
+/// Number of empty regions (king-move connected) with an odd number of
+/// squares. The side to move can take the last move in each of them -
+/// and so the last flips, which stick - unless the opponent gives up a
+/// tempo, so in the endgame this counts in favour of the side to move.
+/// Symmetric in its arguments.
+pub fn region_parity(white: u64, black: u64) -> i32 {
+    let mut empty = !(white | black);
+    let mut odd = 0;
+    while empty != 0 {
+        let mut region = lowest_set_bit(empty);
+        loop {
+            let grown = (region | dilate(region)) & empty;
+            if grown == region {
+                break;
+            }
+            region = grown;
+        }
+        if region.count_ones() % 2 == 1 {
+            odd += 1;
+        }
+        empty &= !region;
+    }
+    odd
+}
+
+/// The X-squares and C-squares next to corners that are still `empty`.
+#[inline(always)]
+fn penalised_squares(empty: u64) -> (u64, u64) {
+    let (mut x_squares, mut c_squares) = (0, 0);
+    for &(corner, x, c) in &CORNER_NEIGHBOURS {
+        if corner & empty != 0 {
+            x_squares |= x;
+            c_squares |= c;
+        }
+    }
+    (x_squares, c_squares)
+}
+
 /// Phase-independent positional score. The disc-count and mobility
 /// contributions are added by the caller from the phase-selected
-/// coefficients.
+/// coefficients. X- and C-square penalties apply only next to corners
+/// that are still `empty`.
 #[inline(always)]
-fn side_positional(bb: u64, cfg: EvalCfg) -> i32 {
+fn side_positional(bb: u64, empty: u64, cfg: EvalCfg) -> i32 {
+    let (x_squares, c_squares) = penalised_squares(empty);
     (bb & CORNER_MASK).count_ones() as i32 * cfg.corner_value
         + (bb & EDGE_MASK).count_ones() as i32 * cfg.edge_value
-        + (bb & ANTIEDGE_MASK).count_ones() as i32 * cfg.antiedge_value
-        + (bb & ANTICORNER_MASK).count_ones() as i32 * cfg.anticorner_value
+        + (bb & c_squares).count_ones() as i32 * cfg.antiedge_value
+        + (bb & x_squares).count_ones() as i32 * cfg.anticorner_value
+}
+
+/// The square masks of `cfg` spelled out as one weight per square,
+/// indexed by bit (a1 = 0). While all four corners are empty this table
+/// in `eval_position_table` reproduces the positional part of
+/// `eval_position_with_cfg`; a fixed table can't drop the X- and
+/// C-square penalties once a corner is taken.
+pub fn square_weights(cfg: &EvalCfg) -> [i32; 64] {
+    let mut weights = [0; 64];
+    for (sq, w) in weights.iter_mut().enumerate() {
+        *w = side_positional(1u64 << sq, !(1u64 << sq), *cfg);
+    }
+    weights
+}
+
+/// Absolute positional score (black - white) from a per-square weight
+/// table, e.g. one loaded with `parse_square_weights`.
+pub fn eval_position_table(white: u64, black: u64, weights: &[i32; 64]) -> i32 {
+    let side = |mut bb: u64| {
+        let mut sum = 0;
+        while bb != 0 {
+            sum += weights[bb.trailing_zeros() as usize];
+            bb &= bb - 1;
+        }
+        sum
+    };
+    side(black) - side(white)
+}
+
+/// Parse a weight table written as a board: 64 integers separated by
+/// whitespace or commas, rank 8 first and a-file first within a rank,
+/// the same orientation as `Position::parse` diagrams.
+pub fn parse_square_weights(text: &str) -> Result<[i32; 64], String> {
+    let values = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(|t| t.parse::<i32>().map_err(|_| format!("non-integer weight {:?}", t)))
+        .collect::<Result<Vec<i32>, String>>()?;
+    if values.len() != 64 {
+        return Err(format!("expected 64 weights, got {}", values.len()));
+    }
+    let mut weights = [0; 64];
+    for (i, v) in values.into_iter().enumerate() {
+        let (rank, file) = (7 - i / 8, i % 8);
+        weights[rank * 8 + file] = v;
+    }
+    Ok(weights)
+}
+
+/// Disc count margin, black - white. Close to the end of the game the
+/// positional terms stop meaning much and this is what the game will be
+/// scored on; `EvalCfg::disc_only_empties` switches the eval to it. On a
+/// full board it is the final margin.
+pub fn eval_disc_difference(white: u64, black: u64) -> i32 {
+    black.count_ones() as i32 - white.count_ones() as i32
+}
+
+/// Bonus for the side whose `opponent` is down to fewer than
+/// `cfg.wipeout_discs` discs: `wipeout_value` per missing disc. Steep on
+/// purpose, but like every heuristic term it ends up clamped by
+/// `EvalCfg::clamp_heuristic`, short of a won game's score.
+#[inline(always)]
+pub fn wipeout_bonus(opponent: u64, cfg: &EvalCfg) -> i32 {
+    (cfg.wipeout_discs - opponent.count_ones() as i32).max(0) * cfg.wipeout_value
 }
 
 /// Full static evaluation in the us-frame: positional + disc count
@@ -268,7 +960,11 @@ fn side_positional(bb: u64, cfg: EvalCfg) -> i32 {
 /// buys.
 #[inline(always)]
 fn eval_us_them(us: u64, them: u64, cfg: EvalCfg) -> i32 {
-    let empties = (!(us | them)).count_ones();
+    let empty = !(us | them);
+    let empties = empty.count_ones();
+    if empties as i32 <= cfg.disc_only_empties {
+        return us.count_ones() as i32 - them.count_ones() as i32;
+    }
     let phase = phase_index(empties);
 
     let our_mobility = compute_moves(us, them).count_ones() as i32;
@@ -278,15 +974,52 @@ fn eval_us_them(us: u64, them: u64, cfg: EvalCfg) -> i32 {
     let disc_score =
         (us.count_ones() as i32 - them.count_ones() as i32) * cfg.disc_values[phase];
 
-    let positional_score = side_positional(us, cfg) - side_positional(them, cfg);
+    let positional_score = side_positional(us, empty, cfg) - side_positional(them, empty, cfg);
+
+    let mut score = positional_score + mobility_score + disc_score;
+    if cfg.potential_mobility_value != 0 {
+        let (our_pm, their_pm) = potential_mobility(us, them);
+        score += (our_pm as i32 - their_pm as i32) * cfg.potential_mobility_value;
+    }
+    if cfg.parity_value != 0 && empties as i32 <= cfg.parity_empties {
+        score += region_parity(us, them) * cfg.parity_value;
+    }
+    if cfg.frontier_value != 0 {
+        let frontier = frontier_discs(us, empty) as i32 - frontier_discs(them, empty) as i32;
+        score -= frontier * cfg.frontier_value;
+    }
+    if cfg.stable_value != 0 {
+        let stable =
+            stable_discs(us, them).count_ones() as i32 - stable_discs(them, us).count_ones() as i32;
+        score += stable * cfg.stable_value;
+    }
+    if cfg.wipeout_value != 0 {
+        score += wipeout_bonus(them, &cfg) - wipeout_bonus(us, &cfg);
+    }
+    score += cfg.tempo_value;
+    cfg.clamp_heuristic(score)
+}
 
-    positional_score + mobility_score + disc_score
+/// Static eval (black - white) with `DEFAULT_CFG`, which has no tempo
+/// term, so the side to move doesn't matter.
+pub fn eval_position(white: u64, black: u64) -> i32 {
+    eval_position_with_cfg(white, black, false, DEFAULT_CFG)
 }
 
-pub fn eval_position_with_cfg(white: u64, black: u64, eval_cfg: EvalCfg) -> i32 {
+pub fn eval_position_with_cfg(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    eval_cfg: EvalCfg,
+) -> i32 {
     // Absolute frame (black - white) for callers that don't work in
-    // us/them. Mobility is computed from black's perspective.
-    let empties = (!(white | black)).count_ones();
+    // us/them. Mobility is computed from black's perspective. Region
+    // parity is left out; the tempo bonus goes to `is_white_move`'s side.
+    let empty = !(white | black);
+    let empties = empty.count_ones();
+    if empties as i32 <= eval_cfg.disc_only_empties {
+        return eval_disc_difference(white, black);
+    }
     let phase = phase_index(empties);
 
     let black_mobility = compute_moves(black, white).count_ones() as i32;
@@ -297,16 +1030,194 @@ pub fn eval_position_with_cfg(white: u64, black: u64, eval_cfg: EvalCfg) -> i32
     let disc_score = (black.count_ones() as i32 - white.count_ones() as i32)
         * eval_cfg.disc_values[phase];
 
-    let positional = side_positional(black, eval_cfg) - side_positional(white, eval_cfg);
+    let positional =
+        side_positional(black, empty, eval_cfg) - side_positional(white, empty, eval_cfg);
+
+    let mut score = positional + mobility_score + disc_score;
+    if eval_cfg.potential_mobility_value != 0 {
+        let (white_pm, black_pm) = potential_mobility(white, black);
+        score += (black_pm as i32 - white_pm as i32) * eval_cfg.potential_mobility_value;
+    }
+    if eval_cfg.frontier_value != 0 {
+        let frontier =
+            frontier_discs(black, empty) as i32 - frontier_discs(white, empty) as i32;
+        score -= frontier * eval_cfg.frontier_value;
+    }
+    if eval_cfg.stable_value != 0 {
+        let stable = stable_discs(black, white).count_ones() as i32
+            - stable_discs(white, black).count_ones() as i32;
+        score += stable * eval_cfg.stable_value;
+    }
+    if eval_cfg.wipeout_value != 0 {
+        score += wipeout_bonus(white, &eval_cfg) - wipeout_bonus(black, &eval_cfg);
+    }
+    score += if is_white_move { -eval_cfg.tempo_value } else { eval_cfg.tempo_value };
+    eval_cfg.clamp_heuristic(score)
+}
+
+/// One side's weighted eval terms, each counted in that side's favour
+/// (the frontier penalty is negative).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct EvalTerms {
+    pub corners: i32,
+    pub edges: i32,
+    pub antiedges: i32,
+    pub anticorners: i32,
+    pub discs: i32,
+    pub mobility: i32,
+    pub potential_mobility: i32,
+    pub frontier: i32,
+    pub stable: i32,
+    pub wipeout: i32,
+}
+
+impl EvalTerms {
+    pub fn sum(&self) -> i32 {
+        self.corners
+            + self.edges
+            + self.antiedges
+            + self.anticorners
+            + self.discs
+            + self.mobility
+            + self.potential_mobility
+            + self.frontier
+            + self.stable
+            + self.wipeout
+    }
+}
+
+/// `eval_position_with_cfg` term by term: `black.sum() - white.sum() +
+/// tempo`, clamped, is `total`. `tempo` is in the absolute frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct EvalBreakdown {
+    pub black: EvalTerms,
+    pub white: EvalTerms,
+    pub tempo: i32,
+    pub total: i32,
+}
+
+pub fn eval_breakdown(white: u64, black: u64, is_white_move: bool, cfg: EvalCfg) -> EvalBreakdown {
+    let empty = !(white | black);
+    let empties = empty.count_ones();
+    let (terms, tempo) = if empties as i32 <= cfg.disc_only_empties {
+        let discs = |bb: u64| EvalTerms {
+            discs: bb.count_ones() as i32,
+            ..EvalTerms::default()
+        };
+        ((discs(black), discs(white)), 0)
+    } else {
+        let phase = phase_index(empties);
+        let (x_squares, c_squares) = penalised_squares(empty);
+        let side = |player: u64, opponent: u64| EvalTerms {
+            corners: (player & CORNER_MASK).count_ones() as i32 * cfg.corner_value,
+            edges: (player & EDGE_MASK).count_ones() as i32 * cfg.edge_value,
+            antiedges: (player & c_squares).count_ones() as i32 * cfg.antiedge_value,
+            anticorners: (player & x_squares).count_ones() as i32 * cfg.anticorner_value,
+            discs: player.count_ones() as i32 * cfg.disc_values[phase],
+            mobility: compute_moves(player, opponent).count_ones() as i32
+                * cfg.mobility_values[phase],
+            potential_mobility: (dilate(opponent) & empty).count_ones() as i32
+                * cfg.potential_mobility_value,
+            frontier: -(frontier_discs(player, empty) as i32) * cfg.frontier_value,
+            stable: stable_discs(player, opponent).count_ones() as i32 * cfg.stable_value,
+            wipeout: wipeout_bonus(opponent, &cfg),
+        };
+        let tempo = if is_white_move { -cfg.tempo_value } else { cfg.tempo_value };
+        ((side(black, white), side(white, black)), tempo)
+    };
+    let (black_terms, white_terms) = terms;
+    let total = cfg.clamp_heuristic(black_terms.sum() - white_terms.sum() + tempo);
+    EvalBreakdown {
+        black: black_terms,
+        white: white_terms,
+        tempo,
+        total,
+    }
+}
+
+/// A static eval the search can use in place of `EvalCfg`'s built-in
+/// one, to try other schemes without touching the search (see
+/// `search_with_evaluator`). Scores are black - white. The search calls
+/// it with the side to move as Black, so an evaluator must be colour-
+/// symmetric: `eval(w, b, wtm) == -eval(b, w, !wtm)`.
+pub trait Evaluator: Sync {
+    fn eval(&self, white: u64, black: u64, is_white_move: bool) -> i32;
+
+    /// Partitions the TT and eval cache between evaluators, like
+    /// `eval_cfg_key` does between configs.
+    fn key(&self) -> u64;
+}
+
+/// The built-in mask-based eval of an `EvalCfg`.
+pub struct MaskEvaluator(pub EvalCfg);
+
+impl Evaluator for MaskEvaluator {
+    fn eval(&self, white: u64, black: u64, is_white_move: bool) -> i32 {
+        eval_position_with_cfg(white, black, is_white_move, self.0)
+    }
+
+    fn key(&self) -> u64 {
+        eval_cfg_key(&self.0)
+    }
+}
+
+/// A 64-square weight table, see `eval_position_table`.
+pub struct TableEvaluator(pub [i32; 64]);
+
+impl Evaluator for TableEvaluator {
+    fn eval(&self, white: u64, black: u64, _is_white_move: bool) -> i32 {
+        eval_position_table(white, black, &self.0)
+    }
+
+    fn key(&self) -> u64 {
+        self.0
+            .iter()
+            .fold(0x5EED_7AB1_E000_0000, |h, &w| splitmix64(h.wrapping_add(w as u32 as u64)))
+    }
+}
+
+// Disc count at which `eval_phased` weighs its two configs equally
+// (`--phase-crossover`). 34 is halfway from the 4 starting discs to a
+// full board.
+static PHASE_CROSSOVER: AtomicU32 = AtomicU32::new(34);
+
+pub fn set_phase_crossover(discs: u32) {
+    PHASE_CROSSOVER.store(discs, Ordering::Relaxed);
+}
+
+pub fn phase_crossover() -> u32 {
+    PHASE_CROSSOVER.load(Ordering::Relaxed)
+}
 
-    positional + mobility_score + disc_score
+/// Absolute eval blended linearly between an `opening` and an `endgame`
+/// config by disc count: all `opening` at the 4 starting discs, half and
+/// half at `phase_crossover()` discs, all `endgame` on a full board. A
+/// smooth alternative to the three phase buckets inside one `EvalCfg`.
+pub fn eval_phased(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    opening: EvalCfg,
+    endgame: EvalCfg,
+) -> i32 {
+    let discs = (white | black).count_ones() as i64;
+    let crossover = (phase_crossover() as i64).clamp(5, 63);
+    // Weight of `endgame`, in 1/256ths.
+    let w = if discs <= crossover {
+        128 * (discs - 4).max(0) / (crossover - 4)
+    } else {
+        128 + 128 * (discs - crossover) / (64 - crossover)
+    };
+    let early = eval_position_with_cfg(white, black, is_white_move, opening) as i64;
+    let late = eval_position_with_cfg(white, black, is_white_move, endgame) as i64;
+    ((early * (256 - w) + late * w) / 256) as i32
 }
 
 // --------------------------------------------------------------------------
 // Core negamax search with transposition table
 // --------------------------------------------------------------------------
 //
-// All scores are in the side-to-move's frame (+10000 = we just won). The
+// All scores are in the side-to-move's frame (+WIN_SCORE = we just won). The
 // colour flag never appears inside the hot path; the public API wrappers
 // convert between absolute (black - white) and us-perspective scores at
 // the call boundary.
@@ -326,6 +1237,83 @@ fn game_status_us_them(us: u64, them: u64) -> u64 {
     check_game_status(us, them, true)
 }
 
+/// Static eval at a search leaf, going through the shared eval cache
+/// when it's enabled. The key carries `cfg_key` for the same reason the
+/// TT key does.
+#[inline(always)]
+fn leaf_eval<const COUNT: bool>(us: u64, them: u64, ctx: &SearchCtx) -> i32 {
+    if !ctx.use_eval_cache {
+        return static_eval(us, them, ctx);
+    }
+    let key = hash_position(us, them) ^ ctx.cfg_key;
+    eval_cache().get_or_eval::<COUNT>(key, || static_eval(us, them, ctx))
+}
+
+#[inline(always)]
+fn static_eval(us: u64, them: u64, ctx: &SearchCtx) -> i32 {
+    match ctx.evaluator {
+        // The side to move plays Black (see `Evaluator`).
+        Some(e) => e.eval(them, us, false),
+        None => eval_us_them(us, them, ctx.cfg),
+    }
+}
+
+/// Corner quiescence at the horizon. The static eval prices corners but
+/// can't see one about to be taken, so before trusting it the side to
+/// move may take any corner on offer, the other side may answer with one
+/// of its own, and so on for up to `plies` moves. Standing pat is always
+/// allowed: declining a corner capture is the quiet alternative.
+fn quiesce<const COUNT: bool>(
+    us: u64,
+    them: u64,
+    mut alpha: i32,
+    beta: i32,
+    plies: u32,
+    ctx: &mut SearchCtx,
+) -> i32 {
+    if COUNT {
+        ctx.leaf_count += 1;
+    }
+    let legal = compute_moves(us, them);
+    if legal == 0 && compute_moves(them, us) == 0 {
+        let diff = us.count_ones() as i32 - them.count_ones() as i32;
+        return diff.signum() * WIN_SCORE;
+    }
+    let mut best = leaf_eval::<COUNT>(us, them, ctx);
+    let mut corners = legal & CORNER_MASK;
+    if plies == 0 || corners == 0 || best >= beta {
+        return best;
+    }
+    if best > alpha {
+        alpha = best;
+    }
+    while corners != 0 {
+        let mv = pop_lsb(&mut corners);
+        if COUNT {
+            ctx.node_count += 1;
+        }
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        let v = adjust_mate_distance(-quiesce::<COUNT>(
+            new_them,
+            new_us,
+            -beta,
+            -alpha,
+            plies - 1,
+            ctx,
+        ));
+        if v > best {
+            best = v;
+            if v > alpha {
+                alpha = v;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
 fn nega_search_impl<const COUNT: bool>(
     us: u64,
     them: u64,
@@ -337,16 +1325,19 @@ fn nega_search_impl<const COUNT: bool>(
     if COUNT {
         ctx.node_count += 1;
     }
+    if ctx.stopped() {
+        return (u64::MAX, 0);
+    }
     let orig_depth = ctx.orig_depth;
 
     let outcome = game_status_us_them(us, them);
 
     if outcome >= DRAW_OUTCOME {
         if outcome == WHITE_WON_OUTCOME {
-            return (u64::MAX, 10_000);
+            return (u64::MAX, WIN_SCORE);
         }
         if outcome == BLACK_WON_OUTCOME {
-            return (u64::MAX, -10_000);
+            return (u64::MAX, -WIN_SCORE);
         }
         if outcome == DRAW_OUTCOME {
             return (u64::MAX, 0);
@@ -358,7 +1349,7 @@ fn nega_search_impl<const COUNT: bool>(
     }
 
     if depth == 0 {
-        return (u64::MAX, eval_us_them(us, them, ctx.cfg));
+        return (u64::MAX, quiesce::<COUNT>(us, them, alpha, beta, QUIESCENCE_PLIES, ctx));
     }
 
     // ---- TT probe -------------------------------------------------------
@@ -369,8 +1360,17 @@ fn nega_search_impl<const COUNT: bool>(
     let mut a = alpha;
     let mut b = beta;
 
-    if let Some(entry) = tt().probe(key) {
-        if entry.bound != BOUND_NONE && entry.depth as i32 >= depth as i32 {
+    let probed = if ctx.use_tt { ctx.table.probe(key) } else { None };
+    if COUNT && ctx.use_tt {
+        ctx.tt_probes += 1;
+        if probed.is_some() {
+            ctx.tt_hits += 1;
+        } else if ctx.table.collides(key) {
+            ctx.tt_collisions += 1;
+        }
+    }
+    if let Some(entry) = probed {
+        if entry.usable_at(depth) {
             let s = entry.score;
             let stored_move = if entry.move_sq < NO_MOVE_SQ {
                 1u64 << entry.move_sq
@@ -406,6 +1406,34 @@ fn nega_search_impl<const COUNT: bool>(
         }
     }
 
+    // ---- Endgame ProbCut ------------------------------------------------
+    // Never at the root (which must return a real move), and only where the
+    // search runs to the end of the game - see `PROBCUT_MARGINS`. A bound
+    // is met by a final margin of at least 0 (draw) or 1 (win), and its
+    // mirror for alpha; a window that tells wins apart by their distance
+    // (past `MATE_THRESHOLD`) needs the full search.
+    if ctx.selectivity > 0
+        && depth >= PROBCUT_MIN_DEPTH
+        && depth < orig_depth
+        && (!(us | them)).count_ones() <= depth
+    {
+        let level = (ctx.selectivity as usize).min(PROBCUT_MARGINS.len() - 1);
+        let margin = PROBCUT_MARGINS[level];
+        let shallow = depth / PROBCUT_DEPTH_DIVISOR;
+        if b.abs() <= MATE_THRESHOLD {
+            let hi = i32::from(b > 0) + margin;
+            if shallow_margin(us, them, shallow, hi - 1, hi) >= hi {
+                return (u64::MAX, b);
+            }
+        }
+        if a.abs() <= MATE_THRESHOLD {
+            let lo = -i32::from(a < 0) - margin;
+            if shallow_margin(us, them, shallow, lo, lo + 1) <= lo {
+                return (u64::MAX, a);
+            }
+        }
+    }
+
     // "alpha we searched with", captured before any mutation during the
     // move loop - used for final bound classification.
     let alpha_used = a;
@@ -440,20 +1468,58 @@ fn nega_search_impl<const COUNT: bool>(
     // subsequent move is speculatively searched with a null window, and
     // only re-searched with the full window if it fails high. This is
     // strictly cheaper when move ordering is good (which, with the TT-move
-    // seed and the coarse bucket ordering, it usually is).
+    // seed and the coarse bucket ordering, it usually is). `cfg.pvs` off
+    // searches every move with the full window.
     let mut searched_any = false;
+    // Moves searched so far at this node, for the cutoff-rank statistics
+    // and late move reductions.
+    let mut tried: usize = 0;
+    let lmr_here =
+        ctx.use_lmr && depth >= LMR_MIN_DEPTH && (!(us | them)).count_ones() > depth;
+    let extend_corners = extends_corners(us, them, depth, orig_depth, &ctx.cfg);
 
     macro_rules! try_move_cached {
         ($candidate:expr, $new_us:expr, $new_them:expr) => {{
             let candidate = $candidate;
             let new_us_c = $new_us;
             let new_them_c = $new_them;
+            let nodes_before = ctx.node_count;
+            let child_depth = if extend_corners && candidate & CORNER_MASK != 0 {
+                if COUNT {
+                    ctx.corner_extensions += 1;
+                }
+                depth
+            } else {
+                depth - 1
+            };
 
-            let child_v = if !searched_any {
+            // Late moves first get a reduced null-window probe; a fail
+            // low there stands as the move's (upper-bound) score.
+            let late = lmr_here && tried >= LMR_FULL_MOVES && candidate & CORNER_MASK == 0;
+            let reduced = if late {
                 let (_, cv) = nega_search_impl::<COUNT>(
                     new_them_c,
                     new_us_c,
-                    depth - 1,
+                    depth - 1 - lmr_reduction(tried),
+                    -a - 1,
+                    -a,
+                    ctx,
+                );
+                if adjust_mate_distance(-cv) <= a {
+                    Some(cv)
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+            let child_v = if let Some(cv) = reduced {
+                cv
+            } else if !searched_any || !ctx.cfg.pvs {
+                let (_, cv) = nega_search_impl::<COUNT>(
+                    new_them_c,
+                    new_us_c,
+                    child_depth,
                     -b,
                     -a,
                     ctx,
@@ -463,7 +1529,7 @@ fn nega_search_impl<const COUNT: bool>(
                 let (_, cv) = nega_search_impl::<COUNT>(
                     new_them_c,
                     new_us_c,
-                    depth - 1,
+                    child_depth,
                     -a - 1,
                     -a,
                     ctx,
@@ -473,7 +1539,7 @@ fn nega_search_impl<const COUNT: bool>(
                     let (_, cv2) = nega_search_impl::<COUNT>(
                         new_them_c,
                         new_us_c,
-                        depth - 1,
+                        child_depth,
                         -b,
                         -a,
                         ctx,
@@ -483,8 +1549,17 @@ fn nega_search_impl<const COUNT: bool>(
                     cv
                 }
             };
+            // An aborted child's score means nothing: unwind with the best
+            // move completed so far, storing nothing in the TT.
+            if ctx.stopped() {
+                return (best_move, best_v.max(MIN_EVAL));
+            }
             let v = adjust_mate_distance(-child_v);
+            if COUNT && depth == orig_depth {
+                ctx.root_nodes.push((candidate, v, ctx.node_count - nodes_before));
+            }
             searched_any = true;
+            tried += 1;
             if v > best_v {
                 best_v = v;
                 best_move = candidate;
@@ -492,6 +1567,12 @@ fn nega_search_impl<const COUNT: bool>(
                     a = v;
                 }
                 if a >= b {
+                    if COUNT {
+                        ctx.cutoff_ranks[(tried - 1).min(CUTOFF_RANKS - 1)] += 1;
+                        if candidate == killer0 || candidate == killer1 {
+                            ctx.killer_cutoffs += 1;
+                        }
+                    }
                     // Record the cutoff move as a killer at this ply, unless
                     // it's already slot 0 (so the two slots are always
                     // distinct). Slot 0 shifts to slot 1 (a tiny LRU).
@@ -500,13 +1581,15 @@ fn nega_search_impl<const COUNT: bool>(
                         ctx.killers.0[ply_idx][1] = cur_k0;
                         ctx.killers.0[ply_idx][0] = candidate;
                     }
-                    tt().store(
-                        key,
-                        v,
-                        depth as i8,
-                        BOUND_LOWER,
-                        candidate.trailing_zeros() as u8,
-                    );
+                    if ctx.use_tt {
+                        ctx.table.store(
+                            key,
+                            v,
+                            depth as i8,
+                            BOUND_LOWER,
+                            candidate.trailing_zeros() as u8,
+                        );
+                    }
                     return (candidate, v);
                 }
             }
@@ -623,7 +1706,9 @@ fn nega_search_impl<const COUNT: bool>(
     } else {
         NO_MOVE_SQ
     };
-    tt().store(key, best_v, depth as i8, bound, move_sq);
+    if ctx.use_tt {
+        ctx.table.store(key, best_v, depth as i8, bound, move_sq);
+    }
 
     (best_move, best_v)
 }
@@ -649,6 +1734,144 @@ fn nega_search_impl<const COUNT: bool>(
 // machinery (parity-based move ordering, stability-based alpha-beta
 // narrowing) rather than a generic alpha-beta rewrite.
 
+// --------------------------------------------------------------------------
+// Exact-margin endgame solver
+// --------------------------------------------------------------------------
+//
+// Not a faster solve (see the notes above) but a different question: the
+// main search scores every won game as `WIN_SCORE`, so it knows *that*
+// it wins, not by how much. `solve_endgame` maximises the final disc
+// difference instead, which is what game play wants once the tree is
+// small enough to finish. No TT and no eval; moves are tried fewest
+// opponent replies first.
+
+/// Empty-square count from which root searches switch to `solve_endgame`
+/// (see `set_root_solve_enabled`).
+pub const ENDGAME_SOLVE_EMPTIES: u32 = 12;
+
+fn solve_impl(us: u64, them: u64, mut alpha: i32, beta: i32) -> i32 {
+    let legal = compute_moves(us, them);
+    if legal == 0 {
+        if compute_moves(them, us) == 0 {
+            return us.count_ones() as i32 - them.count_ones() as i32;
+        }
+        return -solve_impl(them, us, -beta, -alpha);
+    }
+    let mut ordered: [(u32, u64, u64); 32] = [(0, 0, 0); 32];
+    let mut n = 0;
+    let mut remaining = legal;
+    while remaining != 0 {
+        let mv = pop_lsb(&mut remaining);
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        ordered[n] = (compute_moves(new_them, new_us).count_ones(), new_us, new_them);
+        n += 1;
+    }
+    ordered[..n].sort_unstable_by_key(|o| o.0);
+    let mut best = -64;
+    for &(_, new_us, new_them) in &ordered[..n] {
+        let v = -solve_impl(new_them, new_us, -beta, -alpha);
+        if v > best {
+            best = v;
+            if v > alpha {
+                alpha = v;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
+// ProbCut's estimate of `solve_impl`: fail-soft negamax over the final
+// disc difference, cut off after `plies` with the disc difference at
+// that point standing in for it.
+fn shallow_margin(us: u64, them: u64, plies: u32, mut alpha: i32, beta: i32) -> i32 {
+    let legal = compute_moves(us, them);
+    if legal == 0 {
+        if compute_moves(them, us) == 0 {
+            return us.count_ones() as i32 - them.count_ones() as i32;
+        }
+        return -shallow_margin(them, us, plies, -beta, -alpha);
+    }
+    if plies == 0 {
+        return us.count_ones() as i32 - them.count_ones() as i32;
+    }
+    let mut best = -64;
+    let mut remaining = legal;
+    while remaining != 0 {
+        let mv = pop_lsb(&mut remaining);
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        let v = -shallow_margin(new_them, new_us, plies - 1, -beta, -alpha);
+        if v > best {
+            best = v;
+            if v > alpha {
+                alpha = v;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Solve the position to the end of the game. Returns the move that
+/// maximises the final disc difference (`u64::MAX` if the side to move
+/// must pass or the game is over) and that difference, in Black's
+/// favour, in `-64..=64`. Exponential in the empties: meant for
+/// `ENDGAME_SOLVE_EMPTIES` and below.
+pub fn solve_endgame(white: u64, black: u64, is_white_move: bool) -> (u64, i32) {
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut remaining = compute_moves(us, them);
+    if remaining == 0 {
+        return (u64::MAX, to_absolute(solve_impl(us, them, -65, 65), is_white_move));
+    }
+    let (mut best_move, mut best) = (u64::MAX, -65);
+    while remaining != 0 {
+        let mv = pop_lsb(&mut remaining);
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        let v = -solve_impl(new_them, new_us, -65, -best);
+        if v > best {
+            best = v;
+            best_move = mv;
+        }
+    }
+    (best_move, to_absolute(best, is_white_move))
+}
+
+// A root search (`depth == orig_depth`) that runs to the end of a game
+// with at most `ENDGAME_SOLVE_EMPTIES` empties is answered by
+// `solve_endgame`, so game play learns the margin as well as the result.
+// Only at the root: below it the search's won/lost scores are on another
+// scale. The solver doesn't use the TT, so it leaves nothing there for
+// `principal_variation` or the next search.
+fn root_solve(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    orig_depth: u32,
+) -> Option<(u64, i32)> {
+    let empties = (!(white | black)).count_ones();
+    if !root_solve_enabled()
+        || depth != orig_depth
+        || depth < empties
+        || empties > ENDGAME_SOLVE_EMPTIES
+    {
+        return None;
+    }
+    let (mv, margin) = solve_endgame(white, black, is_white_move);
+    Some((mv, margin_to_eval(margin)))
+}
+
+/// Express an exact final margin (Black's favour) on the search's eval
+/// scale: a won or lost game is `±WIN_SCORE` plus the margin, so it
+/// ranks like a search-proven result while keeping the margin readable.
+pub fn margin_to_eval(margin: i32) -> i32 {
+    margin + margin.signum() * WIN_SCORE
+}
+
 fn nega_search(
     us: u64,
     them: u64,
@@ -657,8 +1880,14 @@ fn nega_search(
     beta: i32,
     orig_depth: u32,
     cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+    table: &TranspositionTable,
 ) -> (u64, i32) {
-    let mut ctx = SearchCtx::new(orig_depth, cfg);
+    let mut ctx = SearchCtx {
+        stop,
+        table,
+        ..SearchCtx::new(orig_depth, cfg)
+    };
     nega_search_impl::<false>(us, them, depth, alpha, beta, &mut ctx)
 }
 
@@ -671,13 +1900,49 @@ fn nega_search_cntr(
     orig_depth: u32,
     cfg: EvalCfg,
     counter: &mut u64,
+    table: &TranspositionTable,
 ) -> (u64, i32) {
-    let mut ctx = SearchCtx::new(orig_depth, cfg);
+    let mut stats = SearchStats::default();
+    let result =
+        nega_search_counted(us, them, depth, alpha, beta, orig_depth, cfg, &mut stats, table);
+    *counter += stats.nodes;
+    result
+}
+
+fn nega_search_counted(
+    us: u64,
+    them: u64,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    stats: &mut SearchStats,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    let mut ctx = SearchCtx {
+        table,
+        ..SearchCtx::new(orig_depth, cfg)
+    };
     let result = nega_search_impl::<true>(us, them, depth, alpha, beta, &mut ctx);
-    *counter += ctx.node_count;
+    add_stats(&ctx, stats);
     result
 }
 
+fn add_stats(ctx: &SearchCtx, stats: &mut SearchStats) {
+    stats.nodes += ctx.node_count;
+    stats.leaves += ctx.leaf_count;
+    stats.killer_cutoffs += ctx.killer_cutoffs;
+    stats.corner_extensions += ctx.corner_extensions;
+    stats.tt_probes += ctx.tt_probes;
+    stats.tt_hits += ctx.tt_hits;
+    stats.tt_collisions += ctx.tt_collisions;
+    let mut totals = CUTOFF_RANK_COUNTS.lock().unwrap();
+    for (total, n) in totals.iter_mut().zip(ctx.cutoff_ranks) {
+        *total += n;
+    }
+}
+
 // --------------------------------------------------------------------------
 // Public white/black wrappers (preserve external API semantics)
 // --------------------------------------------------------------------------
@@ -709,6 +1974,28 @@ fn to_absolute(v_us: i32, is_white_move: bool) -> i32 {
     }
 }
 
+/// Re-express an absolute (Black-perspective) eval from one colour's
+/// point of view: positive is good for White when `as_white`, for Black
+/// otherwise. Unlike the side-to-move frame this stays fixed for a whole
+/// game, which is what a player's log wants.
+pub fn eval_for_color(eval: i32, as_white: bool) -> i32 {
+    to_absolute(eval, as_white)
+}
+
+/// What a fail-soft search result says about the true eval, given the
+/// window `(alpha, beta)` it was searched with (both absolute): at or
+/// below alpha it is an upper bound (`BOUND_UPPER`), at or above beta a
+/// lower bound (`BOUND_LOWER`), in between exact. The TT's bound flags.
+pub fn window_bound(eval: i32, alpha: i32, beta: i32) -> u8 {
+    if eval <= alpha {
+        BOUND_UPPER
+    } else if eval >= beta {
+        BOUND_LOWER
+    } else {
+        BOUND_EXACT
+    }
+}
+
 pub fn search_moves_opt(
     white: u64,
     black: u64,
@@ -719,12 +2006,109 @@ pub fn search_moves_opt(
     orig_depth: u32,
     cfg: EvalCfg,
 ) -> (u64, i32) {
+    search_moves_opt_in(white, black, is_white_move, depth, alpha, beta, orig_depth, cfg, tt())
+}
+
+/// `search_moves_opt` against `table` instead of the global `tt()`, e.g.
+/// a fresh one that no other search reads or disturbs.
+pub fn search_moves_opt_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    if let Some(solved) = root_solve(white, black, is_white_move, depth, orig_depth) {
+        return solved;
+    }
     let (us, them) = to_us_them(white, black, is_white_move);
     let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
-    let (mv, v_us) = nega_search(us, them, depth, a_us, b_us, orig_depth, cfg);
+    let (mv, v_us) = nega_search(us, them, depth, a_us, b_us, orig_depth, cfg, None, table);
+    (mv, to_absolute(v_us, is_white_move))
+}
+
+/// Full-window serial search to `depth` that scores leaves with
+/// `evaluator`. `cfg` still sets the search shape (corner extension,
+/// PVS); its eval terms are unused. Returns the best move and its
+/// absolute eval.
+pub fn search_with_evaluator(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+    evaluator: &dyn Evaluator,
+) -> (u64, i32) {
+    search_with_evaluator_in(white, black, is_white_move, depth, cfg, evaluator, tt())
+}
+
+/// Search `(white, black)` to the end of the game at endgame selectivity
+/// `level` (see `set_endgame_selectivity`) against `table`, whatever the
+/// `--endgame-selectivity` setting. Returns the best move and its
+/// absolute won/drawn/lost score; at level 0 that is exact.
+pub fn solve_selective(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    level: u8,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    let depth = (!(white | black)).count_ones();
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut ctx = SearchCtx {
+        selectivity: level,
+        table,
+        ..SearchCtx::new(depth, cfg)
+    };
+    let (mv, v_us) = nega_search_impl::<false>(us, them, depth, MIN_EVAL, MAX_EVAL, &mut ctx);
+    (mv, to_absolute(v_us, is_white_move))
+}
+
+/// `search_with_evaluator` against `table` instead of the global `tt()`
+/// (see `search_moves_opt_in`).
+pub fn search_with_evaluator_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+    evaluator: &dyn Evaluator,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut ctx = SearchCtx {
+        cfg_key: splitmix64(eval_cfg_key(&cfg).wrapping_add(evaluator.key())),
+        evaluator: Some(evaluator),
+        table,
+        ..SearchCtx::new(depth, cfg)
+    };
+    let (mv, v_us) = nega_search_impl::<false>(us, them, depth, MIN_EVAL, MAX_EVAL, &mut ctx);
     (mv, to_absolute(v_us, is_white_move))
 }
 
+/// `search_moves_opt` that also says whether the eval is exact or only
+/// a bound (see `window_bound`), e.g. for re-searching with a wider or
+/// shifted window.
+pub fn search_moves_opt_bound(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+) -> (u64, i32, u8) {
+    let (mv, eval) =
+        search_moves_opt(white, black, is_white_move, depth, alpha, beta, orig_depth, cfg);
+    (mv, eval, window_bound(eval, alpha, beta))
+}
+
 pub fn search_moves_opt_cntr(
     white: u64,
     black: u64,
@@ -738,18 +2122,138 @@ pub fn search_moves_opt_cntr(
 ) -> (u64, i32) {
     let (us, them) = to_us_them(white, black, is_white_move);
     let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
-    let (mv, v_us) = nega_search_cntr(us, them, depth, a_us, b_us, orig_depth, cfg, counter);
+    let (mv, v_us) =
+        nega_search_cntr(us, them, depth, a_us, b_us, orig_depth, cfg, counter, tt());
     (mv, to_absolute(v_us, is_white_move))
 }
 
+/// Work done by a search: nodes entered, static evals at the horizon,
+/// beta cutoffs that came from a killer move, TT traffic, wall time and
+/// the depth searched. `hashfull` is the TT's occupancy (per mille) at
+/// the end.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    pub leaves: u64,
+    pub killer_cutoffs: u64,
+    pub corner_extensions: u64,
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub tt_collisions: u64,
+    pub hashfull: u32,
+    pub elapsed: Duration,
+    pub nps: u64,
+    pub depth: u32,
+}
+
+impl SearchStats {
+    fn finish(self, elapsed: Duration, depth: u32, table: &TranspositionTable) -> Self {
+        Self {
+            elapsed,
+            nps: (self.nodes as f64 / elapsed.as_secs_f64().max(1e-9)) as u64,
+            depth,
+            hashfull: table.hashfull(),
+            ..self
+        }
+    }
+}
+
+/// `search_moves_opt` that also reports a `SearchStats`.
+pub fn search_moves_opt_stats(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+) -> (u64, i32, SearchStats) {
+    search_moves_opt_stats_in(
+        white,
+        black,
+        is_white_move,
+        depth,
+        alpha,
+        beta,
+        orig_depth,
+        cfg,
+        tt(),
+    )
+}
+
+/// `search_moves_opt_stats` against `table` instead of the global `tt()`
+/// (see `search_moves_opt_in`); `hashfull` is that table's.
+pub fn search_moves_opt_stats_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> (u64, i32, SearchStats) {
+    let start = Instant::now();
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
+    let mut stats = SearchStats::default();
+    let (mv, v_us) =
+        nega_search_counted(us, them, depth, a_us, b_us, orig_depth, cfg, &mut stats, table);
+    (mv, to_absolute(v_us, is_white_move), stats.finish(start.elapsed(), depth, table))
+}
+
+/// Full-window `search_moves_opt_stats` that also reports what each root
+/// move cost: `(move, eval, nodes)` in search order, evals absolute. A
+/// move that didn't raise alpha only carries a bound (see
+/// `window_bound`). The root itself is the one node not in the list.
+pub fn search_moves_opt_root_nodes(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+) -> (u64, i32, SearchStats, Vec<(u64, i32, u64)>) {
+    search_moves_opt_root_nodes_in(white, black, is_white_move, depth, cfg, tt())
+}
+
+/// `search_moves_opt_root_nodes` against `table` instead of the global
+/// `tt()` (see `search_moves_opt_in`).
+pub fn search_moves_opt_root_nodes_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> (u64, i32, SearchStats, Vec<(u64, i32, u64)>) {
+    let start = Instant::now();
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut ctx = SearchCtx {
+        table,
+        ..SearchCtx::new(depth, cfg)
+    };
+    let (mv, v_us) = nega_search_impl::<true>(us, them, depth, MIN_EVAL, MAX_EVAL, &mut ctx);
+    let mut stats = SearchStats::default();
+    add_stats(&ctx, &mut stats);
+    let roots = ctx
+        .root_nodes
+        .iter()
+        .map(|&(m, v, nodes)| (m, to_absolute(v, is_white_move), nodes))
+        .collect();
+    (mv, to_absolute(v_us, is_white_move), stats.finish(start.elapsed(), depth, table), roots)
+}
+
 // --------------------------------------------------------------------------
 // Parallel root search
 // --------------------------------------------------------------------------
 //
-// Rayon-parallel evaluation of root candidates. Individual subtrees still
-// run the sequential TT-aware `nega_search`, so all threads share the same
-// transposition table (Hyatt's XOR trick keeps probes internally consistent
-// under Relaxed-ordered atomic writes).
+// Rayon-parallel evaluation of the top plies (`--parallel-depth`), pruned
+// against an alpha shared between the threads (see `par_search`). Subtrees
+// below the split run the sequential TT-aware `nega_search`, so all threads
+// share the same transposition table (Hyatt's XOR trick keeps probes
+// internally consistent under Relaxed-ordered atomic writes).
 
 pub fn search_moves_par(
     white: u64,
@@ -761,105 +2265,484 @@ pub fn search_moves_par(
     orig_depth: u32,
     cfg: EvalCfg,
 ) -> (u64, i32) {
+    search_moves_par_in(white, black, is_white_move, depth, alpha, beta, orig_depth, cfg, tt())
+}
+
+/// `search_moves_par` against `table` instead of the global `tt()` (see
+/// `search_moves_opt_in`); every thread shares it.
+pub fn search_moves_par_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    if let Some(solved) = root_solve(white, black, is_white_move, depth, orig_depth) {
+        return solved;
+    }
     let (us, them) = to_us_them(white, black, is_white_move);
-    let outcome = game_status_us_them(us, them);
+    let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
+    let split = parallel_depth();
+    let (mv, v_us) =
+        par_search(us, them, depth, a_us, b_us, orig_depth, cfg, None, None, split, table);
+    (mv, to_absolute(v_us, is_white_move))
+}
 
-    if outcome == WHITE_WON_OUTCOME {
-        return (u64::MAX, to_absolute(10_000, is_white_move));
+/// `search_moves_par` with an explicit parallel split depth instead of
+/// the `--parallel-depth` setting, for comparing split depths. The move
+/// and eval don't depend on it, only the speed.
+pub fn search_moves_par_split(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    parallel_depth: u32,
+) -> (u64, i32) {
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
+    let (mv, v_us) =
+        par_search(us, them, depth, a_us, b_us, orig_depth, cfg, None, None, parallel_depth, tt());
+    (mv, to_absolute(v_us, is_white_move))
+}
+
+/// Root move order carried from one iterative-deepening iteration to the
+/// next: the previous iteration's best move first, then the others by
+/// the scores it gave them (bounds, for moves cut against the shared
+/// alpha), ties to the lower square. Empty until a root search has
+/// scored every move; a root it doesn't fit falls back to
+/// `ordered_moves`.
+#[derive(Clone, Debug, Default)]
+pub struct RootOrder {
+    moves: Vec<u64>,
+}
+
+impl RootOrder {
+    pub fn moves(&self) -> &[u64] {
+        &self.moves
     }
-    if outcome == BLACK_WON_OUTCOME {
-        return (u64::MAX, to_absolute(-10_000, is_white_move));
+
+    // `scores` in the side-to-move frame, one entry per root move.
+    fn update(&mut self, best: u64, scores: &[(u64, i32)]) {
+        let mut rest: Vec<(u64, i32)> =
+            scores.iter().copied().filter(|&(mv, _)| mv != best).collect();
+        rest.sort_by_key(|&(mv, v)| (std::cmp::Reverse(v), mv));
+        self.moves = std::iter::once(best).chain(rest.into_iter().map(|(mv, _)| mv)).collect();
     }
-    if outcome == DRAW_OUTCOME {
-        return (u64::MAX, 0);
+
+    // The stored order if it is exactly the `legal` moves.
+    fn for_root(&self, legal: u64) -> Option<Vec<u64>> {
+        let bits = self.moves.iter().fold(0u64, |acc, &mv| acc | mv);
+        (bits == legal && self.moves.len() == legal.count_ones() as usize)
+            .then(|| self.moves.clone())
     }
+}
 
-    if depth == 0 {
-        return (u64::MAX, eval_position_with_cfg(white, black, cfg));
+// `search_moves_par` that gives up once `stop` is raised (the result of
+// an aborted search is meaningless) and orders the root by `order`,
+// updating it for the next iteration.
+fn search_moves_par_stop(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+    order: &mut RootOrder,
+) -> (u64, i32) {
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let (a_us, b_us) = us_frame_bounds(alpha, beta, is_white_move);
+    let split = parallel_depth();
+    let (mv, v_us) =
+        par_search(us, them, depth, a_us, b_us, orig_depth, cfg, stop, Some(order), split, tt());
+    (mv, to_absolute(v_us, is_white_move))
+}
+
+// Negamax over the top `parallel_depth` plies, in the side-to-move frame
+// like `nega_search_impl`: every child score is negated and mate-adjusted
+// on the way up, whether the child ran through `par_search` again or,
+// below the split, through `nega_search`.
+fn par_search(
+    us: u64,
+    them: u64,
+    depth: u32,
+    alpha: i32,
+    beta: i32,
+    orig_depth: u32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+    root: Option<&mut RootOrder>,
+    parallel_depth: u32,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    let outcome = game_status_us_them(us, them);
+
+    // Finished games, passes and the horizon are left to `nega_search`, so
+    // they are scored exactly as the serial search scores them: a pass
+    // swaps sides without consuming a ply, and a leaf goes through
+    // quiescence rather than the bare static eval.
+    if depth == 0 || outcome >= DRAW_OUTCOME {
+        return nega_search(us, them, depth, alpha, beta, orig_depth, cfg, stop, table);
     }
 
-    if outcome == PASS_OUTCOME {
-        if depth == orig_depth {
-            return (u64::MAX, eval_position_with_cfg(white, black, cfg));
+    let candidates = match root.as_deref().and_then(|order| order.for_root(outcome)) {
+        Some(moves) => moves,
+        None => ordered_moves(us, them, outcome, eval_cfg_key(&cfg), table),
+    };
+    let extend_corners = extends_corners(us, them, depth, orig_depth, &cfg);
+    // Score of `candidate` in our frame, searched with the window (a, b).
+    let score = |candidate: u64, a: i32, b: i32| {
+        let (new_us, new_them) = apply_move_us_them(us, them, candidate);
+        // Same corner extension as `nega_search_impl`, so both agree.
+        let child_depth = if extend_corners && candidate & CORNER_MASK != 0 {
+            depth
+        } else {
+            depth - 1
+        };
+        let (_, child) = if orig_depth - depth + 1 >= parallel_depth {
+            nega_search(new_them, new_us, child_depth, -b, -a, orig_depth, cfg, stop, table)
+        } else {
+            par_search(
+                new_them,
+                new_us,
+                child_depth,
+                -b,
+                -a,
+                orig_depth,
+                cfg,
+                stop,
+                None,
+                parallel_depth,
+                table,
+            )
+        };
+        adjust_mate_distance(-child)
+    };
+
+    let tiebreak = root_tiebreak();
+    if depth == orig_depth && tiebreak != Tiebreak::FirstFound {
+        // Breaking ties needs the exact eval of every move, so nothing
+        // is pruned against anything else here.
+        let results: Vec<(u64, i32)> = candidates
+            .into_par_iter()
+            .map(|candidate| (candidate, score(candidate, alpha, beta)))
+            .collect();
+        let best = results.iter().map(|r| r.1).max().unwrap_or(i32::MIN);
+        let tied: Vec<u64> = results
+            .iter()
+            .filter(|r| r.1 == best)
+            .map(|r| r.0)
+            .collect();
+        let chosen = break_root_tie(us, them, &tied, tiebreak);
+        if let Some(order) = root {
+            order.update(chosen, &results);
+        }
+        return (chosen, best);
+    }
+
+    // Young brothers wait: the first (TT) move is searched alone with the
+    // full window. Its score seeds an alpha shared by the other moves,
+    // which run in parallel: each is first tried with a null window at
+    // the current alpha and only re-searched with the full window if it
+    // beats it, raising the shared alpha for the rest.
+    let first = candidates[0];
+    let first_v = score(first, alpha, beta);
+    if first_v >= beta || candidates.len() == 1 {
+        return (first, first_v);
+    }
+    let shared_alpha = AtomicI32::new(alpha.max(first_v));
+    // (move, score, exact). A fail-low score is only an upper bound and
+    // may equal the best score without being it, so on equal scores an
+    // exact result wins; otherwise the earlier move keeps the tie until
+    // the root settles it below.
+    let better = |acc: (u64, i32, bool), x: (u64, i32, bool)| {
+        if x.1 > acc.1 || (x.1 == acc.1 && x.2 && !acc.2) {
+            x
+        } else {
+            acc
+        }
+    };
+    let rest: Vec<(u64, i32, bool)> = candidates[1..]
+        .par_iter()
+        .map(|&candidate| {
+            let a = shared_alpha.load(Ordering::Relaxed);
+            if a >= beta {
+                return (candidate, i32::MIN, false);
+            }
+            let mut v = score(candidate, a, a + 1);
+            if v <= a {
+                return (candidate, v, false);
+            }
+            if v < beta {
+                let a = shared_alpha.load(Ordering::Relaxed);
+                v = score(candidate, a, beta);
+                if v <= a {
+                    return (candidate, v, false);
+                }
+            }
+            shared_alpha.fetch_max(v, Ordering::Relaxed);
+            (candidate, v, true)
+        })
+        .collect();
+    let (mut best_move, best_v, _) = rest.iter().copied().fold((first, first_v, true), better);
+    // A move that ties the best only as a bound was cut because another
+    // reached that score first, which depends on thread timing and on
+    // the TT move leading the order. At the root, settle ties on the
+    // lowest square instead, so the move played doesn't depend on the
+    // schedule, the split depth or what earlier searches left in the TT.
+    if depth == orig_depth && best_v < beta {
+        let mut tied: Vec<u64> = rest
+            .iter()
+            .filter(|r| !r.2 && r.1 == best_v && r.0 < best_move)
+            .map(|r| r.0)
+            .collect();
+        tied.sort_unstable();
+        if let Some(&mv) = tied.iter().find(|&&mv| score(mv, best_v - 1, best_v) >= best_v) {
+            best_move = mv;
         }
-        // Must match `nega_search_impl`: a pass swaps sides without
-        // consuming a ply of the remaining search budget. Using
-        // `depth - 1` here was a bug — it made the parallel root path
-        // one ply shallower than `search_moves_opt` / `nega_search`
-        // for the same position after a pass.
-        let (_, eval) = search_moves_opt(
-            white,
-            black,
-            !is_white_move,
-            depth,
-            alpha,
-            beta,
-            orig_depth,
-            cfg,
-        );
-        return (u64::MAX, eval);
     }
+    if let Some(order) = root {
+        let scores: Vec<(u64, i32)> = std::iter::once((first, first_v))
+            .chain(rest.iter().map(|&(mv, v, _)| (mv, v)))
+            .collect();
+        order.update(best_move, &scores);
+    }
+    (best_move, best_v)
+}
 
-    // Plain ascending bit order preserves rayon-reduce tie-break behaviour
-    // w.r.t. the original find_legal_moves_alt-based implementation.
-    let mut candidates: Vec<u64> = Vec::new();
-    let mut remaining = outcome;
+// Candidate order for `par_search` below the root, or at a root without a
+// fitting `RootOrder`: the TT move first, the rest in ascending bit order.
+// The order only affects how much is pruned: root ties go to the lowest
+// square whatever the order.
+fn ordered_moves(
+    us: u64,
+    them: u64,
+    legal: u64,
+    cfg_key: u64,
+    table: &TranspositionTable,
+) -> Vec<u64> {
+    let mut moves: Vec<u64> = Vec::with_capacity(legal.count_ones() as usize);
+    let mut remaining = legal;
     while remaining != 0 {
-        candidates.push(pop_lsb(&mut remaining));
+        moves.push(pop_lsb(&mut remaining));
+    }
+    let entry = if tt_enabled() {
+        table.probe(hash_position(us, them) ^ cfg_key)
+    } else {
+        None
+    };
+    if let Some(entry) = entry {
+        if entry.move_sq < NO_MOVE_SQ {
+            let tt_move = 1u64 << entry.move_sq;
+            if let Some(i) = moves.iter().position(|&m| m == tt_move) {
+                moves[..=i].rotate_right(1);
+            }
+        }
     }
+    moves
+}
 
-    let sign_us: i32 = if is_white_move { -1 } else { 1 };
+/// The order in which `search_moves_par` hands the legal moves of a
+/// position to its workers (with `table` = `tt()`); empty if the side to
+/// move has no move.
+pub fn root_move_order(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> Vec<u64> {
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let legal = game_status_us_them(us, them);
+    if legal >= DRAW_OUTCOME {
+        return Vec::new();
+    }
+    ordered_moves(us, them, legal, eval_cfg_key(&cfg), table)
+}
+
+/// A root search's decision for game-play code. The searches return a
+/// bare bitboard in which `u64::MAX` stands for "no move" both when the
+/// side to move must pass and when the game is over, and 0 for a failed
+/// search; `resolve` sorts that out against the position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMove {
+    Play(u64),
+    Pass,
+    /// The game is over, or the search came back without a legal move.
+    NoMove,
+}
+
+impl SearchMove {
+    /// Classify `mv`, as returned by a search of the given position. A
+    /// position where the side to move has no move but the game goes on
+    /// is always a pass, whatever the search returned.
+    pub fn resolve(white: u64, black: u64, is_white_move: bool, mv: u64) -> Self {
+        let legal = check_game_status(white, black, is_white_move);
+        if legal == PASS_OUTCOME {
+            SearchMove::Pass
+        } else if legal < DRAW_OUTCOME && mv.count_ones() == 1 && legal & mv != 0 {
+            SearchMove::Play(mv)
+        } else {
+            SearchMove::NoMove
+        }
+    }
+}
+
+/// How `search_moves_par` picks among root moves with equal evals
+/// (`--tiebreak`). The secondary criteria only look at the tied moves,
+/// so search strength is unchanged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tiebreak {
+    /// No secondary criterion: the search itself settles root ties on
+    /// the lowest square.
+    FirstFound,
+    LowestSquare,
+    /// Most moves for us if we were to move again after the move.
+    MostMobilityForUs,
+    /// Fewest replies for the opponent.
+    LeastMobilityForOpponent,
+}
+
+impl std::str::FromStr for Tiebreak {
+    type Err = String;
 
-    let (best_move, _best_eval_us, best_orig_eval) = candidates
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first-found" => Ok(Tiebreak::FirstFound),
+            "lowest-square" => Ok(Tiebreak::LowestSquare),
+            "most-mobility-for-us" => Ok(Tiebreak::MostMobilityForUs),
+            "least-mobility-for-opponent" => Ok(Tiebreak::LeastMobilityForOpponent),
+            _ => Err(format!("unknown tiebreak {:?}", s)),
+        }
+    }
+}
+
+static ROOT_TIEBREAK: AtomicU8 = AtomicU8::new(Tiebreak::FirstFound as u8);
+
+pub fn set_root_tiebreak(tiebreak: Tiebreak) {
+    ROOT_TIEBREAK.store(tiebreak as u8, Ordering::Relaxed);
+}
+
+fn root_tiebreak() -> Tiebreak {
+    match ROOT_TIEBREAK.load(Ordering::Relaxed) {
+        1 => Tiebreak::LowestSquare,
+        2 => Tiebreak::MostMobilityForUs,
+        3 => Tiebreak::LeastMobilityForOpponent,
+        _ => Tiebreak::FirstFound,
+    }
+}
+
+/// Choose among the eval-tied root moves `tied` (in search order) of the
+/// `(us, them)` position. Remaining ties go to the lowest square.
+pub fn break_root_tie(us: u64, them: u64, tied: &[u64], tiebreak: Tiebreak) -> u64 {
+    let mobility_after = |mv: u64| {
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        (
+            compute_moves(new_us, new_them).count_ones(),
+            compute_moves(new_them, new_us).count_ones(),
+        )
+    };
+    let pick = match tiebreak {
+        Tiebreak::FirstFound => tied.first().copied(),
+        Tiebreak::LowestSquare => tied.iter().copied().min(),
+        Tiebreak::MostMobilityForUs => tied
+            .iter()
+            .copied()
+            .min_by_key(|&mv| (std::cmp::Reverse(mobility_after(mv).0), mv)),
+        Tiebreak::LeastMobilityForOpponent => {
+            tied.iter().copied().min_by_key(|&mv| (mobility_after(mv).1, mv))
+        }
+    };
+    pick.unwrap_or(u64::MAX)
+}
+
+/// Full-window search of every legal root move at `depth`, in parallel.
+/// Returns `(move, eval)` in `root_move_order` with evals in the same
+/// frame as `search_moves_par`; empty if the side to move has no move.
+/// Unlike `search_moves_par`, which keeps only the best, this is for
+/// callers that weigh alternatives (e.g. book generation).
+pub fn search_root_moves(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+) -> Vec<(u64, i32)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let (us, them) = to_us_them(white, black, is_white_move);
+    root_move_order(white, black, is_white_move, cfg, tt())
         .into_par_iter()
         .map(|candidate| {
             let (new_us, new_them) = apply_move_us_them(us, them, candidate);
-            let child_white = new_white(is_white_move, new_us, new_them);
-            let child_black = new_black(is_white_move, new_us, new_them);
-
-            if orig_depth - depth > 0 {
-                let (_, orig) = search_moves_opt(
-                    child_white,
-                    child_black,
-                    !is_white_move,
-                    depth - 1,
-                    alpha,
-                    beta,
-                    orig_depth,
-                    cfg,
-                );
-                let eval_us_local = orig * sign_us;
-                (candidate, eval_us_local, orig)
-            } else {
-                let (_, mut orig) = search_moves_par(
-                    child_white,
-                    child_black,
-                    !is_white_move,
-                    depth - 1,
-                    alpha,
-                    beta,
-                    orig_depth,
-                    cfg,
-                );
-                orig = adjust_mate_distance(orig);
-                let eval_us_local = orig * sign_us;
-                (candidate, eval_us_local, orig)
-            }
+            let (_, eval) = search_moves_opt(
+                new_white(is_white_move, new_us, new_them),
+                new_black(is_white_move, new_us, new_them),
+                !is_white_move,
+                depth - 1,
+                MIN_EVAL,
+                MAX_EVAL,
+                // Searched as the child it is rather than as a root of its
+                // own, so it gets the same corner extensions as in
+                // `search_moves_par`.
+                depth,
+                cfg,
+            );
+            (candidate, adjust_mate_distance(eval))
         })
-        .reduce(
-            || (0, i32::MIN, i32::MIN),
-            |acc, x| {
-                let (_, acc_eval, _) = acc;
-                let (cand, x_eval, x_orig) = x;
-                if x_eval > acc_eval && cand != 0 {
-                    (cand, x_eval, x_orig)
-                } else {
-                    acc
-                }
-            },
-        );
+        .collect()
+}
 
-    (best_move, best_orig_eval)
+/// Pick uniformly among the `scored` root moves (evals in Black's frame,
+/// as from `search_root_moves`) that come within `margin` of the best
+/// for the side to move. Candidates are taken in square order, so the
+/// pick depends only on the scores and `rng`. `None` if `scored` is
+/// empty.
+pub fn pick_within_margin(
+    scored: &[(u64, i32)],
+    is_white_move: bool,
+    margin: i32,
+    rng: &mut Rng64,
+) -> Option<(u64, i32)> {
+    let ours = |eval: i32| eval_for_color(eval, is_white_move);
+    let best = scored.iter().map(|&(_, eval)| ours(eval)).max()?;
+    let mut near: Vec<(u64, i32)> = scored
+        .iter()
+        .copied()
+        .filter(|&(_, eval)| ours(eval) >= best - margin)
+        .collect();
+    near.sort_unstable_by_key(|&(mv, _)| mv);
+    Some(near[rng.below(near.len())])
+}
+
+/// The `k` best root moves at `depth`, best first for the side to move,
+/// with evals in Black's frame. Each is a full-window search of its own
+/// (see `search_root_moves`), so the runners-up carry exact scores
+/// rather than the bounds a single root alpha-beta would give them.
+/// Equal evals keep `root_move_order`.
+pub fn search_multipv(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    k: usize,
+    cfg: EvalCfg,
+) -> Vec<(u64, i32)> {
+    let mut lines = search_root_moves(white, black, is_white_move, depth, cfg);
+    lines.sort_by_key(|&(_, eval)| -eval_for_color(eval, is_white_move));
+    lines.truncate(k);
+    lines
 }
 
 #[inline(always)]
@@ -883,11 +2766,25 @@ fn new_black(is_white_move: bool, new_us: u64, new_them: u64) -> u64 {
 // --------------------------------------------------------------------------
 // Iterative deepening drivers
 // --------------------------------------------------------------------------
+
+/// Depth to search `(white, black)` at when `requested_depth` was asked
+/// for: never more than the empty squares left, since every move fills
+/// one and deeper plies would only re-search the finished game, and all
+/// of them once that is within `AUTO_SOLVE_EXTRA_PLIES` of the request.
+pub fn effective_depth(white: u64, black: u64, requested_depth: u32) -> u32 {
+    let empties = (!(white | black)).count_ones();
+    if empties <= requested_depth.saturating_add(AUTO_SOLVE_EXTRA_PLIES) {
+        empties
+    } else {
+        requested_depth
+    }
+}
 //
 // The transposition table makes iterative deepening nearly-free: each prior
 // iteration seeds the next with good move ordering (via the TT-move-first
 // probe in `nega_search_impl`), and completed subtrees turn into cutoffs.
-// These helpers are the recommended entry points for game-play code.
+// These helpers are the recommended entry points for game-play code, and
+// return the root's decision as a `SearchMove`.
 
 pub fn search_iterative(
     white: u64,
@@ -895,37 +2792,416 @@ pub fn search_iterative(
     is_white_move: bool,
     max_depth: u32,
     cfg: EvalCfg,
+) -> (SearchMove, i32) {
+    search_iterative_ordered(white, black, is_white_move, max_depth, cfg, &mut RootOrder::default())
+}
+
+/// `search_iterative` starting from, and leaving behind, a root move
+/// order: every iteration searches the root in the order the previous
+/// one left (see `RootOrder`), so on return `order` is what a next,
+/// deeper iteration would start from, led by the returned move.
+pub fn search_iterative_ordered(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    order: &mut RootOrder,
+) -> (SearchMove, i32) {
+    let (mv, eval, _) =
+        iterate(white, black, is_white_move, max_depth, MIN_EVAL, MAX_EVAL, cfg, None, order);
+    (mv, eval)
+}
+
+/// `search_iterative` with a caller-chosen root window, e.g. for
+/// null-window verification searches. A result outside `(alpha, beta)`
+/// is only a bound. After depth 1 each iteration starts from a narrow
+/// aspiration window around the previous score (see
+/// `aspiration_search`), except once that score is a won or lost game.
+pub fn search_iterative_window(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    alpha: i32,
+    beta: i32,
+    cfg: EvalCfg,
+) -> (SearchMove, i32) {
+    let mut order = RootOrder::default();
+    let (mv, eval, _) =
+        iterate(white, black, is_white_move, max_depth, alpha, beta, cfg, None, &mut order);
+    (mv, eval)
+}
+
+/// `search_iterative` that another thread can cut short by raising
+/// `stop`. The iteration running at that point is abandoned and the
+/// deepest completed one returned, as `(move, eval, depth reached)`.
+/// Depth 1 ignores the flag, so there is always a move to play.
+pub fn search_iterative_stop(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    stop: &AtomicBool,
+) -> (SearchMove, i32, u32) {
+    let mut order = RootOrder::default();
+    let stop = StopSignal::new(stop);
+    let (lo, hi) = (MIN_EVAL, MAX_EVAL);
+    iterate(white, black, is_white_move, max_depth, lo, hi, cfg, Some(&stop), &mut order)
+}
+
+fn iterate(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    alpha: i32,
+    beta: i32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+    order: &mut RootOrder,
+) -> (SearchMove, i32, u32) {
+    if let Some((mv, eval)) = root_solve(white, black, is_white_move, max_depth, max_depth) {
+        return (SearchMove::resolve(white, black, is_white_move, mv), eval, max_depth);
+    }
+    tt().new_age();
+    let mut best = (u64::MAX, 0i32);
+    let mut reached = 0;
+    for d in 1..=max_depth {
+        let stop = if d == 1 { None } else { stop };
+        let result = if d == 1 || best.1.abs() > MATE_THRESHOLD {
+            search_moves_par_stop(white, black, is_white_move, d, alpha, beta, d, cfg, stop, order)
+        } else {
+            let guess = best.1;
+            aspiration_search(white, black, is_white_move, d, guess, alpha, beta, cfg, stop, order)
+        };
+        if stop.is_some_and(StopSignal::interrupted) {
+            break;
+        }
+        // Finished before the flag went up: keep it, but don't start
+        // an iteration that would be cut short at once.
+        best = result;
+        reached = d;
+        if stop_requested(stop) {
+            break;
+        }
+    }
+    (SearchMove::resolve(white, black, is_white_move, best.0), best.1, reached)
+}
+
+/// Search `depth` with the window `guess ± ASPIRATION_WINDOW`, clipped
+/// to `(lo, hi)`. The search is fail-soft, so a result at or below alpha
+/// (at or above beta) is only an upper (lower) bound: that side of the
+/// window is widened, doubling the step each time, and the depth
+/// searched again, with the TT keeping re-searches cheap. Once a side
+/// reaches `lo` or `hi` it stays there, so this terminates with the same
+/// answer as a single search over `(lo, hi)`.
+fn aspiration_search(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    guess: i32,
+    lo: i32,
+    hi: i32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+    order: &mut RootOrder,
+) -> (u64, i32) {
+    let guess = guess.clamp(lo, hi);
+    let mut delta = ASPIRATION_WINDOW;
+    let mut alpha = (guess - delta).max(lo);
+    let mut beta = (guess + delta).min(hi);
+    loop {
+        let (mv, eval) = search_moves_par_stop(
+            white,
+            black,
+            is_white_move,
+            depth,
+            alpha,
+            beta,
+            depth,
+            cfg,
+            stop,
+            order,
+        );
+        if stop.is_some_and(StopSignal::interrupted) {
+            return (mv, eval);
+        }
+        match window_bound(eval, alpha, beta) {
+            BOUND_UPPER if alpha > lo => alpha = (alpha - delta).max(lo),
+            BOUND_LOWER if beta < hi => beta = (beta + delta).min(hi),
+            _ => return (mv, eval),
+        }
+        delta *= 2;
+    }
+}
+
+/// MTD(f): converge on the exact value at `depth` by null-window
+/// searches alone, starting from `first_guess` (absolute). Each search
+/// moves the lower or upper bound onto its result until they meet; the
+/// TT keeps the repeated searches of the same tree cheap, so a good
+/// guess (the previous depth's score) takes only a few passes. Serial.
+pub fn mtdf(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    first_guess: i32,
+    cfg: EvalCfg,
 ) -> (u64, i32) {
+    mtdf_stop(white, black, is_white_move, depth, first_guess, cfg, None)
+}
+
+// `mtdf` that gives up once `stop` is raised, leaving a meaningless result
+// (see `StopSignal`).
+fn mtdf_stop(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    depth: u32,
+    first_guess: i32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+) -> (u64, i32) {
+    if let Some(solved) = root_solve(white, black, is_white_move, depth, depth) {
+        return solved;
+    }
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut lower = MIN_EVAL;
+    let mut upper = MAX_EVAL;
+    let mut g = to_absolute(first_guess, is_white_move).clamp(lower + 1, upper - 1);
+    // Only a search that fails high proves its move reaches the score.
+    let mut best = u64::MAX;
+    let mut last = u64::MAX;
+    while lower < upper {
+        let beta = if g == lower { g + 1 } else { g };
+        let (mv, v) = nega_search(us, them, depth, beta - 1, beta, depth, cfg, stop, tt());
+        if stop.is_some_and(StopSignal::interrupted) {
+            break;
+        }
+        g = v;
+        last = mv;
+        if g < beta {
+            upper = g;
+        } else {
+            lower = g;
+            best = mv;
+        }
+    }
+    if best == u64::MAX {
+        best = last;
+    }
+    (best, to_absolute(g, is_white_move))
+}
+
+/// Iterative deepening driven by `mtdf`, each depth seeded with the
+/// previous depth's score; the `--mtdf` alternative to the aspiration
+/// windows of `search_iterative`.
+pub fn search_iterative_mtdf(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+) -> (SearchMove, i32) {
+    let (mv, eval, _) = iterate_mtdf(white, black, is_white_move, max_depth, cfg, None);
+    (mv, eval)
+}
+
+/// `search_iterative_mtdf` that another thread can cut short by raising
+/// `stop`, like `search_iterative_stop`: the deepest completed iteration
+/// is returned as `(move, eval, depth reached)`.
+pub fn search_iterative_mtdf_stop(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    stop: &AtomicBool,
+) -> (SearchMove, i32, u32) {
+    let stop = StopSignal::new(stop);
+    iterate_mtdf(white, black, is_white_move, max_depth, cfg, Some(&stop))
+}
+
+fn iterate_mtdf(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    stop: Option<&StopSignal>,
+) -> (SearchMove, i32, u32) {
     tt().new_age();
+    let mut best = (u64::MAX, 0);
+    let mut reached = 0;
+    for d in 1..=max_depth {
+        // As in `iterate`: depth 1 always completes.
+        let stop = if d == 1 { None } else { stop };
+        let result = mtdf_stop(white, black, is_white_move, d, best.1, cfg, stop);
+        if stop.is_some_and(StopSignal::interrupted) {
+            break;
+        }
+        best = result;
+        reached = d;
+        if stop_requested(stop) {
+            break;
+        }
+    }
+    (SearchMove::resolve(white, black, is_white_move, best.0), best.1, reached)
+}
+
+/// Iterative deepening against a wall-clock `budget` instead of a fixed
+/// depth. An iteration is never interrupted, so the deepest completed
+/// one is returned; a new one is only started if, at the observed
+/// growth rate, it is expected to finish within the budget. Depth 1 is
+/// always searched. Returns `(move, eval, depth reached)`.
+pub fn search_timed(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    budget: Duration,
+    max_depth: u32,
+    cfg: EvalCfg,
+) -> (SearchMove, i32, u32) {
+    if let Some((mv, eval)) = root_solve(white, black, is_white_move, max_depth, max_depth) {
+        return (SearchMove::resolve(white, black, is_white_move, mv), eval, max_depth);
+    }
+    tt().new_age();
+    let start = Instant::now();
     let mut best = (u64::MAX, 0i32);
+    let mut reached = 0;
+    let mut last_iter = Duration::ZERO;
+    let mut order = RootOrder::default();
     for d in 1..=max_depth {
-        best = search_moves_par(white, black, is_white_move, d, -20000, 20000, d, cfg);
+        let elapsed = start.elapsed();
+        if d > 1 && elapsed + last_iter.mul_f64(TIMED_GROWTH_ESTIMATE) > budget {
+            break;
+        }
+        best = search_moves_par_stop(
+            white,
+            black,
+            is_white_move,
+            d,
+            MIN_EVAL,
+            MAX_EVAL,
+            d,
+            cfg,
+            None,
+            &mut order,
+        );
+        reached = d;
+        last_iter = start.elapsed() - elapsed;
     }
-    best
+    (SearchMove::resolve(white, black, is_white_move, best.0), best.1, reached)
 }
 
-pub fn search_iterative_cntr(
+/// Serial iterative deepening (like `search_iterative_cntr`) with the
+/// work of all iterations summed into one `SearchStats`.
+pub fn search_iterative_stats(
     white: u64,
     black: u64,
     is_white_move: bool,
     max_depth: u32,
     cfg: EvalCfg,
-    counter: &mut u64,
-) -> (u64, i32) {
+) -> (SearchMove, i32, SearchStats) {
     tt().new_age();
+    let start = Instant::now();
     let mut best = (u64::MAX, 0i32);
+    let mut total = SearchStats::default();
     for d in 1..=max_depth {
-        best = search_moves_opt_cntr(
+        let (mv, eval, stats) = search_moves_opt_stats(
             white,
             black,
             is_white_move,
             d,
-            -20000,
-            20000,
+            MIN_EVAL,
+            MAX_EVAL,
             d,
             cfg,
-            counter,
         );
+        best = (mv, eval);
+        total.nodes += stats.nodes;
+        total.leaves += stats.leaves;
+        total.killer_cutoffs += stats.killer_cutoffs;
+        total.corner_extensions += stats.corner_extensions;
+        total.tt_probes += stats.tt_probes;
+        total.tt_hits += stats.tt_hits;
+        total.tt_collisions += stats.tt_collisions;
+    }
+    let mv = SearchMove::resolve(white, black, is_white_move, best.0);
+    (mv, best.1, total.finish(start.elapsed(), max_depth, tt()))
+}
+
+/// Walk the TT's stored best moves from `(white, black)` to recover the
+/// principal variation of the last search, as move bits. Stops at a TT
+/// miss, an entry without a move, a stored move that isn't legal here
+/// (a key collision) or the end of the game. Passes are followed but not
+/// listed. Every move adds a disc, so the walk is bounded even if
+/// collisions chain entries into a loop.
+pub fn principal_variation(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    cfg: EvalCfg,
+    table: &TranspositionTable,
+) -> Vec<u64> {
+    let cfg_key = eval_cfg_key(&cfg);
+    let (mut us, mut them) = to_us_them(white, black, is_white_move);
+    let mut pv = Vec::new();
+    while pv.len() < 64 {
+        let status = game_status_us_them(us, them);
+        if status == PASS_OUTCOME {
+            std::mem::swap(&mut us, &mut them);
+            continue;
+        }
+        if status >= DRAW_OUTCOME {
+            break;
+        }
+        let mv = match table.probe(hash_position(us, them) ^ cfg_key) {
+            Some(entry) if entry.move_sq < NO_MOVE_SQ => 1u64 << entry.move_sq,
+            _ => break,
+        };
+        if status & mv == 0 {
+            break;
+        }
+        pv.push(mv);
+        let (new_us, new_them) = apply_move_us_them(us, them, mv);
+        us = new_them;
+        them = new_us;
+    }
+    pv
+}
+
+pub fn search_iterative_cntr(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    counter: &mut u64,
+) -> (u64, i32) {
+    search_iterative_cntr_in(white, black, is_white_move, max_depth, cfg, counter, tt())
+}
+
+/// `search_iterative_cntr` against `table` instead of the global `tt()`
+/// (see `search_moves_opt_in`).
+pub fn search_iterative_cntr_in(
+    white: u64,
+    black: u64,
+    is_white_move: bool,
+    max_depth: u32,
+    cfg: EvalCfg,
+    counter: &mut u64,
+    table: &TranspositionTable,
+) -> (u64, i32) {
+    table.new_age();
+    let (us, them) = to_us_them(white, black, is_white_move);
+    let mut best = (u64::MAX, 0i32);
+    for d in 1..=max_depth {
+        let (mv, v_us) = nega_search_cntr(us, them, d, MIN_EVAL, MAX_EVAL, d, cfg, counter, table);
+        best = (mv, to_absolute(v_us, is_white_move));
     }
     best
 }
@@ -0,0 +1,140 @@
+//! Shared static-evaluation cache.
+//!
+//! Leaf evaluation now pays two `compute_moves` calls for the mobility
+//! term, and transpositions reach the same leaf from many move orders -
+//! often in different rayon workers of `search_moves_par`. This table
+//! memoizes `eval_us_them` results by position key so that work is done
+//! once per position rather than once per visit.
+//!
+//! The layout mirrors the transposition table (see `tt.rs`): a direct-
+//! mapped array of 16-byte slots written with Hyatt's XOR trick, so
+//! concurrent readers only ever observe a consistent entry or a miss and
+//! no locks are taken. Entries are unconditionally overwritten - an eval
+//! is equally cheap to recompute wherever it came from.
+//!
+//! The cache is off by default and enabled with `--eval-cache` so its
+//! effect can be A/B-compared with `--benchmark`; `--no-tt` turns it off
+//! too, so that switch still gives a search that memoizes nothing and
+//! really evaluates every leaf it visits. Hit/probe counters are
+//! only maintained by the counting (benchmark) search, keeping shared
+//! atomics out of the production hot path.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+// Set on every stored data word so a valid entry is never all-zero and
+// can't be confused with an empty slot.
+const OCCUPIED_BIT: u64 = 1 << 32;
+
+#[repr(align(16))]
+struct EvalSlot {
+    word_a: AtomicU64,
+    word_b: AtomicU64,
+}
+
+pub struct EvalCache {
+    slots: Box<[EvalSlot]>,
+    mask: usize,
+    probes: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl EvalCache {
+    pub fn new_mb(mb: usize) -> Self {
+        let requested = (mb * 1024 * 1024) / std::mem::size_of::<EvalSlot>();
+        let entries = if requested < 1024 {
+            1024
+        } else {
+            1usize << (usize::BITS - 1 - requested.leading_zeros())
+        };
+        let slots: Vec<EvalSlot> = (0..entries)
+            .map(|_| EvalSlot {
+                word_a: AtomicU64::new(0),
+                word_b: AtomicU64::new(0),
+            })
+            .collect();
+        Self {
+            slots: slots.into_boxed_slice(),
+            mask: entries - 1,
+            probes: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn clear(&self) {
+        for s in self.slots.iter() {
+            s.word_a.store(0, Ordering::Relaxed);
+            s.word_b.store(0, Ordering::Relaxed);
+        }
+        self.probes.store(0, Ordering::Relaxed);
+        self.hits.store(0, Ordering::Relaxed);
+    }
+
+    /// Look up `key`. `COUNT` selects whether the probe/hit counters are
+    /// updated (see module docs).
+    #[inline(always)]
+    pub fn probe<const COUNT: bool>(&self, key: u64) -> Option<i32> {
+        let slot = unsafe { self.slots.get_unchecked((key as usize) & self.mask) };
+        let a = slot.word_a.load(Ordering::Relaxed);
+        let b = slot.word_b.load(Ordering::Relaxed);
+        if COUNT {
+            self.probes.fetch_add(1, Ordering::Relaxed);
+        }
+        if b & OCCUPIED_BIT == 0 || a ^ b != key {
+            return None;
+        }
+        if COUNT {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Some(b as u32 as i32)
+    }
+
+    #[inline(always)]
+    pub fn store(&self, key: u64, eval: i32) {
+        let slot = unsafe { self.slots.get_unchecked((key as usize) & self.mask) };
+        let b = (eval as u32 as u64) | OCCUPIED_BIT;
+        slot.word_a.store(key ^ b, Ordering::Relaxed);
+        slot.word_b.store(b, Ordering::Relaxed);
+    }
+
+    /// The cached eval for `key`, or `eval()` stored under it on a miss.
+    #[inline(always)]
+    pub fn get_or_eval<const COUNT: bool>(&self, key: u64, eval: impl FnOnce() -> i32) -> i32 {
+        if let Some(v) = self.probe::<COUNT>(key) {
+            return v;
+        }
+        let v = eval();
+        self.store(key, v);
+        v
+    }
+
+    /// `(probes, hits)` recorded by counting searches since the last clear.
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.probes.load(Ordering::Relaxed),
+            self.hits.load(Ordering::Relaxed),
+        )
+    }
+}
+
+// --------------------------------------------------------------------------
+// Global cache singleton and on/off switch
+// --------------------------------------------------------------------------
+
+static GLOBAL_EVAL_CACHE: OnceLock<EvalCache> = OnceLock::new();
+static EVAL_CACHE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub const DEFAULT_EVAL_CACHE_MB: usize = 4;
+
+/// Access the global eval cache, creating it on first use.
+pub fn eval_cache() -> &'static EvalCache {
+    GLOBAL_EVAL_CACHE.get_or_init(|| EvalCache::new_mb(DEFAULT_EVAL_CACHE_MB))
+}
+
+pub fn set_eval_cache_enabled(enabled: bool) {
+    EVAL_CACHE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn eval_cache_enabled() -> bool {
+    EVAL_CACHE_ENABLED.load(Ordering::Relaxed)
+}
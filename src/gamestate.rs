@@ -0,0 +1,131 @@
+//! Serialized game record shared by `--state-file` and `--resume-game`.
+//!
+//! A game is exported as one JSON object: the current board, the moves in
+//! wire form (`f5`, `pass`, ...) and the result once the game is over.
+//! The file is rewritten after every move via a temp file and a rename,
+//! so a crash leaves either the previous or the new record on disk and
+//! loses at most one move. Resuming replays `moves` from the standard
+//! position rather than trusting the stored board, and refuses a record
+//! whose board disagrees with its own move list.
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{apply_moves, format_move, parse_move, Move, START_BLACK, START_WHITE};
+
+/// Bumped whenever the layout changes incompatibly; newer records are
+/// rejected rather than misread.
+pub const GAME_STATE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameState {
+    pub version: u32,
+    pub black: u64,
+    pub white: u64,
+    pub white_to_move: bool,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+    /// Multiplayer only: the server game and our colour, needed to rejoin.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Multiplayer only: set when the server's winner disagreed with the
+    /// finished board; `result` then holds the server's verdict.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_discrepancy: Option<String>,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self {
+            version: GAME_STATE_VERSION,
+            black: START_BLACK,
+            white: START_WHITE,
+            white_to_move: false,
+            moves: Vec::new(),
+            result: None,
+            game_id: None,
+            color: None,
+            result_discrepancy: None,
+        }
+    }
+}
+
+impl GameState {
+    /// Record `mv` and the position it led to.
+    pub fn record(&mut self, mv: Move, white: u64, black: u64, white_to_move: bool) {
+        self.moves.push(format_move(mv));
+        self.white = white;
+        self.black = black;
+        self.white_to_move = white_to_move;
+    }
+
+    /// Write to `path` atomically. A no-op for an empty path, so callers
+    /// can pass `--state-file` through unconditionally.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if path.is_empty() {
+            return Ok(());
+        }
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, serde_json::to_string(self)?)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Record the final score as `black_won`, `white_won` or `draw`
+    /// (the server's status names).
+    pub fn finish(&mut self, white: u64, black: u64) {
+        let (w, b) = (white.count_ones(), black.count_ones());
+        self.result = Some(
+            if b > w {
+                "black_won"
+            } else if w > b {
+                "white_won"
+            } else {
+                "draw"
+            }
+            .to_string(),
+        );
+    }
+
+    pub fn load(path: &str) -> Result<GameState, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let state: GameState =
+            serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        if state.version > GAME_STATE_VERSION {
+            return Err(format!(
+                "{}: state version {} is newer than supported {}",
+                path, state.version, GAME_STATE_VERSION
+            ));
+        }
+        Ok(state)
+    }
+
+    /// The squares played, in order. Passes are dropped (replays re-derive
+    /// them) and a resignation ends the list.
+    pub fn played_moves(&self) -> Result<Vec<u64>, String> {
+        let mut bits = Vec::with_capacity(self.moves.len());
+        for (i, m) in self.moves.iter().enumerate() {
+            match parse_move(m) {
+                Ok(Move::Play(bit)) => bits.push(bit),
+                Ok(Move::Pass) => {}
+                Ok(Move::Resign) => break,
+                Err(e) => return Err(format!("move #{}: {}", i + 1, e)),
+            }
+        }
+        Ok(bits)
+    }
+
+    /// Rebuild `(white, black, white_to_move)` by replaying `moves` from
+    /// the standard position. Passes are re-derived by the replay itself.
+    pub fn replay(&self) -> Result<(u64, u64, bool), String> {
+        let bits = self.played_moves()?;
+        let (white, black, white_to_move) =
+            apply_moves(START_WHITE, START_BLACK, false, &bits).map_err(|(i, e)| {
+                format!("played move #{} ({}): {}", i + 1, format_move(Move::Play(bits[i])), e)
+            })?;
+        if (white, black) != (self.white, self.black) {
+            return Err("stored board does not match the replayed moves".to_string());
+        }
+        Ok((white, black, white_to_move))
+    }
+}
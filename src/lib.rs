@@ -7,3 +7,8 @@ pub use multiplayer::model::*;
 
 pub mod cli;
 pub use cli::args::*;
+
+pub mod engine;
+pub mod evalcache;
+pub mod tt;
+pub mod utils;
@@ -2,29 +2,41 @@ use chrono;
 use clap::Parser;
 use rayon::prelude::*;
 use reversi_tools::position::*;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::path::Path;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 mod openingbook;
 use openingbook::*;
 
-mod tt;
-
-mod engine;
+use reversi_engine::{engine, evalcache, tt, utils};
 use engine::*;
-
-mod utils;
 use utils::*;
 
 mod tune;
 use tune::*;
 
+mod tournament;
+
+mod gamestate;
+use gamestate::GameState;
+
+mod timeman;
+use timeman::{TimeCurve, TimeManager};
+
 /// Parse a comma-separated coefficient string into an `EvalCfg`.
-/// Expected field order (10 ints):
+/// Expected field order (10 ints, optionally followed by the newer
+/// terms in the order they were added):
 ///   corner, edge, antiedge, anticorner,
 ///   disc_opening, disc_midgame, disc_endgame,
 ///   mobility_opening, mobility_midgame, mobility_endgame
+///   [, potential_mobility [, parity, parity_empties [, frontier [, stable [, tempo]]]]]
+///
+/// Omitted trailing terms keep their `DEFAULT_CFG` values, so strings
+/// written before a term existed still mean the same config.
 ///
 /// Empty input (the CLI default) yields `DEFAULT_CFG`; unparseable
 /// input falls back to `DEFAULT_CFG` with a stderr note so the
@@ -34,32 +46,26 @@ fn parse_coefs_or_default(s: &str) -> EvalCfg {
         return DEFAULT_CFG;
     }
     let parts: Vec<&str> = s.split(',').collect();
-    if parts.len() != 10 {
+    if parts.len() < 10 || parts.len() > TUNE_DIM {
         eprintln!(
-            "parse_coefs: expected 10 comma-separated ints (corner,edge,antiedge,anticorner,disc_opening,disc_midgame,disc_endgame,mobility_opening,mobility_midgame,mobility_endgame), got {} parts in {:?}; using DEFAULT_CFG",
+            "parse_coefs: expected 10 to {} comma-separated ints (corner,edge,antiedge,anticorner,disc_opening,disc_midgame,disc_endgame,mobility_opening,mobility_midgame,mobility_endgame[,potential_mobility[,parity,parity_empties[,frontier[,stable[,tempo]]]]]), got {} parts in {:?}; using DEFAULT_CFG",
+            TUNE_DIM,
             parts.len(),
             s
         );
         return DEFAULT_CFG;
     }
-    let mut vals = [0i32; 10];
+    let mut vals = cfg_to_vec(&DEFAULT_CFG);
     for (i, p) in parts.iter().enumerate() {
         match p.trim().parse::<i32>() {
-            Ok(v) => vals[i] = v,
+            Ok(v) => vals[i] = v as f64,
             Err(_) => {
                 eprintln!("parse_coefs: non-integer element {:?} in {:?}; using DEFAULT_CFG", p, s);
                 return DEFAULT_CFG;
             }
         }
     }
-    EvalCfg {
-        corner_value: vals[0],
-        edge_value: vals[1],
-        antiedge_value: vals[2],
-        anticorner_value: vals[3],
-        disc_values: [vals[4], vals[5], vals[6]],
-        mobility_values: [vals[7], vals[8], vals[9]],
-    }
+    vec_to_cfg(&vals)
 }
 
 #[cfg(feature = "multiplayer")]
@@ -72,16 +78,41 @@ use reversi_engine::cli::args::*;
 #[cfg(feature = "multiplayer")]
 use std::{thread, time};
 
+/// Breadth-first opening book generation. The BFS is rooted at the
+/// standard position or, when `opening_line` is a non-empty transcript,
+/// at the position it leads to - `full_depth`/`partial_depth` then count
+/// plies from there. Positions already in the book at `save_path` are
+/// not re-searched but their stored main line is still followed, so an
+/// interrupted run picks up where it stopped when restarted.
+///
+/// Each time a position is reached again in the same run, through a
+/// different move order, its stored best move's weight goes up by one,
+/// so the weights count how many book lines lead through a move.
+///
+/// Beyond `full_depth` only the best move is expanded, unless
+/// `book_margin` is positive: then every move whose eval is within that
+/// margin of the best is expanded as well. Alternatives of positions
+/// already in the book are not recovered on a resumed run, since only
+/// the best move is stored.
 fn generate_opening_book(
     calculation_depth: u32,
     full_depth: u32,
     partial_depth: u32,
     save_path: &str,
+    opening_line: &str,
+    book_margin: i32,
+    format: BookFormat,
+    save_interval: u32,
 ) {
     println!("Generating opening book;calc depth: {}, full search depth: {}, partial search depth: {}, path: {}", calculation_depth, full_depth, partial_depth, save_path);
-    let black = 0x0000000810000000u64;
-    let white = 0x0000001008000000u64;
-    let white_to_move: bool = false;
+    let (white, black, white_to_move) =
+        match parse_transcript(opening_line).and_then(|moves| replay_transcript(&moves)) {
+            Ok(p) => p,
+            Err(e) => {
+                println!("Cannot seed book generation from {:?}: {}", opening_line, e);
+                return;
+            }
+        };
     let mut queue: Vec<Position> = Vec::new();
     let mut book: OpeningBook;
     if Path::new(save_path).exists() {
@@ -89,12 +120,45 @@ fn generate_opening_book(
     } else {
         book = OpeningBook::default();
     }
+    if book.meta.created.is_empty() {
+        book.meta.created = chrono::offset::Local::now().to_rfc3339();
+    }
+    book.meta.search_depth = calculation_depth;
+    book.meta.full_depth = full_depth;
+    book.meta.partial_depth = partial_depth;
+    // Positions from a run that stopped between saves.
+    let log_path = BookLog::path_for(save_path);
+    match book.replay_log(&log_path) {
+        Ok(0) => {}
+        Ok(n) => println!("Replayed {} logged positions from {}", n, log_path),
+        Err(e) => {
+            println!("Cannot replay {}: {}", log_path, e);
+            return;
+        }
+    }
+    let mut log = match BookLog::open(&log_path) {
+        Ok(log) => log,
+        Err(e) => {
+            println!("Cannot open {}: {}", log_path, e);
+            return;
+        }
+    };
+    let mut unsaved = 0;
+    // Canonical positions reached in this run; reaching one again through
+    // another move order counts as a visit of its stored best move.
+    let mut seen: HashSet<Position> = HashSet::new();
 
     let starting_pos: Position = Position {
         black: black,
         white: white,
         white_to_move: white_to_move,
     };
+    if !opening_line.is_empty() {
+        println!(
+            "Seeding generation from line {} (b {} w {} wtm: {})",
+            opening_line, black, white, white_to_move
+        );
+    }
     queue.push(starting_pos);
     for depth in 0..partial_depth {
         let mut next_queue: Vec<Position> = Vec::new();
@@ -112,55 +176,138 @@ fn generate_opening_book(
                 pos.white,
                 pos.white_to_move
             );
-            let cached_result = book.get(&pos);
+            let cached_result = book.get_canonical(&pos);
+            let first_visit = seen.insert(canonical_position(&pos));
             match cached_result {
-                Some(_) => {
+                Some(entry) => {
                     println!("{:?} Cached position found!", chrono::offset::Local::now());
+                    let visited = entry.suggested_moves.first().map(|m| m.mv);
+                    if let Some(key) = visited
+                        .filter(|_| !first_visit)
+                        .and_then(|mv| book.record_visit(&pos, mv))
+                    {
+                        if let Err(e) = log.append(&key, book.get(&key).unwrap()) {
+                            println!("Error while appending to {}: {}", log_path, e);
+                        }
+                    }
+                    if depth >= full_depth {
+                        // Keep following the stored main line so a resumed
+                        // run reaches the positions it hadn't finished.
+                        let cached_move = entry.suggested_moves.first().map(|m| m.mv);
+                        if let Some(Ok((w, b))) = cached_move
+                            .map(|mv| apply_move(pos.white, pos.black, mv, pos.white_to_move))
+                        {
+                            next_queue.push(Position {
+                                black: b,
+                                white: w,
+                                white_to_move: !pos.white_to_move,
+                            });
+                        }
+                    }
                 }
                 None => {
                     println!(
                         "{:?} Position absent from cache",
                         chrono::offset::Local::now()
                     );
-                    let (best_move, _) = search_moves_par(
-                        pos.white,
-                        pos.black,
-                        pos.white_to_move,
-                        calculation_depth,
-                        -20000,
-                        20000,
-                        calculation_depth,
-                        DEFAULT_CFG,
-                    );
+                    // Beyond `full_depth` with a margin, every root move is
+                    // scored so close alternatives can be expanded too.
+                    let (best_move, best_eval, expand) = if book_margin > 0
+                        && depth >= full_depth
+                    {
+                        let scored: Vec<(u64, i32)> = search_root_moves(
+                            pos.white,
+                            pos.black,
+                            pos.white_to_move,
+                            calculation_depth,
+                            DEFAULT_CFG,
+                        )
+                        .into_iter()
+                        .map(|(mv, eval)| (mv, eval_for_color(eval, pos.white_to_move)))
+                        .collect();
+                        let best = scored
+                            .iter()
+                            .copied()
+                            .reduce(|best, x| if x.1 > best.1 { x } else { best });
+                        match best {
+                            Some((best_move, best_eval)) => (
+                                best_move,
+                                eval_for_color(best_eval, pos.white_to_move),
+                                scored
+                                    .iter()
+                                    .filter(|&&(_, eval)| eval >= best_eval - book_margin)
+                                    .map(|&(mv, _)| mv)
+                                    .collect(),
+                            ),
+                            None => (u64::MAX, 0, Vec::new()),
+                        }
+                    } else {
+                        let (best_move, best_eval) = search_moves_par(
+                            pos.white,
+                            pos.black,
+                            pos.white_to_move,
+                            calculation_depth,
+                            MIN_EVAL,
+                            MAX_EVAL,
+                            calculation_depth,
+                            DEFAULT_CFG,
+                        );
+                        (best_move, best_eval, vec![best_move])
+                    };
                     println!(
                         "{:?} Best move found: {}",
                         chrono::offset::Local::now(),
                         best_move
                     );
-                    book.insert_all_rotations(pos, best_move);
+                    book.insert_canonical(pos, best_move, best_eval);
+                    // A search that reached the end of the game has proven
+                    // the result: it only sees won/lost scores and draws.
+                    // Pass positions are skipped since the root pass path
+                    // returns a static eval.
+                    let empties = (!(pos.white | pos.black)).count_ones();
+                    if empties <= calculation_depth
+                        && best_move != u64::MAX
+                        && endgame_selectivity() == 0
+                    {
+                        let outcome = match best_eval.signum() {
+                            1 => GameStatus::BlackWon,
+                            -1 => GameStatus::WhiteWon,
+                            _ => GameStatus::Draw,
+                        };
+                        println!("{:?} Solved: {:?}", chrono::offset::Local::now(), outcome);
+                        book.set_outcome(&pos, outcome);
+                    }
+                    let canonical = canonical_position(&pos);
+                    if let Some(entry) = book.get(&canonical) {
+                        if let Err(e) = log.append(&canonical, entry) {
+                            println!("Error while appending to {}: {}", log_path, e);
+                        }
+                    }
+                    unsaved += 1;
                     if depth >= full_depth {
                         println!(
-                            "{:?} Inserting move for partial search",
-                            chrono::offset::Local::now()
+                            "{:?} Inserting {} move(s) for partial search",
+                            chrono::offset::Local::now(),
+                            expand.len()
                         );
-                        let new_pos_opt =
-                            apply_move(pos.white, pos.black, best_move, pos.white_to_move);
-                        match new_pos_opt {
-                            Ok((w, b)) => {
+                        for mv in expand {
+                            if let Ok((w, b)) =
+                                apply_move(pos.white, pos.black, mv, pos.white_to_move)
+                            {
                                 next_queue.push(Position {
                                     black: b,
                                     white: w,
                                     white_to_move: !pos.white_to_move,
                                 });
                             }
-                            Err(_) => {
-                                //println!("Move error: {}", s);
-                                continue;
-                            }
                         }
                     }
                 }
             }
+            if unsaved >= save_interval.max(1) {
+                compact_book(&book, &mut log, save_path, format);
+                unsaved = 0;
+            }
             let next_moves = find_legal_moves_alt(pos.white, pos.black, pos.white_to_move);
             if depth >= full_depth {
                 continue;
@@ -185,28 +332,37 @@ fn generate_opening_book(
                     }
                 }
             }
-            let write_res = book.save_to_file(save_path);
-            match write_res {
-                Ok(_) => {}
-                Err(e) => {
-                    println!("Error while saving to file: {}", e);
-                }
-            }
         }
         queue = next_queue;
     }
-    let write_res = book.save_to_file(save_path);
-    match write_res {
-        Ok(_) => {}
+    if compact_book(&book, &mut log, save_path, format) {
+        drop(log);
+        let _ = std::fs::remove_file(&log_path);
+    }
+}
+
+/// Save the book being generated and, once it is written, clear the log
+/// of the changes it now holds. Returns whether both succeeded.
+fn compact_book(
+    book: &OpeningBook,
+    log: &mut BookLog,
+    save_path: &str,
+    format: BookFormat,
+) -> bool {
+    match book.save(save_path, format).and_then(|_| log.clear()) {
+        Ok(_) => true,
         Err(e) => {
             println!("Error while saving to file: {}", e);
+            false
         }
     }
 }
 
 fn evaluate_position(depth: u32, pos: Position) -> u64 {
     // Clear the TT so each position is measured from a cold state; this
-    // makes the benchmark a faithful per-position comparison.
+    // makes the benchmark a faithful per-position comparison. The eval
+    // cache is cleared for the same reason; its hit counters are summed
+    // in `benchmark` before the next clear.
     tt::tt().clear();
     let mut counter: u64 = 0;
     search_iterative_cntr(
@@ -224,6 +380,8 @@ fn play_game_from_position(first: EvalCfg, second: EvalCfg, depth: u32, pos: Pos
     let mut white = pos.white;
     let mut black = pos.black;
     let mut white_to_move = pos.white_to_move;
+    let start = (pos.white, pos.black, pos.white_to_move);
+    let mut history: Vec<u64> = Vec::new();
     const BLACK_WON: u64 = u64::MAX - 1;
     const WHITE_WON: u64 = u64::MAX - 2;
     const DRAWN_GAME: u64 = u64::MAX - 3;
@@ -248,23 +406,46 @@ fn play_game_from_position(first: EvalCfg, second: EvalCfg, depth: u32, pos: Pos
                 } else {
                     curr_cfg = first;
                 }
+                let depth = effective_depth(white, black, depth);
                 let (best_move, _) = search_moves_opt(
                     white,
                     black,
                     white_to_move,
                     depth,
-                    -20000,
-                    20000,
+                    MIN_EVAL,
+                    MAX_EVAL,
                     depth,
                     curr_cfg,
                 );
+                let discs = (white | black).count_ones();
                 match apply_move(white, black, best_move, white_to_move) {
                     Ok((w, b)) => {
+                        history.push(best_move);
+                        if (w | b).count_ones() <= discs {
+                            report_stuck_game(
+                                "disc count did not increase",
+                                start,
+                                &history,
+                                w,
+                                b,
+                                !white_to_move,
+                            );
+                            return 0;
+                        }
                         white = w;
                         black = b;
                         white_to_move = !white_to_move;
                     }
                     Err(_) => {
+                        history.push(best_move);
+                        report_stuck_game(
+                            "engine chose an illegal move",
+                            start,
+                            &history,
+                            white,
+                            black,
+                            white_to_move,
+                        );
                         return 0;
                     }
                 }
@@ -274,52 +455,8 @@ fn play_game_from_position(first: EvalCfg, second: EvalCfg, depth: u32, pos: Pos
 }
 
 fn compare_configs(first: EvalCfg, second: EvalCfg, depth: u32) -> i32 {
-    // Generate all positions with a depth of 6 plies
-    let black = 0x0000000810000000u64;
-    let white = 0x0000001008000000u64;
-    let white_to_move: bool = false;
-    let starting_pos: Position = Position {
-        black: black,
-        white: white,
-        white_to_move: white_to_move,
-    };
-    let mut queue: Vec<Position> = Vec::new();
-    let mut dedup_cache: HashMap<Position, bool> = HashMap::new();
-    queue.push(starting_pos);
-    for _ in 0..6 {
-        let mut next_queue: Vec<Position> = Vec::new();
-        for pos in queue {
-            if dedup_cache.contains_key(&pos) {
-                continue;
-            }
-            let next_moves = find_legal_moves_alt(pos.white, pos.black, pos.white_to_move);
-            for next_move in next_moves {
-                let new_pos_opt = apply_move(pos.white, pos.black, next_move, pos.white_to_move);
-                match new_pos_opt {
-                    Ok((w, b)) => {
-                        let new_pos: Position = Position {
-                            black: b,
-                            white: w,
-                            white_to_move: !pos.white_to_move,
-                        };
-                        let mut p = pos.clone();
-                        for _ in 0..4 {
-                            dedup_cache.insert(p, true);
-                            dedup_cache.insert(flip_position_vertical(&p), true);
-                            dedup_cache.insert(flip_position_horizontal(&p), true);
-                            p = rotate_position_90(&p);
-                        }
-                        next_queue.push(new_pos);
-                    }
-                    Err(_) => {
-                        //println!("Move error: {}", s);
-                        continue;
-                    }
-                }
-            }
-        }
-        queue = next_queue;
-    }
+    // All positions reachable in 6 plies, symmetry-reduced
+    let queue = generate_ply_positions(6);
     println!("Comparing engines over {} positions", queue.len());
     let outcome = queue
         .into_par_iter()
@@ -11920,18 +12057,58 @@ fn benchmark(depth: u32) -> i32 {
         queue.len()
     );
     let mut total: u64 = 0;
+    let mut cache_probes: u64 = 0;
+    let mut cache_hits: u64 = 0;
+    reset_cutoff_stats();
     let now = SystemTime::now();
     for pos in queue {
+        evalcache::eval_cache().clear();
         total += evaluate_position(depth, pos);
+        let (probes, hits) = evalcache::eval_cache().stats();
+        cache_probes += probes;
+        cache_hits += hits;
     }
     println!(
         "Evaluated {} nodes over {} ms",
         total,
         now.elapsed().unwrap().as_millis()
     );
+    if evalcache::eval_cache_enabled() {
+        println!(
+            "Eval cache: {} hits / {} probes ({:.1}%)",
+            cache_hits,
+            cache_probes,
+            100.0 * cache_hits as f64 / cache_probes.max(1) as f64
+        );
+    }
+    print_cutoff_stats(&cutoff_stats());
     return 0;
 }
 
+/// Print the distribution of the rank (0-based position in the move
+/// order) of the move that caused each beta cutoff.
+fn print_cutoff_stats(ranks: &[u64; CUTOFF_RANKS]) {
+    let cutoffs: u64 = ranks.iter().sum();
+    println!(
+        "Beta cutoffs: {} (first-move cutoff rate {:.1}%)",
+        cutoffs,
+        100.0 * ranks[0] as f64 / cutoffs.max(1) as f64
+    );
+    for (rank, n) in ranks.iter().enumerate() {
+        let label = if rank == CUTOFF_RANKS - 1 {
+            format!("{}+", rank + 1)
+        } else {
+            format!("{}", rank + 1)
+        };
+        println!(
+            "  move {:>3}: {:>12} ({:.1}%)",
+            label,
+            n,
+            100.0 * *n as f64 / cutoffs.max(1) as f64
+        );
+    }
+}
+
 /// Roll `pos` forward until it has at most `target_empties` empty squares,
 /// playing the best move at `rollout_depth` on each side. Returns `None`
 /// if the game ends before reaching the target (unlikely for reasonable
@@ -11970,8 +12147,8 @@ fn roll_forward_to_empties(
                     black,
                     white_to_move,
                     rollout_depth,
-                    -20000,
-                    20000,
+                    MIN_EVAL,
+                    MAX_EVAL,
                     rollout_depth,
                     DEFAULT_CFG,
                 );
@@ -12016,17 +12193,224 @@ fn benchmark_endgame(depth: u32, max_positions: usize, target_empties: u32) -> i
     );
     let mut total: u64 = 0;
     let now = SystemTime::now();
-    for pos in rolled {
-        total += evaluate_position(depth, pos);
+    for pos in &rolled {
+        total += evaluate_position(depth, *pos);
     }
     println!(
         "Evaluated {} nodes over {} ms",
         total,
         now.elapsed().unwrap().as_millis()
     );
+    let level = endgame_selectivity();
+    if level > 0 {
+        compare_selectivity(depth, &rolled, level);
+    }
+    0
+}
+
+/// Solve every position exactly and at `level`, each from a cold TT, and
+/// report the node savings and how often the selective search got the
+/// game-theoretic result (win/draw/loss for the side to move) wrong.
+fn compare_selectivity(depth: u32, positions: &[Position], level: u8) {
+    let mut exact_nodes: u64 = 0;
+    let mut selective_nodes: u64 = 0;
+    let mut wrong = 0;
+    for pos in positions {
+        set_endgame_selectivity(0);
+        let mut exact_cnt: u64 = 0;
+        tt::tt().clear();
+        let (_, exact) = search_iterative_cntr(
+            pos.white,
+            pos.black,
+            pos.white_to_move,
+            depth,
+            DEFAULT_CFG,
+            &mut exact_cnt,
+        );
+        set_endgame_selectivity(level);
+        let mut selective_cnt: u64 = 0;
+        tt::tt().clear();
+        let (_, selective) = search_iterative_cntr(
+            pos.white,
+            pos.black,
+            pos.white_to_move,
+            depth,
+            DEFAULT_CFG,
+            &mut selective_cnt,
+        );
+        exact_nodes += exact_cnt;
+        selective_nodes += selective_cnt;
+        if exact.signum() != selective.signum() {
+            wrong += 1;
+        }
+    }
+    tt::tt().clear();
+    println!(
+        "Selectivity {}: {} nodes vs {} exact ({:.1}%), {}/{} results differ from exact",
+        level,
+        selective_nodes,
+        exact_nodes,
+        100.0 * selective_nodes as f64 / exact_nodes.max(1) as f64,
+        wrong,
+        positions.len()
+    );
+}
+
+const MOVEGEN_BENCH_PLY: u32 = 6;
+const MOVEGEN_BENCH_ROUNDS: u32 = 2000;
+
+/// `--benchmark-movegen`: move generation throughput over the positions
+/// reachable in `MOVEGEN_BENCH_PLY` plies, comparing the allocating
+/// `find_legal_moves_alt`, the `legal_moves` iterator and popping bits
+/// off the `compute_moves` mask by hand.
+fn benchmark_movegen() -> i32 {
+    let corpus = generate_ply_positions(MOVEGEN_BENCH_PLY);
+    println!(
+        "Move generation over {} positions x {} rounds",
+        corpus.len(),
+        MOVEGEN_BENCH_ROUNDS
+    );
+    let time = |name: &str, generate: &dyn Fn(&Position) -> u64| {
+        let start = Instant::now();
+        let mut moves: u64 = 0;
+        for _ in 0..MOVEGEN_BENCH_ROUNDS {
+            for pos in &corpus {
+                moves += std::hint::black_box(generate(std::hint::black_box(pos)));
+            }
+        }
+        let secs = start.elapsed().as_secs_f64();
+        println!(
+            "{:>22}: {} moves in {:.3} s ({:.1} M moves/s)",
+            name,
+            moves,
+            secs,
+            moves as f64 / secs.max(1e-9) / 1e6
+        );
+    };
+    time("find_legal_moves_alt", &|p| {
+        find_legal_moves_alt(p.white, p.black, p.white_to_move)
+            .into_iter()
+            .fold(0, |n, mv| n + (mv != 0) as u64)
+    });
+    time("legal_moves", &|p| {
+        legal_moves(p.white, p.black, p.white_to_move).fold(0, |n, mv| n + (mv != 0) as u64)
+    });
+    time("mask iteration", &|p| {
+        let (me, opp) = if p.white_to_move {
+            (p.white, p.black)
+        } else {
+            (p.black, p.white)
+        };
+        let mut mask = compute_moves(me, opp);
+        let mut n = 0;
+        while mask != 0 {
+            let mv = mask & mask.wrapping_neg();
+            n += (mv != 0) as u64;
+            mask ^= mv;
+        }
+        n
+    });
     0
 }
 
+const DETERMINISM_RUNS: usize = 3;
+const DETERMINISM_POSITIONS: usize = 8;
+
+/// `--verify-determinism`: search each position `DETERMINISM_RUNS` times
+/// from a cold TT and eval cache and check every run returns the same
+/// move and eval. Serial searches must also agree on the node count and
+/// any mismatch fails the check. The rayon root search is run the same
+/// way; it settles root ties deterministically, but threads racing on
+/// the shared TT can still shift a score, so its mismatches are only
+/// reported. Returns the process exit code.
+fn verify_determinism(spec: &str, depth: u32) -> i32 {
+    let positions: Vec<Position> = if spec.is_empty() {
+        benchmark_positions()
+            .into_iter()
+            .take(DETERMINISM_POSITIONS)
+            .collect()
+    } else {
+        match Position::parse(spec) {
+            Ok(p) => vec![p],
+            Err(e) => {
+                println!("Cannot parse position {:?}: {}", spec, e);
+                return 2;
+            }
+        }
+    };
+    let cold = || {
+        tt::tt().clear();
+        evalcache::eval_cache().clear();
+    };
+    let mut serial_failures = 0;
+    let mut parallel_diffs = 0;
+    for pos in &positions {
+        let serial: Vec<(SearchMove, i32, u64)> = (0..DETERMINISM_RUNS)
+            .map(|_| {
+                cold();
+                let mut nodes: u64 = 0;
+                let (mv, eval) = search_iterative_cntr(
+                    pos.white,
+                    pos.black,
+                    pos.white_to_move,
+                    depth,
+                    DEFAULT_CFG,
+                    &mut nodes,
+                );
+                (SearchMove::resolve(pos.white, pos.black, pos.white_to_move, mv), eval, nodes)
+            })
+            .collect();
+        if serial.iter().any(|r| *r != serial[0]) {
+            serial_failures += 1;
+            print_determinism_diff("serial", pos, &serial);
+        }
+        let parallel: Vec<(SearchMove, i32, u64)> = (0..DETERMINISM_RUNS)
+            .map(|_| {
+                cold();
+                let (mv, eval) =
+                    search_iterative(pos.white, pos.black, pos.white_to_move, depth, DEFAULT_CFG);
+                (mv, eval, 0)
+            })
+            .collect();
+        if parallel.iter().any(|r| *r != parallel[0]) {
+            parallel_diffs += 1;
+            print_determinism_diff("parallel", pos, &parallel);
+        }
+    }
+    cold();
+    println!(
+        "Determinism over {} positions x {} runs at depth {}: {} serial failures, {} parallel mismatches",
+        positions.len(),
+        DETERMINISM_RUNS,
+        depth,
+        serial_failures,
+        parallel_diffs
+    );
+    if serial_failures > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn print_determinism_diff(mode: &str, pos: &Position, runs: &[(SearchMove, i32, u64)]) {
+    println!("{} search is not deterministic for {}:", mode, pos.to_key());
+    for (i, &(mv, eval, nodes)) in runs.iter().enumerate() {
+        println!(
+            "  run {}: move {} eval {} nodes {}{}",
+            i + 1,
+            match mv {
+                SearchMove::Play(bit) => format_move(Move::Play(bit)),
+                SearchMove::Pass => format_move(Move::Pass),
+                SearchMove::NoMove => "none".to_string(),
+            },
+            eval,
+            nodes,
+            if (mv, eval, nodes) != runs[0] { "  <- differs" } else { "" }
+        );
+    }
+}
+
 /// The fixed set of benchmark starting positions, extracted so the
 /// endgame-rollout benchmark can reuse it without duplicating ~11k lines
 /// of position literals.
@@ -12036,10 +12420,186 @@ fn benchmark_positions() -> Vec<Position> {
     out
 }
 
+/// The book used by the game loops: the one at `--book-path`, or an
+/// empty book (so no lookup can hit) without a path or with `--no-book`.
+fn load_game_book(args: &Args) -> OpeningBook {
+    if args.no_book || args.book_path.is_empty() {
+        OpeningBook::default()
+    } else {
+        let mut book = OpeningBook::load_from_file(args.book_path.as_str()).unwrap();
+        let total = book.entries.len();
+        if book.prune(args.book_min_weight) == total && total > 0 {
+            println!(
+                "No book position reaches weight {}; playing without the book",
+                args.book_min_weight
+            );
+        }
+        book
+    }
+}
+
+/// `--prune-book`: drop the positions of the book at `path` below
+/// `min_weight` and write it back in `format`. A book that would be left
+/// empty is not written. Returns how many positions were removed and
+/// how many there were.
+fn prune_book_file(
+    path: &str,
+    min_weight: u32,
+    format: BookFormat,
+) -> Result<(usize, usize), String> {
+    let mut book = OpeningBook::load_from_file(path).map_err(|e| e.to_string())?;
+    let total = book.entries.len();
+    let removed = book.prune(min_weight);
+    if removed == total && total > 0 {
+        return Err(format!(
+            "none of {} positions reaches weight {}; leaving the book unchanged",
+            total, min_weight
+        ));
+    }
+    book.save(path, format).map_err(|e| format!("error while saving to file: {}", e))?;
+    Ok((removed, total))
+}
+
+/// Announce a book move, with the proven result if the book has one.
+fn report_book_hit(book: &OpeningBook, white: u64, black: u64, white_to_move: bool) {
+    let pos = Position {
+        black,
+        white,
+        white_to_move,
+    };
+    match book.outcome(&pos) {
+        Some(outcome) => println!("Book move found! Proven result: {:?}", outcome),
+        None => println!("Book move found!"),
+    }
+}
+
+/// Per-game tally of moves taken from the opening book vs. found by
+/// search, to judge how much of real play the book actually covers.
+#[derive(Default)]
+struct BookUsage {
+    book_moves: u32,
+    search_moves: u32,
+}
+
+impl BookUsage {
+    fn record(&mut self, from_book: bool) {
+        if from_book {
+            self.book_moves += 1;
+        } else {
+            self.search_moves += 1;
+        }
+    }
+
+    fn report(&self) {
+        let total = self.book_moves + self.search_moves;
+        println!(
+            "Book moves: {}, search moves: {}, book hit rate: {:.1}%",
+            self.book_moves,
+            self.search_moves,
+            100.0 * self.book_moves as f64 / total.max(1) as f64
+        );
+    }
+}
+
+/// `--randomize-opening`: seeded random picks among the book moves and,
+/// until the midgame, among the root moves within `margin` of the best,
+/// so games vary while a seed replays the same one.
+struct OpeningRandomizer {
+    rng: Rng64,
+    margin: i32,
+}
+
+impl OpeningRandomizer {
+    fn new(seed: u64, margin: i32) -> Self {
+        Self {
+            rng: Rng64::new(seed),
+            margin,
+        }
+    }
+
+    fn from_args(args: &Args) -> Option<Self> {
+        if args.randomize_opening {
+            Some(Self::new(args.random_seed, args.random_margin))
+        } else {
+            None
+        }
+    }
+
+    fn book_move(&mut self, entry: &BookEntry) -> Option<u64> {
+        entry.pick_move(&mut self.rng)
+    }
+
+    /// A near-best root move at `depth`; `None` past the opening or when
+    /// the side to move has no move, leaving those to the normal search.
+    fn search_move(
+        &mut self,
+        white: u64,
+        black: u64,
+        white_to_move: bool,
+        depth: u32,
+    ) -> Option<(u64, i32)> {
+        if Phase::of(white, black) != Phase::Opening {
+            return None;
+        }
+        let scored = search_root_moves(white, black, white_to_move, depth, play_cfg());
+        pick_within_margin(&scored, white_to_move, self.margin, &mut self.rng)
+    }
+}
+
+/// The book's move for a position: its first suggestion, or one drawn by
+/// weight with `--randomize-opening`, resolved like a search's. `None`
+/// leaves the position to the search: no entry, an entry without moves,
+/// or a move that isn't legal here (see `--validate-book`).
+fn book_move(
+    book: &OpeningBook,
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    random: &mut Option<OpeningRandomizer>,
+) -> Option<SearchMove> {
+    let entry = book.get_canonical(&Position {
+        black,
+        white,
+        white_to_move,
+    })?;
+    let mv = match random {
+        Some(r) => r.book_move(&entry),
+        None => entry.suggested_moves.first().map(|m| m.mv),
+    }?;
+    match SearchMove::resolve(white, black, white_to_move, mv) {
+        SearchMove::NoMove => {
+            println!("Book move {} is not legal here, searching instead", mv);
+            None
+        }
+        resolved => {
+            report_book_hit(book, white, black, white_to_move);
+            Some(resolved)
+        }
+    }
+}
+
 fn local_game(args: Args) {
     let mut black = 0x0000000810000000u64;
     let mut white = 0x0000001008000000u64;
     let mut white_to_move: bool = false;
+    let mut state = GameState::default();
+    if !args.resume_game.is_empty() {
+        match GameState::load(&args.resume_game).and_then(|s| s.replay().map(|p| (s, p))) {
+            Ok((s, p)) => {
+                println!("Resuming game after {} moves", s.moves.len());
+                (white, black, white_to_move) = p;
+                state = s;
+            }
+            Err(e) => {
+                println!("Cannot resume game: {}", e);
+                return;
+            }
+        }
+        if let Some(result) = &state.result {
+            println!("Game is already over: {}", result);
+            return;
+        }
+    }
 
     // Ply: 51, Is white: false, Move: a8, Eval: 991, Black pos: 33909430323788925, White pos: 4325574457067520514
     // Ply: 9, Is white: false, Move: h7, Eval: 999, Black pos: 4713330624348249857, White pos: 4474012615487561982
@@ -12049,130 +12609,623 @@ fn local_game(args: Args) {
     /*let mut black: u64 = 120795966464;
     let mut white: u64 = 36310151199708159;
     let mut white_to_move: bool = false;*/
-    let book: OpeningBook;
-    if args.book_path.is_empty() {
-        book = OpeningBook::default();
-    } else {
-        book = OpeningBook::load_from_file(args.book_path.as_str()).unwrap();
-    }
+    let book = load_game_book(&args);
 
     print_board(white, black, 0, 0, false);
     //let default_depth: u32 = args.search_depth;
-    let mut ply = 0;
+    let mut ply = state.moves.len();
+    let mut book_usage = BookUsage::default();
+    let mut random = OpeningRandomizer::from_args(&args);
+    // Black's and White's clocks when playing to a time budget.
+    let mut clocks = [game_clock(&args), game_clock(&args)];
     loop {
         ply += 1;
-        let nxt_move: u64;
+        let nxt_move: SearchMove;
         let eval: i32;
+        let mut from_book = false;
         if !white_to_move {
-            let next_move_opt = book.get(&Position {
-                black: black,
-                white: white,
-                white_to_move: white_to_move,
-            });
-            match next_move_opt {
-                Some(m) => {
-                    println!("Book move found!");
-                    nxt_move = m.suggested_moves[0];
+            match book_move(&book, white, black, white_to_move, &mut random) {
+                Some(mv) => {
+                    nxt_move = mv;
                     eval = 0;
+                    from_book = true;
                 }
                 None => {
-                    (nxt_move, eval) = search_iterative(
+                    (nxt_move, eval) = engine_move(
+                        &mut clocks[0],
                         white,
                         black,
                         white_to_move,
                         args.search_depth,
-                        DEFAULT_CFG,
+                        args.search_stats,
+                        args.move_time_limit,
+                        &mut random,
                     );
-                    if nxt_move == 0 {
-                        println!("NO MOVES!");
-                        break;
-                    }
                 }
             }
         } else {
-            (nxt_move, eval) = search_iterative(
+            (nxt_move, eval) = engine_move(
+                &mut clocks[1],
                 white,
                 black,
                 white_to_move,
                 args.search_depth,
-                DEFAULT_CFG,
+                args.search_stats,
+                args.move_time_limit,
+                &mut random,
             );
-            if nxt_move == 0 {
-                println!("NO MOVES!");
-                break;
-            }
         }
-        if nxt_move != u64::MAX {
+        if nxt_move == SearchMove::NoMove {
+            println!("NO MOVES!");
+            break;
+        }
+        if let SearchMove::Play(nxt_move) = nxt_move {
+            book_usage.record(from_book);
             println!(
-                "Ply: {}, Is white: {}, Move: {}, Eval: {}, Black pos: {}, White pos: {}",
+                "Ply: {}, Is white: {}, Move: {}, Eval: {} (black wins {:.1}%), Black pos: {}, White pos: {}",
                 ply,
                 white_to_move,
                 move_to_algebraic(nxt_move).unwrap(),
                 eval,
+                100.0 * win_probability(eval, Phase::of(white, black)),
                 black,
                 white
             );
+            if !from_book {
+                print_principal_variation(white, black, white_to_move, nxt_move);
+            }
+            if let Some(moves) = mate_distance(eval) {
+                let winner = if eval > 0 { "Black" } else { "White" };
+                println!("{} wins by force in {} moves", winner, moves);
+            }
             let (new_white, new_black) =
                 apply_move_verbose(white, black, nxt_move, white_to_move).unwrap();
             //println!("WWW {} {} {}", new_white, new_black, white_to_move);
+            state.record(Move::Play(nxt_move), new_white, new_black, !white_to_move);
             let game_status = check_game_status(new_white, new_black, !white_to_move);
             if game_status == u64::MAX || game_status < (u64::MAX - 3) {
+                save_game_state(&state, &args.state_file);
                 black = new_black;
                 white = new_white;
                 white_to_move = !white_to_move;
             } else {
-                let black_score = new_black.count_ones();
-                let white_score = new_white.count_ones();
-                println!("Black score: {}, white score: {}", black_score, white_score);
-                if game_status == 1 {
-                    println!("White won b {} w {}", new_black, new_white);
-                } else if game_status == 2 {
-                    println!("Black won b {} w {}", new_black, new_white);
-                } else if game_status == 0 {
-                    println!("Draw b {} w {}", new_black, new_white);
-                }
+                state.finish(new_white, new_black);
+                save_game_state(&state, &args.state_file);
+                println!("{}", final_result(new_white, new_black, game_status));
+                println!("b {} w {}", new_black, new_white);
                 break;
             }
         } else {
             println!("Is white: {}; PASS", white_to_move);
             white_to_move = !white_to_move;
+            state.record(Move::Pass, white, black, white_to_move);
+            save_game_state(&state, &args.state_file);
+        }
+    }
+    book_usage.report();
+    if args.replay_speed > 0 {
+        if let Err(e) = state.played_moves().and_then(|m| animate_replay(&m, args.replay_speed)) {
+            println!("Cannot replay game: {}", e);
         }
     }
 }
 
-#[cfg(feature = "multiplayer")]
-fn play_multiplayer(args: Args) {
-    println!(
-        "{} {} {} {}",
-        args.api_url, args.search_depth, args.book_path, args.player_uuid
-    );
-    let games: Vec<String>;
-    loop {
-        match find_games_to_join(&args) {
-            Ok(g) => {
-                games = g;
-                break;
-            }
-            Err(e) => {
-                println!("Failed to retrieve game list, retrying: {}", e);
-                thread::sleep(time::Duration::from_millis(1000));
+const DEFAULT_REPLAY_MS: u64 = 500;
+
+/// `--replay`: animate a game given as a transcript or as a
+/// `--state-file` JSON file. Returns the process exit code.
+fn replay_game(spec: &str, delay_ms: u64) -> i32 {
+    let moves = if Path::new(spec).exists() {
+        GameState::load(spec).and_then(|s| s.played_moves())
+    } else {
+        parse_transcript(spec)
+    };
+    match moves.and_then(|m| animate_replay(&m, delay_ms)) {
+        Ok(()) => 0,
+        Err(e) => {
+            println!("Cannot replay {:?}: {}", spec, e);
+            1
+        }
+    }
+}
+
+/// Persist `state` to `--state-file` (if set), reporting but otherwise
+/// ignoring failures so a full disk doesn't end the game.
+fn save_game_state(state: &GameState, path: &str) {
+    if let Err(e) = state.save(path) {
+        println!("Failed to save game state to {}: {}", path, e);
+    }
+}
+
+/// Per-side clock for `--time-budget`, or `None` to search to a fixed
+/// depth.
+fn game_clock(args: &Args) -> Option<TimeManager> {
+    if args.time_budget == 0 {
+        return None;
+    }
+    Some(TimeManager::new(
+        Duration::from_millis(args.time_budget),
+        TimeCurve {
+            midgame_weight: args.time_midgame_weight,
+            endgame_reserve: args.time_endgame_reserve,
+            solve_empties: args.time_solve_empties,
+        },
+    ))
+}
+
+/// Pick a move by search: iterative deepening to `depth` (as adjusted by
+/// `effective_depth`), or, with a clock, as deep as the clock's
+/// allocation for this move allows. With `show_stats` a fixed-depth
+/// search runs serially so its node counts can be reported; otherwise a
+/// non-zero `time_limit_ms` caps it. With `random`, opening moves are
+/// picked among the near-best instead (see `OpeningRandomizer`).
+fn engine_move(
+    clock: &mut Option<TimeManager>,
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    depth: u32,
+    show_stats: bool,
+    time_limit_ms: u64,
+    random: &mut Option<OpeningRandomizer>,
+) -> (SearchMove, i32) {
+    let start = Instant::now();
+    let randomized = random
+        .as_mut()
+        .and_then(|r| r.search_move(white, black, white_to_move, depth));
+    match randomized {
+        Some((mv, eval)) => {
+            if let Some(c) = clock {
+                c.spend(start.elapsed());
             }
+            (SearchMove::resolve(white, black, white_to_move, mv), eval)
+        }
+        None => {
+            engine_search(clock, white, black, white_to_move, depth, show_stats, time_limit_ms)
         }
     }
-    let mut my_game_uuid: String = String::new();
-    let mut my_color: String = String::new();
-    let mut opp_first_move: u64 = 0;
-    if games.len() == 0 {
-        println!("No games to join, creating one!");
-        let new_game: NewGameResult;
+}
+
+fn engine_search(
+    clock: &mut Option<TimeManager>,
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    depth: u32,
+    show_stats: bool,
+    time_limit_ms: u64,
+) -> (SearchMove, i32) {
+    let empties = (!(white | black)).count_ones();
+    let depth = effective_depth(white, black, depth);
+    let clock = match clock {
+        None if show_stats => {
+            let (mv, eval, stats) =
+                search_iterative_stats(white, black, white_to_move, depth, play_cfg());
+            println!(
+                "Search: depth {}, {} nodes, {} leaves, {} killer cutoffs, {} corner extensions, \
+                 {} ms, {} nps; TT {} probes, {} hits, {} collisions, hashfull {}",
+                stats.depth,
+                stats.nodes,
+                stats.leaves,
+                stats.killer_cutoffs,
+                stats.corner_extensions,
+                stats.elapsed.as_millis(),
+                stats.nps,
+                stats.tt_probes,
+                stats.tt_hits,
+                stats.tt_collisions,
+                stats.hashfull
+            );
+            return (mv, eval);
+        }
+        None if time_limit_ms > 0 => {
+            let limit = Duration::from_millis(time_limit_ms);
+            return search_with_limit(white, black, white_to_move, depth, limit);
+        }
+        None if mtdf_enabled() => {
+            return search_iterative_mtdf(white, black, white_to_move, depth, play_cfg())
+        }
+        None => return search_iterative(white, black, white_to_move, depth, play_cfg()),
+        Some(c) => c,
+    };
+    let budget = clock.allocate(empties);
+    let start = Instant::now();
+    let (mv, eval, reached) =
+        search_timed(white, black, white_to_move, budget, empties.max(1), play_cfg());
+    clock.spend(start.elapsed());
+    println!(
+        "Searched depth {} in {} ms (allocated {} ms, {} ms left)",
+        reached,
+        start.elapsed().as_millis(),
+        budget.as_millis(),
+        clock.remaining().as_millis()
+    );
+    (mv, eval)
+}
+
+/// The stoppable iterative search the game loops use: MTD(f) with
+/// `--mtdf`, aspiration windows otherwise.
+fn iterative_stop(
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    depth: u32,
+    cfg: EvalCfg,
+    stop: &AtomicBool,
+) -> (SearchMove, i32, u32) {
+    if mtdf_enabled() {
+        search_iterative_mtdf_stop(white, black, white_to_move, depth, cfg, stop)
+    } else {
+        search_iterative_stop(white, black, white_to_move, depth, cfg, stop)
+    }
+}
+
+/// Iterative deepening to `depth`, stopped by a watchdog thread if it is
+/// still running after `limit`.
+fn search_with_limit(
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    depth: u32,
+    limit: Duration,
+) -> (SearchMove, i32) {
+    let stop = AtomicBool::new(false);
+    let (done, finished) = std::sync::mpsc::channel::<()>();
+    let (mv, eval, reached) = std::thread::scope(|s| {
+        let stop = &stop;
+        s.spawn(move || {
+            if finished.recv_timeout(limit) == Err(RecvTimeoutError::Timeout) {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        let result = iterative_stop(white, black, white_to_move, depth, play_cfg(), stop);
+        drop(done);
+        result
+    });
+    if reached < depth {
+        println!("Stopped after {} ms at depth {}", limit.as_millis(), reached);
+    }
+    (mv, eval)
+}
+
+// `--ponder`: depth of the search that predicts the opponent's reply when
+// the book has none.
+#[cfg(feature = "multiplayer")]
+const PONDER_PREDICT_DEPTH: u32 = 4;
+
+/// `--ponder`: a search of the position after the opponent's expected
+/// reply, run on a background thread while we wait for the real one. It
+/// only warms the TT: when the reply arrives the search is stopped and
+/// its result dropped, and if the prediction was right our own search
+/// finds most of its tree already in the table.
+#[cfg(feature = "multiplayer")]
+struct Ponder {
+    predicted: u64,
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+#[cfg(feature = "multiplayer")]
+impl Ponder {
+    /// Predict the reply of the side to move, from the book or a shallow
+    /// search, and start searching the position after it at our `depth`.
+    /// `None` if that side has no move to predict.
+    fn start(
+        book: &OpeningBook,
+        white: u64,
+        black: u64,
+        white_to_move: bool,
+        depth: u32,
+    ) -> Option<Ponder> {
+        let legal = check_game_status(white, black, white_to_move);
+        if legal >= u64::MAX - 3 {
+            return None;
+        }
+        let pos = Position {
+            white,
+            black,
+            white_to_move,
+        };
+        let booked = book.get_canonical(&pos).and_then(|e| e.suggested_moves.first().map(|m| m.mv));
+        let predicted = match booked {
+            Some(mv) => mv,
+            None => {
+                let depth = effective_depth(white, black, PONDER_PREDICT_DEPTH);
+                search_moves_opt(
+                    white,
+                    black,
+                    white_to_move,
+                    depth,
+                    MIN_EVAL,
+                    MAX_EVAL,
+                    depth,
+                    play_cfg(),
+                )
+                .0
+            }
+        };
+        if legal & predicted == 0 {
+            return None;
+        }
+        let (new_white, new_black) = apply_move(white, black, predicted, white_to_move).ok()?;
+        let depth = effective_depth(new_white, new_black, depth);
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            iterative_stop(new_white, new_black, !white_to_move, depth, play_cfg(), &flag);
+        });
+        Some(Ponder {
+            predicted,
+            stop,
+            handle,
+        })
+    }
+
+    /// Stop the search once the opponent has played `actual` (0 for no
+    /// move); true if that was the predicted reply.
+    fn finish(self, actual: u64) -> bool {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.join();
+        actual == self.predicted
+    }
+}
+
+/// Per-game tally of correctly and wrongly predicted opponent replies.
+#[cfg(feature = "multiplayer")]
+#[derive(Default)]
+struct PonderUsage {
+    hits: u32,
+    misses: u32,
+}
+
+#[cfg(feature = "multiplayer")]
+impl PonderUsage {
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    fn report(&self) {
+        println!(
+            "Ponder hits: {}, misses: {}, hit rate: {:.1}%",
+            self.hits,
+            self.misses,
+            100.0 * self.hits as f64 / (self.hits + self.misses).max(1) as f64
+        );
+    }
+}
+
+/// Print the line the engine expects after playing `mv`, read back from
+/// the TT.
+fn print_principal_variation(white: u64, black: u64, white_to_move: bool, mv: u64) {
+    let pv = expected_line(white, black, white_to_move, mv, play_cfg(), tt::tt());
+    let line: Vec<String> = pv
+        .iter()
+        .map(|&m| move_to_algebraic(m).unwrap_or_default())
+        .collect();
+    println!("PV: {}", line.join(" "));
+}
+
+/// `mv` followed by the principal variation `table` holds for the
+/// position after it, probed with the `cfg` the search ran with (its
+/// entries are keyed by it). The parallel root isn't stored in the TT,
+/// so the walk starts from the position after `mv`.
+fn expected_line(
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+    mv: u64,
+    cfg: EvalCfg,
+    table: &tt::TranspositionTable,
+) -> Vec<u64> {
+    let (new_white, new_black) = match apply_move(white, black, mv, white_to_move) {
+        Ok(p) => p,
+        Err(_) => return Vec::new(),
+    };
+    let mut pv = vec![mv];
+    pv.extend(principal_variation(new_white, new_black, !white_to_move, cfg, table));
+    pv
+}
+
+/// Scriptable single-position search: parse `spec`, search it once at
+/// `depth` and print one JSON line with the best move and the eval (from
+/// Black's perspective). With `multipv` above 1 a `lines` array lists
+/// the `multipv` best moves with their own evals. Returns the process
+/// exit code.
+fn oneshot(
+    spec: &str,
+    depth: u32,
+    multipv: usize,
+    root_nodes: bool,
+    phased: Option<(EvalCfg, EvalCfg)>,
+    weights: Option<[i32; 64]>,
+    breakdown: bool,
+) -> i32 {
+    let pos = match Position::parse(spec) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("oneshot: cannot parse position {:?}: {}", spec, e);
+            println!("{}", serde_json::json!({ "ok": false, "error": e }));
+            return 2;
+        }
+    };
+    let status = check_game_status(pos.white, pos.black, pos.white_to_move);
+    let game_over = status != u64::MAX && status >= u64::MAX - 3;
+    let depth = effective_depth(pos.white, pos.black, depth);
+    let (best_move, eval) = search_moves_opt(
+        pos.white,
+        pos.black,
+        pos.white_to_move,
+        depth,
+        MIN_EVAL,
+        MAX_EVAL,
+        depth,
+        DEFAULT_CFG,
+    );
+    let mv = match SearchMove::resolve(pos.white, pos.black, pos.white_to_move, best_move) {
+        SearchMove::Play(m) => move_to_algebraic(m).unwrap_or_default().into(),
+        SearchMove::Pass => "pass".into(),
+        SearchMove::NoMove => serde_json::Value::Null,
+    };
+    let mut out = serde_json::json!({
+        "ok": true,
+        "position": pos.to_key(),
+        "white_to_move": pos.white_to_move,
+        "depth": depth,
+        "move": mv,
+        "eval": eval,
+        "black_win_probability": win_probability(eval, Phase::of(pos.white, pos.black)),
+        "game_over": game_over,
+    });
+    if multipv > 1 {
+        let lines: Vec<serde_json::Value> =
+            search_multipv(pos.white, pos.black, pos.white_to_move, depth, multipv, DEFAULT_CFG)
+                .into_iter()
+                .map(|(m, e)| {
+                    serde_json::json!({
+                        "move": move_to_algebraic(m).unwrap_or_default(),
+                        "eval": e,
+                    })
+                })
+                .collect();
+        out["lines"] = lines.into();
+    }
+    if root_nodes {
+        let (white, black, white_to_move) = (pos.white, pos.black, pos.white_to_move);
+        let (_, _, _, roots) =
+            search_moves_opt_root_nodes(white, black, white_to_move, depth, DEFAULT_CFG);
+        let roots: Vec<serde_json::Value> = roots
+            .into_iter()
+            .map(|(m, e, nodes)| {
+                serde_json::json!({
+                    "move": move_to_algebraic(m).unwrap_or_default(),
+                    "eval": e,
+                    "nodes": nodes,
+                })
+            })
+            .collect();
+        out["root_moves"] = roots.into();
+    }
+    if let Some((opening, endgame)) = phased {
+        let (white, black, white_to_move) = (pos.white, pos.black, pos.white_to_move);
+        out["phased_eval"] = eval_phased(white, black, white_to_move, opening, endgame).into();
+    }
+    if let Some(weights) = weights {
+        out["table_eval"] = eval_position_table(pos.white, pos.black, &weights).into();
+    }
+    if breakdown {
+        let terms = eval_breakdown(pos.white, pos.black, pos.white_to_move, DEFAULT_CFG);
+        out["eval_breakdown"] = serde_json::to_value(terms).unwrap();
+    }
+    println!("{}", out);
+    0
+}
+
+/// Multiplayer desync: the server reported something our local board
+/// says can't have happened. Logs the state needed to diagnose the
+/// mismatch. The server API only exposes the last move, not the board or
+/// the move list, so there is nothing to resync from: a move our board
+/// rejects ends the game for us rather than playing on from a board that
+/// may be wrong.
+#[cfg(feature = "multiplayer")]
+fn report_desync(reason: &str, white: u64, black: u64, white_to_move: bool) {
+    println!(
+        "DESYNC: {}; local state: black pos: {}, white pos: {}, white move: {}",
+        reason, black, white, white_to_move
+    );
+    print_board(white, black, 0, 0, false);
+}
+
+/// Game-end check of the server's `winner` against our board. The server
+/// is authoritative and its result is what gets recorded, but when a
+/// finished board says otherwise (e.g. the two sides score empty squares
+/// differently) both views are logged and the discrepancy is kept in the
+/// game state. A board that isn't finished - a resignation - can't be
+/// checked.
+#[cfg(feature = "multiplayer")]
+fn reconcile_winner(
+    server_winner: &str,
+    white: u64,
+    black: u64,
+    status: u64,
+    state: &mut GameState,
+) {
+    let server = server_winner.trim().to_ascii_lowercase();
+    let server = match server.trim_end_matches("_won") {
+        "tie" => "draw",
+        other => other,
+    };
+    let server_result = if server == "draw" {
+        server.to_string()
+    } else {
+        format!("{}_won", server)
+    };
+    let ours = match status {
+        s if s == u64::MAX - 1 => "black",
+        s if s == u64::MAX - 2 => "white",
+        s if s == u64::MAX - 3 => "draw",
+        _ => {
+            state.result = Some(server_result);
+            return;
+        }
+    };
+    state.finish(white, black);
+    if server == ours {
+        return;
+    }
+    let discrepancy = format!(
+        "server says {:?} won, our board says {} (black {}, white {}, {} empty)",
+        server_winner,
+        ours,
+        black.count_ones(),
+        white.count_ones(),
+        (!(white | black)).count_ones()
+    );
+    println!("RESULT MISMATCH: {}; recording the server's result", discrepancy);
+    print_board(white, black, 0, 0, false);
+    state.result = Some(server_result);
+    state.result_discrepancy = Some(discrepancy);
+}
+
+/// Join the first open game on the server, or create one and wait for an
+/// opponent. Returns `(game_id, color, opponent's first move or 0)`;
+/// the game id is empty if nothing could be joined.
+#[cfg(feature = "multiplayer")]
+fn find_or_create_game(args: &Args, retry_stats: &mut RetryStats) -> (String, String, u64) {
+    let games: Vec<String>;
+    let mut attempt = 0;
+    loop {
+        match find_games_to_join(args) {
+            Ok(g) => {
+                games = g;
+                break;
+            }
+            Err(e) => {
+                attempt += 1;
+                retry_stats.record("game_list", attempt, &e);
+                thread::sleep(time::Duration::from_millis(1000));
+            }
+        }
+    }
+    let mut my_game_uuid: String = String::new();
+    let mut my_color: String = String::new();
+    let mut opp_first_move: u64 = 0;
+    if games.len() == 0 {
+        println!("No games to join, creating one!");
+        let new_game: NewGameResult;
+        let mut attempt = 0;
         loop {
-            match create_game(&args) {
+            match create_game(args) {
                 Ok(g) => {
                     new_game = g;
                     break;
                 }
                 Err(e) => {
-                    println!("Error while creating a game, retrying: {}", e);
+                    attempt += 1;
+                    retry_stats.record("create_game", attempt, &e);
                     thread::sleep(time::Duration::from_millis(1000));
                 }
             }
@@ -12180,21 +13233,27 @@ fn play_multiplayer(args: Args) {
         my_game_uuid = new_game.game_id;
         my_color = new_game.color;
         println!("Waiting for ooponent to join");
-        let opp_join_status = wait_for_joining_player(&args, my_game_uuid.clone());
+        let opp_join_status = wait_for_joining_player(args, my_game_uuid.clone(), retry_stats);
         if opp_join_status.last_move != String::new() {
-            opp_first_move = move_to_bitmap(opp_join_status.last_move.as_str()).unwrap();
+            match parse_move(&opp_join_status.last_move) {
+                Ok(Move::Play(bit)) => opp_first_move = bit,
+                Ok(other) => println!("Ignoring unexpected opening move {:?}", other),
+                Err(e) => println!("Ignoring malformed opening move from server: {}", e),
+            }
         }
     } else {
         for game in games {
             let joined_game: GameJoinResult;
+            let mut attempt = 0;
             loop {
-                match join_game(&args, game.clone()) {
+                match join_game(args, game.clone()) {
                     Ok(g) => {
                         joined_game = g;
                         break;
                     }
                     Err(e) => {
-                        println!("Error while joining a game, retrying: {}", e);
+                        attempt += 1;
+                        retry_stats.record("join", attempt, &e);
                         thread::sleep(time::Duration::from_millis(1000));
                     }
                 }
@@ -12206,13 +13265,59 @@ fn play_multiplayer(args: Args) {
             }
         }
     }
+    (my_game_uuid, my_color, opp_first_move)
+}
+
+#[cfg(feature = "multiplayer")]
+fn play_multiplayer(args: Args) {
+    println!(
+        "{} {} {} {}",
+        args.api_url, args.search_depth, args.book_path, args.player_uuid
+    );
+    let mut retry_stats = RetryStats::default();
+    let (my_game_uuid, my_color, opp_first_move, mut state) = if args.resume_game.is_empty() {
+        let (game_id, color, first_move) = find_or_create_game(&args, &mut retry_stats);
+        let state = GameState {
+            game_id: Some(game_id.clone()),
+            color: Some(color.clone()),
+            ..GameState::default()
+        };
+        (game_id, color, first_move, state)
+    } else {
+        let state = match GameState::load(&args.resume_game) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Cannot resume game: {}", e);
+                return;
+            }
+        };
+        if let Some(result) = &state.result {
+            println!("Game is already over: {}", result);
+            return;
+        }
+        match (state.game_id.clone(), state.color.clone()) {
+            (Some(game_id), Some(color)) => {
+                println!("Resuming game {} after {} moves", game_id, state.moves.len());
+                (game_id, color, 0, state)
+            }
+            _ => {
+                println!("Cannot resume game: {} has no game id", args.resume_game);
+                return;
+            }
+        }
+    };
     if my_game_uuid.is_empty() {
         println!("Failed to create or join game!");
     } else {
         println!("Playing game {} as {}", my_game_uuid, my_color);
-        let mut black = 0x0000000810000000u64;
-        let mut white = 0x0000001008000000u64;
-        let mut white_to_move: bool = false;
+        let (mut white, mut black, mut white_to_move) = match state.replay() {
+            Ok(p) => p,
+            Err(e) => {
+                println!("Cannot resume game: {}", e);
+                retry_stats.report();
+                return;
+            }
+        };
         if opp_first_move > 0 {
             println!("Applying opponent's initial move");
             let (new_white, new_black) =
@@ -12220,74 +13325,86 @@ fn play_multiplayer(args: Args) {
             white = new_white;
             black = new_black;
             white_to_move = !white_to_move;
+            state.record(Move::Play(opp_first_move), white, black, white_to_move);
+            save_game_state(&state, &args.state_file);
         }
         print_board(white, black, 0, 0, false);
-        let book: OpeningBook;
-        if args.book_path != String::new() {
-            book = OpeningBook::load_from_file(args.book_path.clone().as_str()).unwrap();
-        } else {
-            book = OpeningBook::default();
-        }
+        let book = load_game_book(&args);
+        // Whether our last move sent to the server was a pass; an opponent
+        // pass straight after it means neither side can move.
+        let mut we_passed = false;
+        // Consecutive unparseable `last_move` values seen from the server.
+        let mut bad_move_polls: u32 = 0;
+        const MAX_BAD_MOVE_POLLS: u32 = 5;
+        let mut book_usage = BookUsage::default();
+        let mut ponder_usage = PonderUsage::default();
+        let mut random = OpeningRandomizer::from_args(&args);
+        let mut clock = game_clock(&args);
         loop {
             if white_to_move == (my_color == "white".to_string()) {
-                let nxt_move: u64;
+                let nxt_move: SearchMove;
                 let eval: i32;
-                let next_move_opt = book.get(&Position {
-                    black: black,
-                    white: white,
-                    white_to_move: white_to_move,
-                });
-                match next_move_opt {
-                    Some(m) => {
-                        println!("Book move found!");
-                        nxt_move = m.suggested_moves[0];
+                let mut from_book = false;
+                match book_move(&book, white, black, white_to_move, &mut random) {
+                    Some(mv) => {
+                        nxt_move = mv;
                         eval = 0;
+                        from_book = true;
                     }
                     None => {
-                        let piece_count = (white | black).count_ones();
-                        let depth: u32;
-                        if (64 - piece_count) > args.search_depth {
-                            depth = args.search_depth;
-                        } else {
-                            depth = 64 - piece_count;
-                        }
-                        (nxt_move, eval) = search_iterative(
+                        (nxt_move, eval) = engine_move(
+                            &mut clock,
                             white,
                             black,
                             white_to_move,
-                            depth,
-                            DEFAULT_CFG,
+                            args.search_depth,
+                            args.search_stats,
+                            args.move_time_limit,
+                            &mut random,
                         );
-                        if nxt_move == 0 {
-                            println!("NO MOVES!");
-                        }
                     }
                 }
-                let mut nxt_move_algebraic: String;
-                if nxt_move == 0 {
-                    nxt_move_algebraic = "resign".to_string();
-                    println!("Failed to find a move, we resign!");
-                } else if nxt_move == u64::MAX {
-                    nxt_move_algebraic = "pass".to_string();
-                    println!("No legal moves, we pass!");
-                } else {
-                    let (new_white, new_black) =
-                        apply_move_verbose(white, black, nxt_move, white_to_move).unwrap();
-                    nxt_move_algebraic = move_to_algebraic(nxt_move).unwrap();
-                    println!(
-                        "Move {} {}, eval {}, black pos: {}, white pos: {}, white move: {}",
-                        nxt_move_algebraic, nxt_move, eval, black, white, white_to_move
-                    );
-                    white = new_white;
-                    black = new_black;
-                    let game_status = check_game_status(new_white, new_black, !white_to_move);
-                    if (game_status == (u64::MAX - 1) && my_color == "white".to_string())
-                        || (game_status == (u64::MAX - 2) && my_color == "black".to_string())
-                    {
-                        nxt_move_algebraic = "resign".to_string();
+                let mut sent_move: Move;
+                match nxt_move {
+                    SearchMove::NoMove => {
+                        sent_move = Move::Resign;
+                        println!("Failed to find a move, we resign!");
+                    }
+                    SearchMove::Pass => {
+                        sent_move = Move::Pass;
+                        println!("No legal moves, we pass!");
+                    }
+                    SearchMove::Play(nxt_move) => {
+                        book_usage.record(from_book);
+                        let (new_white, new_black) =
+                            apply_move_verbose(white, black, nxt_move, white_to_move).unwrap();
+                        sent_move = Move::Play(nxt_move);
+                        println!(
+                            "Move {} {}, eval {}, black pos: {}, white pos: {}, white move: {}",
+                            format_move(sent_move),
+                            nxt_move,
+                            if args.eval_our_perspective {
+                                format!("{} (ours)", eval_for_color(eval, my_color == "white"))
+                            } else {
+                                eval.to_string()
+                            },
+                            black,
+                            white,
+                            white_to_move
+                        );
+                        white = new_white;
+                        black = new_black;
+                        let game_status = check_game_status(new_white, new_black, !white_to_move);
+                        if (game_status == (u64::MAX - 1) && my_color == "white".to_string())
+                            || (game_status == (u64::MAX - 2) && my_color == "black".to_string())
+                        {
+                            sent_move = Move::Resign;
+                        }
                     }
                 }
+                let nxt_move_algebraic = format_move(sent_move);
                 let move_result: MoveResult;
+                let mut attempt = 0;
                 loop {
                     match make_move(&args, my_game_uuid.clone(), nxt_move_algebraic.clone()) {
                         Ok(g) => {
@@ -12295,54 +13412,183 @@ fn play_multiplayer(args: Args) {
                             break;
                         }
                         Err(e) => {
-                            println!("Error while making a move, retrying: {}", e);
+                            attempt += 1;
+                            retry_stats.record("move", attempt, &e);
                             thread::sleep(time::Duration::from_millis(1000));
                         }
                     }
                 }
+                we_passed = nxt_move == SearchMove::Pass;
+                state.record(sent_move, white, black, !white_to_move);
                 if !move_result.r#continue {
                     println!("Game ended, {} won!", move_result.winner);
-                    println!(
-                        "Black score: {}. white score: {}",
-                        black.count_ones(),
-                        white.count_ones()
-                    );
+                    let status = check_game_status(white, black, !white_to_move);
+                    println!("{}", final_result(white, black, status));
+                    reconcile_winner(&move_result.winner, white, black, status, &mut state);
+                    save_game_state(&state, &args.state_file);
                     break;
                 } else {
                     white_to_move = !white_to_move;
+                    save_game_state(&state, &args.state_file);
                 }
                 // Our move!
             } else {
                 println!("Patiently waiting for opponent's move");
-                let next_status: GameStatusResult =
-                    wait_for_response(&args, my_game_uuid.clone(), my_color.clone());
+                let ponder = if args.ponder {
+                    Ponder::start(&book, white, black, white_to_move, args.search_depth)
+                } else {
+                    None
+                };
+                let next_status: GameStatusResult = wait_for_response(
+                    &args,
+                    my_game_uuid.clone(),
+                    my_color.clone(),
+                    &mut retry_stats,
+                );
+                if let Some(ponder) = ponder {
+                    let actual = match parse_move(&next_status.last_move) {
+                        Ok(Move::Play(bit)) => bit,
+                        _ => 0,
+                    };
+                    let hit = ponder.finish(actual);
+                    println!("Ponder {}", if hit { "hit" } else { "miss" });
+                    ponder_usage.record(hit);
+                }
                 if next_status.status == "black_won".to_string() {
                     println!("Game ended, black won!");
+                    state.result = Some(next_status.status.clone());
+                    save_game_state(&state, &args.state_file);
                     break;
                 } else if next_status.status == "white_won".to_string() {
                     println!("Game ended, white won!");
+                    state.result = Some(next_status.status.clone());
+                    save_game_state(&state, &args.state_file);
                     break;
                 }
-                if next_status.last_move == "pass".to_string() {
+                let opp_move: u64 = match parse_move(&next_status.last_move) {
+                    Ok(Move::Play(bit)) => {
+                        bad_move_polls = 0;
+                        bit
+                    }
+                    Ok(Move::Pass) => 0,
+                    Ok(Move::Resign) => {
+                        println!("Opponent resigned!");
+                        state.record(Move::Resign, white, black, !white_to_move);
+                        state.result = Some(format!("{}_won", my_color));
+                        save_game_state(&state, &args.state_file);
+                        break;
+                    }
+                    Err(e) => {
+                        // Possibly a transient glitch: poll the status again
+                        // a few times before giving up on the game.
+                        bad_move_polls += 1;
+                        report_desync(
+                            &format!("malformed opponent move from server: {}", e),
+                            white,
+                            black,
+                            white_to_move,
+                        );
+                        if bad_move_polls >= MAX_BAD_MOVE_POLLS {
+                            println!("Giving up after {} malformed moves", bad_move_polls);
+                            break;
+                        }
+                        thread::sleep(time::Duration::from_millis(1000));
+                        continue;
+                    }
+                };
+                if opp_move == 0 {
                     println!("Opponnent passes their move!");
+                    let opp_status = check_game_status(white, black, white_to_move);
+                    if we_passed || (opp_status != u64::MAX && opp_status >= u64::MAX - 3) {
+                        println!("Both sides are out of moves, game over!");
+                        state.finish(white, black);
+                        save_game_state(&state, &args.state_file);
+                        println!("{}", final_result(white, black, opp_status));
+                        break;
+                    }
+                    if opp_status != u64::MAX {
+                        report_desync(
+                            "opponent passed while having legal moves",
+                            white,
+                            black,
+                            white_to_move,
+                        );
+                        println!("Cannot continue without knowing the board, stopping");
+                        break;
+                    }
                     white_to_move = !white_to_move;
+                    state.record(Move::Pass, white, black, white_to_move);
+                    save_game_state(&state, &args.state_file);
                     continue;
                 }
-                let opp_move: u64 = move_to_bitmap(next_status.last_move.as_str()).unwrap();
                 println!("Here it is: {} {}!", next_status.last_move, opp_move);
                 let (new_white, new_black) =
-                    apply_move_verbose(white, black, opp_move, white_to_move).unwrap();
+                    match apply_move_verbose(white, black, opp_move, white_to_move) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            report_desync(
+                                &format!(
+                                    "opponent move {} is illegal on our board: {}",
+                                    next_status.last_move, e
+                                ),
+                                white,
+                                black,
+                                white_to_move,
+                            );
+                            println!("Cannot continue without knowing the board, stopping");
+                            break;
+                        }
+                    };
                 white = new_white;
                 black = new_black;
                 white_to_move = !white_to_move;
+                state.record(Move::Play(opp_move), white, black, white_to_move);
+                save_game_state(&state, &args.state_file);
                 // Opponent's move!
             }
         }
+        book_usage.report();
+        if args.ponder {
+            ponder_usage.report();
+        }
     }
+    retry_stats.report();
 }
 
 fn main() {
     let args = Args::parse();
+    evalcache::set_eval_cache_enabled(args.eval_cache);
+    tt::set_tt_enabled(!args.no_tt);
+    tt::init_tt(args.tt_size_mb);
+    set_lmr_enabled(!args.no_lmr);
+    set_parallel_depth(args.parallel_depth);
+    set_mtdf_enabled(args.mtdf);
+    set_root_solve_enabled(true);
+    set_phase_crossover(args.phase_crossover);
+    if !args.eval_config.is_empty() {
+        match EvalCfg::load_from_file(&args.eval_config) {
+            Ok(cfg) => set_play_cfg(cfg),
+            Err(e) => {
+                eprintln!("--eval-config {}: {}", args.eval_config, e);
+                std::process::exit(2);
+            }
+        }
+    }
+    set_endgame_selectivity(args.endgame_selectivity);
+    match args.tiebreak.parse::<Tiebreak>() {
+        Ok(t) => set_root_tiebreak(t),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+    let book_format = match args.book_format.parse::<BookFormat>() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
     if args.generate_book {
         if args.book_path.as_str() != "" {
             println!(
@@ -12354,10 +13600,64 @@ fn main() {
                 args.full_depth,
                 args.k_partial_depth,
                 args.book_path.as_str(),
+                args.book_line.as_str(),
+                args.book_margin,
+                book_format,
+                args.book_save_interval,
             );
         } else {
             println!("No opening book save path provided!");
         }
+    } else if args.book_stats {
+        match OpeningBook::load_from_file(&args.book_path) {
+            Ok(book) => println!("{}", book.stats()),
+            Err(e) => {
+                eprintln!("{}: {}", args.book_path, e);
+                std::process::exit(2);
+            }
+        }
+    } else if !args.export_book.is_empty() {
+        let book = match OpeningBook::load_from_file(&args.book_path) {
+            Ok(book) => book,
+            Err(e) => {
+                eprintln!("{}: {}", args.book_path, e);
+                std::process::exit(2);
+            }
+        };
+        let lines = book.export_lines();
+        let text: String = lines.iter().flat_map(|l| [l.as_str(), "\n"]).collect();
+        if let Err(e) = std::fs::write(&args.export_book, text) {
+            eprintln!("{}: {}", args.export_book, e);
+            std::process::exit(1);
+        }
+        println!("Exported {} lines to {}", lines.len(), args.export_book);
+    } else if args.validate_book {
+        let book = match OpeningBook::load_from_file(&args.book_path) {
+            Ok(book) => book,
+            Err(e) => {
+                eprintln!("{}: {}", args.book_path, e);
+                std::process::exit(2);
+            }
+        };
+        let bad = book.validate();
+        for pos in &bad {
+            println!("illegal book move at {}", pos.to_key());
+        }
+        println!("{} of {} positions have illegal moves", bad.len(), book.entries.len());
+        if !bad.is_empty() {
+            std::process::exit(1);
+        }
+    } else if args.prune_book {
+        match prune_book_file(&args.book_path, args.book_min_weight, book_format) {
+            Ok((removed, total)) => println!(
+                "Pruned {} of {} positions below weight {}",
+                removed, total, args.book_min_weight
+            ),
+            Err(e) => {
+                eprintln!("{}: {}", args.book_path, e);
+                std::process::exit(1);
+            }
+        }
     } else if args.compare_configs {
         // Two ad-hoc configs used historically as a `compare_configs`
         // smoke test. `--validate-match` + `--tune-initial-coefs` is
@@ -12369,15 +13669,77 @@ fn main() {
             edge_value: 17,
             antiedge_value: -20,
             anticorner_value: -30,
-            disc_values: DEFAULT_CFG.disc_values,
-            mobility_values: DEFAULT_CFG.mobility_values,
+            ..DEFAULT_CFG
         };
         println!(
             "The score between first and second configs is {}",
             compare_configs(first, second, args.search_depth)
         );
+    } else if !args.tournament.is_empty() {
+        // `default` (or an empty entry) stands for DEFAULT_CFG.
+        let configs: Vec<(String, EvalCfg)> = args
+            .tournament
+            .split(';')
+            .map(|c| c.trim())
+            .map(|c| if c == "default" { "" } else { c })
+            .map(|c| {
+                let name = if c.is_empty() { "default" } else { c };
+                (name.to_string(), parse_coefs_or_default(c))
+            })
+            .collect();
+        let positions = generate_ply_positions(args.tune_ply);
+        println!(
+            "tournament: {} configs at depth {} over {} positions",
+            configs.len(),
+            args.search_depth,
+            positions.len()
+        );
+        let results = tournament::run_tournament(
+            &configs,
+            args.search_depth,
+            &positions,
+            &args.tournament_results,
+        );
+        tournament::print_cross_table(&configs, &results, args.search_depth, positions.len());
+    } else if args.verify_determinism {
+        std::process::exit(verify_determinism(&args.position, args.search_depth));
+    } else if args.oneshot {
+        let weights = if args.square_weights.is_empty() {
+            None
+        } else {
+            let loaded = std::fs::read_to_string(&args.square_weights)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse_square_weights(&text));
+            match loaded {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    eprintln!("--square-weights {}: {}", args.square_weights, e);
+                    std::process::exit(2);
+                }
+            }
+        };
+        std::process::exit(oneshot(
+            &args.position,
+            args.search_depth,
+            args.multipv,
+            args.root_nodes,
+            args.phase_configs.split_once(';').map(|(opening, endgame)| {
+                (parse_coefs_or_default(opening), parse_coefs_or_default(endgame))
+            }),
+            weights,
+            args.eval_breakdown,
+        ));
+    } else if !args.replay.is_empty() {
+        let delay_ms = if args.replay_speed > 0 {
+            args.replay_speed
+        } else {
+            DEFAULT_REPLAY_MS
+        };
+        std::process::exit(replay_game(&args.replay, delay_ms));
     } else if args.benchmark {
         benchmark(args.search_depth);
+    } else if args.benchmark_movegen {
+        benchmark_movegen();
     } else if args.benchmark_endgame {
         benchmark_endgame(
             args.search_depth,
@@ -12442,16 +13804,1986 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    /// The pre-canonical-hash enumeration from `compare_configs`, kept to
+    /// check that the `HashSet<u64>` dedup yields the same position set.
+    fn legacy_ply_positions(ply: u32) -> Vec<Position> {
+        let mut queue = vec![Position {
+            black: START_BLACK,
+            white: START_WHITE,
+            white_to_move: false,
+        }];
+        let mut dedup_cache: HashMap<Position, bool> = HashMap::new();
+        for _ in 0..ply {
+            let mut next_queue: Vec<Position> = Vec::new();
+            for pos in queue {
+                if dedup_cache.contains_key(&pos) {
+                    continue;
+                }
+                for next_move in find_legal_moves_alt(pos.white, pos.black, pos.white_to_move) {
+                    if let Ok((w, b)) =
+                        apply_move(pos.white, pos.black, next_move, pos.white_to_move)
+                    {
+                        let mut p = pos;
+                        for _ in 0..4 {
+                            dedup_cache.insert(p, true);
+                            dedup_cache.insert(flip_position_vertical(&p), true);
+                            dedup_cache.insert(flip_position_horizontal(&p), true);
+                            p = rotate_position_90(&p);
+                        }
+                        next_queue.push(Position {
+                            black: b,
+                            white: w,
+                            white_to_move: !pos.white_to_move,
+                        });
+                    }
+                }
+            }
+            queue = next_queue;
+        }
+        queue
+    }
+
+    #[test]
+    fn canonical_dedup_matches_legacy_position_set() {
+        for ply in 1..=5 {
+            assert_eq!(generate_ply_positions(ply), legacy_ply_positions(ply), "ply {ply}");
+        }
+    }
 
     #[test]
-    fn eval_position_with_cfg_smoke() {
+    fn eval_position_smoke() {
         // Midgame bitboards from an old smoke test; exact score drifts
         // when `EvalCfg` changes — we only assert finiteness / sanity.
-        let v = eval_position_with_cfg(
-            4325574457067520514,
-            33909430323788925,
-            DEFAULT_CFG,
-        );
+        let (white, black) = (4325574457067520514, 33909430323788925);
+        let v = engine::eval_position(white, black);
+        assert_eq!(v, eval_position_with_cfg(white, black, false, DEFAULT_CFG));
         assert!(v.abs() < 50_000, "eval out of range: {v}");
     }
+
+    #[test]
+    fn apply_moves_replays_full_game() {
+        // Play a whole game move by move (lowest legal square each turn),
+        // then check the batched replay lands on the same final position.
+        let (mut white, mut black, mut white_to_move) = (START_WHITE, START_BLACK, false);
+        let mut moves = Vec::new();
+        loop {
+            let legal = check_game_status(white, black, white_to_move);
+            if legal == u64::MAX {
+                white_to_move = !white_to_move;
+                continue;
+            }
+            if legal >= u64::MAX - 3 {
+                break;
+            }
+            let mv = legal & legal.wrapping_neg();
+            (white, black) = apply_move(white, black, mv, white_to_move).unwrap();
+            white_to_move = !white_to_move;
+            moves.push(mv);
+        }
+        assert_eq!(moves.len(), 60);
+        let (w, b, _) = apply_moves(START_WHITE, START_BLACK, false, &moves).unwrap();
+        assert_eq!((w, b), (white, black));
+
+        let mut bad = moves.clone();
+        bad[10] = bad[9];
+        assert_eq!(
+            apply_moves(START_WHITE, START_BLACK, false, &bad).unwrap_err(),
+            (10, MoveError::Illegal)
+        );
+        let mut extra = moves.clone();
+        extra.push(moves[0]);
+        assert_eq!(
+            apply_moves(START_WHITE, START_BLACK, false, &extra).unwrap_err(),
+            (moves.len(), MoveError::GameOver)
+        );
+    }
+
+    #[test]
+    fn canonical_hash_is_shared_by_all_symmetries() {
+        // f5 d6 c3: no symmetry of its own, so all eight images differ.
+        let moves = parse_transcript("f5d6c3").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let pos = Position { black, white, white_to_move };
+        let images = symmetries(&pos);
+        let distinct: HashSet<(u64, u64)> = images.iter().map(|p| (p.black, p.white)).collect();
+        assert_eq!(distinct.len(), 8);
+        for image in &images {
+            assert_eq!(canonical_hash(image), canonical_hash(&pos));
+        }
+        let other_side = Position { white_to_move: !white_to_move, ..pos };
+        assert_ne!(canonical_hash(&other_side), canonical_hash(&pos));
+    }
+
+    /// The bit of a square given as `"d6"`.
+    fn sq(s: &str) -> u64 {
+        match parse_move(s) {
+            Ok(Move::Play(bit)) => bit,
+            _ => panic!("bad square {s}"),
+        }
+    }
+
+    #[test]
+    fn tiebreaks_pick_expected_root_move() {
+        // After f5, White's d6, f4 and f6 treated as an eval tie.
+        let (white, black) = apply_move(START_WHITE, START_BLACK, sq("f5"), false).unwrap();
+        let tied = [sq("d6"), sq("f4"), sq("f6")];
+        let pick = |t| break_root_tie(white, black, &tied, t);
+        assert_eq!(pick(Tiebreak::FirstFound), sq("d6"));
+        assert_eq!(pick(Tiebreak::LowestSquare), sq("f4"));
+        // f4 and f6 both leave us five moves; the lower square wins.
+        assert_eq!(pick(Tiebreak::MostMobilityForUs), sq("f4"));
+        assert_eq!(pick(Tiebreak::LeastMobilityForOpponent), sq("f6"));
+    }
+
+    #[test]
+    fn iterative_deepening_matches_direct_search() {
+        let moves = parse_transcript("f5d6c3d3c4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let depth = 5;
+        // Each from an empty table of its own, so neither can reuse the
+        // other's (or another test's) deeper entries.
+        let (_, direct) = search_moves_opt_in(
+            white,
+            black,
+            white_to_move,
+            depth,
+            MIN_EVAL,
+            MAX_EVAL,
+            depth,
+            DEFAULT_CFG,
+            &tt::TranspositionTable::new_mb(1),
+        );
+        let table = tt::TranspositionTable::new_mb(1);
+        let mut nodes = 0;
+        let (mv, iterative) = search_iterative_cntr_in(
+            white,
+            black,
+            white_to_move,
+            depth,
+            DEFAULT_CFG,
+            &mut nodes,
+            &table,
+        );
+        assert_eq!(iterative, direct);
+        let legal = check_game_status(white, black, white_to_move);
+        assert!(mv.count_ones() == 1 && legal & mv != 0);
+    }
+
+    #[test]
+    fn game_state_round_trips_and_replays() {
+        let mut state = GameState::default();
+        let (mut white, mut black, mut white_to_move) = (START_WHITE, START_BLACK, false);
+        for sq in ["f5", "d6", "c3"] {
+            let Ok(Move::Play(mv)) = parse_move(sq) else {
+                panic!("bad square {sq}");
+            };
+            (white, black) = apply_move(white, black, mv, white_to_move).unwrap();
+            white_to_move = !white_to_move;
+            state.record(Move::Play(mv), white, black, white_to_move);
+        }
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.moves, ["f5", "d6", "c3"]);
+        assert_eq!(loaded.replay().unwrap(), (white, black, white_to_move));
+
+        let mut tampered = loaded;
+        tampered.black ^= 1;
+        assert!(tampered.replay().is_err());
+    }
+
+    /// A random position with at most `max_empties` empty squares where
+    /// the side to move has a move, reached by seeded random play.
+    fn random_endgame(seed: u64, max_empties: u32) -> (u64, u64, bool) {
+        let mut rng = seed;
+        loop {
+            let (mut white, mut black, mut white_to_move) = (START_WHITE, START_BLACK, false);
+            loop {
+                let status = check_game_status(white, black, white_to_move);
+                if status >= u64::MAX - 3 && status != u64::MAX {
+                    break;
+                }
+                if status != u64::MAX {
+                    if (!(white | black)).count_ones() <= max_empties {
+                        return (white, black, white_to_move);
+                    }
+                    let mut moves = legal_moves(white, black, white_to_move);
+                    rng = splitmix64(rng);
+                    let pick = (rng % moves.len() as u64) as usize;
+                    let mv = moves.nth(pick).unwrap();
+                    (white, black) = apply_move(white, black, mv, white_to_move).unwrap();
+                }
+                white_to_move = !white_to_move;
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_and_serial_negamax_agree_on_endgames() {
+        // Solved to the end so every TT hit is exact and the value is
+        // independent of what other tests left in the shared table.
+        for seed in 0..50 {
+            let (white, black, white_to_move) = random_endgame(seed, 8);
+            let depth = (!(white | black)).count_ones();
+            let (par_move, par) = search_moves_par(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            let (_, serial) = search_moves_opt(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            assert_eq!(par, serial, "seed {}", seed);
+            let legal = check_game_status(white, black, white_to_move);
+            assert!(legal & par_move != 0, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn root_order_starts_with_tt_move() {
+        let moves = parse_transcript("f5d6c3d3c4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let (best, _) = search_moves_opt(
+            white,
+            black,
+            white_to_move,
+            4,
+            MIN_EVAL,
+            MAX_EVAL,
+            4,
+            DEFAULT_CFG,
+        );
+        // A private table, so concurrent tests can't clear or overwrite it.
+        let table = tt::TranspositionTable::new_mb(1);
+        let (us, them) = if white_to_move { (white, black) } else { (black, white) };
+        let key = tt::hash_position(us, them) ^ eval_cfg_key(&DEFAULT_CFG);
+        table.store(key, 0, 4, tt::BOUND_EXACT, best.trailing_zeros() as u8);
+        let order = root_move_order(white, black, white_to_move, DEFAULT_CFG, &table);
+        assert_eq!(order[0], best);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, find_legal_moves_alt(white, black, white_to_move));
+    }
+
+    #[test]
+    fn killer_moves_produce_cutoffs() {
+        // In a midgame tree the refutation found in one subtree keeps
+        // refuting its siblings, so the killer slots get filled and used.
+        let moves = parse_transcript("f5d6c3d3c4f4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let (_, _, stats) = search_moves_opt_stats(
+            white,
+            black,
+            white_to_move,
+            5,
+            MIN_EVAL,
+            MAX_EVAL,
+            5,
+            DEFAULT_CFG,
+        );
+        assert!(stats.killer_cutoffs > 0, "{:?}", stats);
+    }
+
+    #[test]
+    fn aspiration_windows_match_full_window_search() {
+        // Exact solves: the heuristic scores of the early iterations are
+        // far from the final one, so the last iteration has to re-search.
+        for seed in 100..110 {
+            let (white, black, white_to_move) = random_endgame(seed, 10);
+            let depth = (!(white | black)).count_ones();
+            let (mv, eval) = search_iterative(white, black, white_to_move, depth, DEFAULT_CFG);
+            let (_, full) = search_moves_par(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            assert_eq!(eval, full, "seed {}", seed);
+            assert!(matches!(mv, SearchMove::Play(_)), "seed {}: {:?}", seed, mv);
+        }
+    }
+
+    #[test]
+    fn endgame_solver_finds_exact_margin() {
+        // 10 empties, Black to move; Black loses by 8 with best play
+        // (checked with an independent full-width minimax).
+        let moves = parse_transcript(
+            "f5f4f3f6d3f2g6c3b3b2g4g3b1d2c4c5f1g2g1g5c6a1h6a2b5\
+             c7f7d6c2h5b8e7d1a4a3g7h4e3e6h1d8e1e8b4f8h2c1h8a6b6",
+        )
+        .unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert!(!white_to_move);
+        assert_eq!((!(white | black)).count_ones(), 10);
+        let (mv, margin) = solve_endgame(white, black, white_to_move);
+        assert_eq!(margin, -8);
+        // The chosen move must actually keep the margin.
+        let (w, b) = apply_move(white, black, mv, white_to_move).unwrap();
+        assert_eq!(solve_endgame(w, b, true).1, -8);
+    }
+
+    #[test]
+    fn region_parity_counts_odd_regions() {
+        assert_eq!(region_parity(START_WHITE, START_BLACK), 0);
+        // Empty: a1 alone (odd), g8+h8 (even), and c3 d4 e5 - a
+        // diagonal chain, one region of three (odd).
+        let (a1, g8, h8) = (1u64, 1u64 << 62, 1u64 << 63);
+        let chain = (1u64 << 18) | (1u64 << 27) | (1u64 << 36);
+        let filled = !(a1 | g8 | h8 | chain);
+        let black = filled & 0x5555_5555_5555_5555;
+        let white = filled & !black;
+        assert_eq!(region_parity(white, black), 2);
+        assert_eq!(region_parity(black, white), 2);
+    }
+
+    /// Full-window `search_moves_opt` to `depth` against a fresh table of
+    /// its own, so the result can't depend on what other tests, running
+    /// alongside, leave in or clear from the global TT.
+    fn search_fresh(
+        white: u64,
+        black: u64,
+        white_to_move: bool,
+        depth: u32,
+        cfg: EvalCfg,
+    ) -> (u64, i32) {
+        let table = tt::TranspositionTable::new_mb(1);
+        let (lo, hi) = (MIN_EVAL, MAX_EVAL);
+        search_moves_opt_in(white, black, white_to_move, depth, lo, hi, depth, cfg, &table)
+    }
+
+    /// Plain minimax (absolute frame), for checking the pruned search at
+    /// small depths. Passes don't use up depth and the horizon runs the
+    /// same corner quiescence, as in the search; the game must not end
+    /// within `depth`.
+    fn minimax(white: u64, black: u64, white_to_move: bool, depth: u32, cfg: EvalCfg) -> i32 {
+        if depth == 0 {
+            return corner_quiescence(white, black, white_to_move, 4, cfg);
+        }
+        let legal = check_game_status(white, black, white_to_move);
+        assert!(legal == u64::MAX || legal < u64::MAX - 3, "game ended");
+        if legal == u64::MAX {
+            return minimax(white, black, !white_to_move, depth, cfg);
+        }
+        let evals = legal_moves(white, black, white_to_move).map(|mv| {
+            let (w, b) = apply_move(white, black, mv, white_to_move).unwrap();
+            minimax(w, b, !white_to_move, depth - 1, cfg)
+        });
+        if white_to_move {
+            evals.min().unwrap()
+        } else {
+            evals.max().unwrap()
+        }
+    }
+
+    fn corner_quiescence(
+        white: u64,
+        black: u64,
+        white_to_move: bool,
+        plies: u32,
+        cfg: EvalCfg,
+    ) -> i32 {
+        let stand_pat = eval_position_with_cfg(white, black, white_to_move, cfg);
+        if plies == 0 {
+            return stand_pat;
+        }
+        let evals = legal_moves(white, black, white_to_move)
+            .filter(|&mv| mv & 0x8100_0000_0000_0081 != 0)
+            .map(|mv| {
+                let (w, b) = apply_move(white, black, mv, white_to_move).unwrap();
+                corner_quiescence(w, b, !white_to_move, plies - 1, cfg)
+            })
+            .chain(std::iter::once(stand_pat));
+        if white_to_move {
+            evals.min().unwrap()
+        } else {
+            evals.max().unwrap()
+        }
+    }
+
+    #[test]
+    fn search_without_lmr_matches_minimax() {
+        // LMR is off unless `main` enables it; without it and the corner
+        // extension the search must be exact.
+        assert!(!lmr_enabled());
+        let cfg = EvalCfg {
+            corner_extension: false,
+            ..DEFAULT_CFG
+        };
+        let moves = parse_transcript("f5f6e6f4e3").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        for depth in 1..=4 {
+            let (_, eval) = search_fresh(white, black, white_to_move, depth, cfg);
+            assert_eq!(eval, minimax(white, black, white_to_move, depth, cfg), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn forced_pass_resolves_to_pass() {
+        // White a1 b1, Black c1: Black has no move, White can take d1.
+        let (white, black) = (0b011u64, 0b100u64);
+        assert_eq!(check_game_status(white, black, false), u64::MAX);
+        let resolve = |wtm, mv| SearchMove::resolve(white, black, wtm, mv);
+        for depth in 1..=3 {
+            let window = (MIN_EVAL, MAX_EVAL);
+            let par = |wtm| {
+                search_moves_par(white, black, wtm, depth, window.0, window.1, depth, DEFAULT_CFG).0
+            };
+            let serial = |wtm| {
+                search_moves_opt(white, black, wtm, depth, window.0, window.1, depth, DEFAULT_CFG).0
+            };
+            assert_eq!(resolve(false, par(false)), SearchMove::Pass);
+            assert_eq!(resolve(false, serial(false)), SearchMove::Pass);
+            assert_eq!(resolve(true, par(true)), SearchMove::Play(0b1000));
+        }
+        // d1 flips c1 and ends the game: nothing left to play.
+        assert_eq!(SearchMove::resolve(0b1111, 0, false, u64::MAX), SearchMove::NoMove);
+    }
+
+    #[test]
+    fn quiescence_sees_corner_beyond_horizon() {
+        // Black to move. On static eval alone f6 looks best, but it gives
+        // White a corner; a depth-1 search must see that and avoid it.
+        let moves = parse_transcript("d3e3f3c3f5f4b3g2c5e2g3c6g1c2b7e6c1b6").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert!(!white_to_move);
+        let corners: u64 = 0x8100_0000_0000_0081;
+        let after = |mv| apply_move(white, black, mv, white_to_move).unwrap();
+        let hangs_corner = |mv| {
+            let (w, b) = after(mv);
+            compute_moves(w, b) & corners != 0
+        };
+        let static_best = legal_moves(white, black, white_to_move)
+            .max_by_key(|&mv| {
+                let (w, b) = after(mv);
+                eval_position_with_cfg(w, b, !white_to_move, DEFAULT_CFG)
+            })
+            .unwrap();
+        assert!(hangs_corner(static_best));
+        let (mv, _) = search_moves_opt(
+            white,
+            black,
+            white_to_move,
+            1,
+            MIN_EVAL,
+            MAX_EVAL,
+            1,
+            DEFAULT_CFG,
+        );
+        assert!(!hangs_corner(mv), "{:?}", move_to_algebraic(mv));
+    }
+
+    #[test]
+    fn multipv_lines_are_sorted_and_led_by_the_best_move() {
+        let moves = parse_transcript("f5d6c3").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert!(white_to_move);
+        let lines = search_multipv(white, black, white_to_move, 3, 3, DEFAULT_CFG);
+        assert_eq!(lines.len(), 3);
+        // White to move: best first means lowest Black-frame eval first.
+        assert!(lines.windows(2).all(|w| w[0].1 <= w[1].1), "{:?}", lines);
+        let (_, best) =
+            search_moves_opt(white, black, white_to_move, 3, MIN_EVAL, MAX_EVAL, 3, DEFAULT_CFG);
+        assert_eq!(lines[0].1, best);
+        let all = search_multipv(white, black, white_to_move, 3, 64, DEFAULT_CFG);
+        assert_eq!(all.len(), find_legal_moves_alt(white, black, white_to_move).len());
+    }
+
+    #[test]
+    fn pruned_parallel_root_matches_serial_search() {
+        // Deep enough that the shared alpha cuts most late root moves.
+        for seed in 200..220 {
+            let (white, black, white_to_move) = random_endgame(seed, 11);
+            let depth = (!(white | black)).count_ones();
+            let (par_move, par) = search_moves_par(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            let (_, serial) = search_moves_opt(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            assert_eq!(par, serial, "seed {}", seed);
+            let (after_white, after_black) =
+                apply_move(white, black, par_move, white_to_move).unwrap();
+            let (_, reply) = search_moves_opt(
+                after_white,
+                after_black,
+                !white_to_move,
+                depth - 1,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth - 1,
+                DEFAULT_CFG,
+            );
+            // Off by one for won or lost positions: mate scores shrink per ply.
+            assert!((reply - par).abs() <= 1, "seed {}: chosen move is not the best", seed);
+        }
+    }
+
+    #[test]
+    fn raised_stop_flag_ends_the_search_with_a_legal_move() {
+        let (white, black, white_to_move) = (START_WHITE, START_BLACK, false);
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        let (mv, _, reached) = std::thread::scope(|s| {
+            let search = s.spawn(|| {
+                search_iterative_stop(white, black, white_to_move, 40, DEFAULT_CFG, &stop)
+            });
+            std::thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+            search.join().unwrap()
+        });
+        assert!(start.elapsed() < Duration::from_secs(5), "took {:?}", start.elapsed());
+        assert!(reached >= 1 && reached < 40, "reached depth {}", reached);
+        assert!(matches!(mv, SearchMove::Play(_)), "{:?}", mv);
+    }
+
+    #[test]
+    fn forced_win_prefers_the_shortest_line() {
+        // Every Black move here wins by force; g2 ends the game after 8
+        // moves, the others after 9.
+        let (white, black) = (0x103c_5df3_a1e8_a000, 0xe0c0_200c_1e17_1fff);
+        let g2 = 1u64 << 14;
+        for (mv, eval) in [
+            search_moves_opt(white, black, false, 10, MIN_EVAL, MAX_EVAL, 10, DEFAULT_CFG),
+            search_moves_par(white, black, false, 10, MIN_EVAL, MAX_EVAL, 10, DEFAULT_CFG),
+        ] {
+            assert_eq!(mv, g2);
+            assert!(eval > 0);
+            assert_eq!(mate_distance(eval), Some(8));
+        }
+        let (after_white, after_black) = apply_move(white, black, 1 << 30, false).unwrap();
+        let (_, slower) =
+            search_moves_opt(after_white, after_black, true, 9, MIN_EVAL, MAX_EVAL, 9, DEFAULT_CFG);
+        assert_eq!(mate_distance(slower), Some(8));
+    }
+
+    #[test]
+    fn corner_captures_are_searched_a_ply_deeper() {
+        // Black's only move is h3; White then has h1 plus three other
+        // moves, and after h1 Black has no corner to take back. At depth 2
+        // exactly one branch - h1 - gets extended.
+        let moves = parse_transcript("f5f4c3e6g4g3e7h4h2f2").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert!(!white_to_move);
+        // Each from an empty table, so the counts are the search's own.
+        let with = |corner_extension| EvalCfg {
+            corner_extension,
+            ..DEFAULT_CFG
+        };
+        let search = |cfg| {
+            let table = tt::TranspositionTable::new_mb(1);
+            search_moves_opt_stats_in(white, black, false, 2, MIN_EVAL, MAX_EVAL, 2, cfg, &table).2
+        };
+        let extended = search(with(true));
+        let plain = search(with(false));
+        assert_eq!(extended.corner_extensions, 1, "{:?}", extended);
+        assert_eq!(plain.corner_extensions, 0, "{:?}", plain);
+        assert!(extended.nodes > plain.nodes, "{:?} vs {:?}", extended, plain);
+    }
+
+    #[test]
+    fn next_iteration_starts_with_the_previous_best_move() {
+        let moves = parse_transcript("f5d6c3d3c4f4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let legal = find_legal_moves_alt(white, black, white_to_move);
+        for depth in 1..=4 {
+            let mut order = RootOrder::default();
+            let (best, _) = search_iterative_ordered(
+                white,
+                black,
+                white_to_move,
+                depth,
+                DEFAULT_CFG,
+                &mut order,
+            );
+            assert_eq!(SearchMove::Play(order.moves()[0]), best, "depth {}", depth);
+            let mut sorted = order.moves().to_vec();
+            sorted.sort_unstable();
+            assert_eq!(sorted, legal, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn transposition_table_survives_concurrent_stores() {
+        // Threads store overlapping keys into a small table while probing
+        // each other's. Every entry is a function of its key, so a torn
+        // write would show up as a hit with mismatched data.
+        let table = tt::TranspositionTable::new_mb(1);
+        let expected = |key: u64| {
+            let h = splitmix64(key);
+            ((h % 4001) as i32 - 2000, (h % 60) as i8, (h % 3) as u8 + 1, (h % 64) as u8)
+        };
+        std::thread::scope(|s| {
+            for t in 0..8u64 {
+                let table = &table;
+                s.spawn(move || {
+                    let mut rng = t;
+                    for _ in 0..200_000 {
+                        rng = splitmix64(rng);
+                        let key = splitmix64(rng % 50_000);
+                        if rng & 1 == 0 {
+                            let (score, depth, bound, move_sq) = expected(key);
+                            table.store(key, score, depth, bound, move_sq);
+                        } else if let Some(e) = table.probe(key) {
+                            assert_eq!((e.score, e.depth, e.bound, e.move_sq), expected(key));
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn effective_depth_solves_narrow_endgames() {
+        // Six empties: a1, b1 and e8-h8.
+        let empty: u64 = 0xF000_0000_0000_0003;
+        let black = !empty & 0x0000_FFFF_FFFF_FFFF;
+        let white = !empty & !black;
+        assert_eq!((!(white | black)).count_ones(), 6);
+        // Never deeper than the game goes.
+        assert_eq!(effective_depth(white, black, 6), 6);
+        assert_eq!(effective_depth(white, black, 10), 6);
+        // Within four plies of the end: solve it.
+        assert_eq!(effective_depth(white, black, 2), 6);
+        // Further out the request stands.
+        assert_eq!(effective_depth(white, black, 1), 1);
+        assert_eq!(effective_depth(START_WHITE, START_BLACK, 8), 8);
+        assert_eq!(effective_depth(START_WHITE, START_BLACK, 60), 60);
+    }
+
+    #[test]
+    fn parallel_split_depth_does_not_change_the_result() {
+        // Solved to the end, so every split must find the same eval, and
+        // ties are settled in move order rather than by thread timing.
+        for seed in 300..310 {
+            let (white, black, white_to_move) = random_endgame(seed, 10);
+            let depth = (!(white | black)).count_ones();
+            let search = |split| {
+                search_moves_par_split(
+                    white,
+                    black,
+                    white_to_move,
+                    depth,
+                    MIN_EVAL,
+                    MAX_EVAL,
+                    depth,
+                    DEFAULT_CFG,
+                    split,
+                )
+            };
+            let reference = search(1);
+            for split in 2..=4 {
+                assert_eq!(search(split), reference, "seed {} split {}", seed, split);
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_windows_report_bounds() {
+        // Solved positions, so the exact eval doesn't depend on the TT.
+        for seed in 400..410 {
+            let (white, black, white_to_move) = random_endgame(seed, 8);
+            let depth = (!(white | black)).count_ones();
+            let search = |alpha, beta| {
+                search_moves_opt_bound(
+                    white,
+                    black,
+                    white_to_move,
+                    depth,
+                    alpha,
+                    beta,
+                    depth,
+                    DEFAULT_CFG,
+                )
+            };
+            let (_, exact, bound) = search(MIN_EVAL, MAX_EVAL);
+            assert_eq!(bound, tt::BOUND_EXACT, "seed {}", seed);
+            let (_, high, bound) = search(exact - 2, exact - 1);
+            assert_eq!(bound, tt::BOUND_LOWER, "seed {}", seed);
+            assert!(high >= exact - 1 && high <= exact, "seed {}: {}", seed, high);
+            let (_, low, bound) = search(exact + 1, exact + 2);
+            assert_eq!(bound, tt::BOUND_UPPER, "seed {}", seed);
+            assert!(low >= exact && low <= exact + 1, "seed {}: {}", seed, low);
+            let (_, inside, bound) = search(exact - 1, exact + 1);
+            assert_eq!((inside, bound), (exact, tt::BOUND_EXACT), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn pvs_keeps_the_plain_alpha_beta_move() {
+        // Exact solves, where both searches pick the first root move
+        // with the best score. Each starts from an empty table.
+        let plain = EvalCfg {
+            pvs: false,
+            ..DEFAULT_CFG
+        };
+        let pvs = EvalCfg { pvs: true, ..plain };
+        let (mut plain_nodes, mut pvs_nodes) = (0, 0);
+        for seed in 500..600 {
+            let (white, black, white_to_move) = random_endgame(seed, 10);
+            let depth = (!(white | black)).count_ones();
+            let search = |cfg| {
+                search_moves_opt_stats_in(
+                    white,
+                    black,
+                    white_to_move,
+                    depth,
+                    MIN_EVAL,
+                    MAX_EVAL,
+                    depth,
+                    cfg,
+                    &tt::TranspositionTable::new_mb(1),
+                )
+            };
+            let (plain_move, plain_eval, plain_stats) = search(plain);
+            let (pvs_move, pvs_eval, pvs_stats) = search(pvs);
+            assert_eq!((pvs_move, pvs_eval), (plain_move, plain_eval), "seed {}", seed);
+            plain_nodes += plain_stats.nodes;
+            pvs_nodes += pvs_stats.nodes;
+        }
+        println!(
+            "nodes: alpha-beta {}, PVS {} ({:.1}%)",
+            plain_nodes,
+            pvs_nodes,
+            100.0 * pvs_nodes as f64 / plain_nodes.max(1) as f64
+        );
+    }
+
+    #[test]
+    fn mtdf_converges_on_the_full_window_score() {
+        for seed in 600..610 {
+            let (white, black, white_to_move) = random_endgame(seed, 10);
+            let depth = (!(white | black)).count_ones();
+            let (_, full) = search_moves_opt(
+                white,
+                black,
+                white_to_move,
+                depth,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth,
+                DEFAULT_CFG,
+            );
+            let (mv, eval) = mtdf(white, black, white_to_move, depth, 0, DEFAULT_CFG);
+            assert_eq!(eval, full, "seed {}", seed);
+            let (white_after, black_after) =
+                apply_move(white, black, mv, white_to_move).unwrap();
+            let (_, reply) = search_moves_opt(
+                white_after,
+                black_after,
+                !white_to_move,
+                depth - 1,
+                MIN_EVAL,
+                MAX_EVAL,
+                depth - 1,
+                DEFAULT_CFG,
+            );
+            // Off by one for won or lost positions: mate scores shrink per ply.
+            assert!((reply - full).abs() <= 1, "seed {}: chosen move is not the best", seed);
+            let (_, iterated) =
+                search_iterative_mtdf(white, black, white_to_move, depth, DEFAULT_CFG);
+            assert_eq!(iterated, full, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn mtdf_iterations_stop_on_the_flag() {
+        let (white, black, white_to_move) = random_endgame(601, 14);
+        let stop = AtomicBool::new(false);
+        let (_, _, reached) =
+            search_iterative_mtdf_stop(white, black, white_to_move, 6, DEFAULT_CFG, &stop);
+        assert_eq!(reached, 6);
+        // Raised from the start, only depth 1 completes.
+        stop.store(true, Ordering::Relaxed);
+        let (mv, _, reached) =
+            search_iterative_mtdf_stop(white, black, white_to_move, 6, DEFAULT_CFG, &stop);
+        assert_eq!(reached, 1);
+        assert!(matches!(mv, SearchMove::Play(_)), "{:?}", mv);
+    }
+
+    #[test]
+    fn parallel_search_breaks_ties_the_same_way_every_time() {
+        // The four opening moves are mirror images of each other, so they
+        // tie at every depth. Later runs start with the TT move of the
+        // earlier ones first, which must not change the pick.
+        let runs: Vec<(u64, i32)> = (0..20)
+            .map(|_| {
+                let (white, black) = (START_WHITE, START_BLACK);
+                search_moves_par(white, black, false, 4, MIN_EVAL, MAX_EVAL, 4, DEFAULT_CFG)
+            })
+            .collect();
+        assert!(runs.iter().all(|r| *r == runs[0]), "{:?}", runs);
+    }
+
+    #[cfg(feature = "multiplayer")]
+    #[test]
+    fn ponder_predicts_the_book_reply() {
+        let (white, black) = apply_move(START_WHITE, START_BLACK, sq("f5"), false).unwrap();
+        let mut book = OpeningBook::default();
+        let pos = Position {
+            white,
+            black,
+            white_to_move: true,
+        };
+        book.insert_position(pos, sq("d6"), 0);
+        let ponder = Ponder::start(&book, white, black, true, 3).unwrap();
+        assert!(ponder.finish(sq("d6")));
+        let ponder = Ponder::start(&book, white, black, true, 3).unwrap();
+        assert!(!ponder.finish(sq("f6")));
+    }
+
+    #[test]
+    fn seeded_opening_randomization_is_reproducible() {
+        // Absolute evals with White to move: d6 and f4 are within 20 of
+        // White's best (f6, -10), c5 is not.
+        let scored = [(sq("d6"), 5), (sq("c5"), 40), (sq("f6"), -10), (sq("f4"), 0)];
+        let entry = BookEntry {
+            suggested_moves: [sq("d6"), sq("f4"), sq("f6")]
+                .map(|mv| BookMove::new(mv, 0))
+                .to_vec(),
+            outcome: None,
+        };
+        let picks = |seed| {
+            let mut random = OpeningRandomizer::new(seed, 20);
+            (0..32)
+                .map(|_| {
+                    let (mv, _) =
+                        pick_within_margin(&scored, true, random.margin, &mut random.rng).unwrap();
+                    (mv, random.book_move(&entry))
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = picks(7);
+        assert_eq!(first, picks(7));
+        assert!(first.iter().all(|&(mv, _)| mv != sq("c5")));
+        for candidate in [sq("d6"), sq("f4"), sq("f6")] {
+            assert!(first.iter().any(|&(mv, _)| mv == candidate));
+            assert!(first.iter().any(|&(_, mv)| mv == Some(candidate)));
+        }
+    }
+
+    #[test]
+    fn principal_variation_follows_the_searched_cfg() {
+        let cfg = EvalCfg {
+            corner_value: 90,
+            frontier_value: 3,
+            ..DEFAULT_CFG
+        };
+        assert_ne!(eval_cfg_key(&cfg), eval_cfg_key(&DEFAULT_CFG));
+        let (white, black, white_to_move) =
+            replay_transcript(&parse_transcript("f5d6c3d3c4").unwrap()).unwrap();
+        let table = tt::TranspositionTable::new_mb(1);
+        let (lo, hi) = (MIN_EVAL, MAX_EVAL);
+        let (mv, _) = search_moves_opt_in(white, black, white_to_move, 5, lo, hi, 5, cfg, &table);
+        let pv = expected_line(white, black, white_to_move, mv, cfg, &table);
+        assert_eq!(pv[0], mv);
+        assert!(pv.len() > 1, "{:?}", pv);
+        // Probed under another config's keys, the table has nothing.
+        let other = expected_line(white, black, white_to_move, mv, DEFAULT_CFG, &table);
+        assert_eq!(other, [mv]);
+    }
+
+    #[test]
+    fn large_heuristic_evals_are_not_mate_scores() {
+        // After f5 Black is three discs up, worth 6000 at these weights:
+        // past the mate threshold, so unclamped it would be shrunk each
+        // ply and reported as a forced win.
+        let cfg = EvalCfg {
+            disc_values: [2000; 3],
+            ..DEFAULT_CFG
+        };
+        let moves = parse_transcript("f5").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert_eq!(eval_position_with_cfg(white, black, white_to_move, cfg), MAX_HEURISTIC_EVAL);
+        for depth in 1..=3 {
+            let (_, eval) = search_fresh(white, black, white_to_move, depth, cfg);
+            assert!(eval.abs() <= MAX_HEURISTIC_EVAL, "depth {}: {}", depth, eval);
+            assert_eq!(mate_distance(eval), None, "depth {}: {}", depth, eval);
+        }
+
+        // A config can lower the bound, but not raise it past the mate
+        // threshold.
+        let lower = EvalCfg {
+            max_heuristic_eval: 300,
+            ..cfg
+        };
+        assert_eq!(eval_position_with_cfg(white, black, white_to_move, lower), 300);
+        let (_, eval) = search_fresh(white, black, white_to_move, 2, lower);
+        assert!(eval.abs() <= 300, "{}", eval);
+        let higher = EvalCfg {
+            max_heuristic_eval: 2 * MAX_HEURISTIC_EVAL,
+            ..cfg
+        };
+        let clamped = eval_position_with_cfg(white, black, white_to_move, higher);
+        assert_eq!(clamped, MAX_HEURISTIC_EVAL);
+    }
+
+    #[test]
+    fn parallel_and_serial_search_agree_at_shallow_depths() {
+        // Positions from every phase of the game. Without corner
+        // extensions a position is always searched at the same remaining
+        // depth, so TT hits can't make the two searches disagree; each
+        // starts from an empty table.
+        let cfg = EvalCfg {
+            corner_extension: false,
+            ..DEFAULT_CFG
+        };
+        for seed in 700..740 {
+            let (white, black, white_to_move) = random_endgame(seed, 20 + seed as u32 % 40);
+            for depth in 1..=3 {
+                let search = |parallel: bool| {
+                    let table = tt::TranspositionTable::new_mb(1);
+                    let search = if parallel { search_moves_par_in } else { search_moves_opt_in };
+                    let (lo, hi) = (MIN_EVAL, MAX_EVAL);
+                    search(white, black, white_to_move, depth, lo, hi, depth, cfg, &table).1
+                };
+                assert_eq!(search(true), search(false), "seed {} depth {}", seed, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn root_move_node_counts_add_up_to_the_search() {
+        let moves = parse_transcript("f5d6c3d3c4f4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let table = tt::TranspositionTable::new_mb(1);
+        let (mv, eval, stats, roots) =
+            search_moves_opt_root_nodes_in(white, black, white_to_move, 5, DEFAULT_CFG, &table);
+        let mut searched: Vec<u64> = roots.iter().map(|r| r.0).collect();
+        searched.sort_unstable();
+        assert_eq!(searched, find_legal_moves_alt(white, black, white_to_move));
+        // Everything but the root node itself belongs to some root move.
+        assert_eq!(roots.iter().map(|r| r.2).sum::<u64>() + 1, stats.nodes);
+        assert!(roots.iter().any(|r| (r.0, r.1) == (mv, eval)), "{:?}", roots);
+    }
+
+    #[test]
+    fn mobility_swings_the_eval_towards_the_freer_side() {
+        // Black to move with 10 moves against White's 2, in the opening.
+        let moves = parse_transcript("f5d6c5f6e6b6").unwrap();
+        let (white, black, _) = replay_transcript(&moves).unwrap();
+        assert_eq!(find_legal_moves_alt(white, black, false).len(), 10);
+        assert_eq!(find_legal_moves_alt(white, black, true).len(), 2);
+        let immobile = EvalCfg {
+            mobility_values: [0; 3],
+            ..DEFAULT_CFG
+        };
+        let swing = eval_position_with_cfg(white, black, false, DEFAULT_CFG)
+            - eval_position_with_cfg(white, black, false, immobile);
+        assert_eq!(swing, 8 * DEFAULT_CFG.mobility_values[0]);
+    }
+
+    #[test]
+    fn frontier_discs_count_discs_next_to_empties() {
+        // A 3x3 block c3-e5 on an empty board: all but d4 touch an empty.
+        let block = 0x0000_001c_1c1c_0000u64;
+        assert_eq!(frontier_discs(block, !block), 8);
+        // Everything but a1 filled: only a2, b1 and b2 touch it.
+        let a1 = 1u64;
+        assert_eq!(frontier_discs(!a1, a1), 3);
+        // The H file next to an empty A file: neighbours by bit index
+        // only, since the shifts must not wrap around the board.
+        let a_file = 0x0101_0101_0101_0101u64;
+        let h_file = a_file << 7;
+        assert_eq!(frontier_discs(h_file, a_file), 0);
+        assert_eq!(frontier_discs(a_file, h_file), 0);
+        assert_eq!(frontier_discs(a_file, a_file << 1), 8);
+    }
+
+    #[test]
+    fn stable_discs_grow_from_corners_and_full_lines() {
+        let squares = |list: &[&str]| list.iter().fold(0, |bb, s| bb | sq(s));
+        // A lone corner is stable; the disc diagonally inside it is not.
+        let player = squares(&["a1", "b2", "d4"]);
+        assert_eq!(stable_discs(player, squares(&["e5"])), sq("a1"));
+        // An edge run from the corner, up to an opponent disc that can
+        // still be outflanked along the open edge.
+        let player = squares(&["a1", "b1", "c1"]);
+        let opponent = squares(&["d1"]);
+        assert_eq!(stable_discs(player, opponent), player);
+        assert_eq!(stable_discs(opponent, player), 0);
+        // A full edge: every disc on it, of either colour, is stable.
+        let player = squares(&["a1", "b1", "c1", "g1", "h1", "d4"]);
+        let opponent = squares(&["d1", "e1", "f1", "e5"]);
+        assert_eq!(stable_discs(player, opponent), player & !sq("d4"));
+        assert_eq!(stable_discs(opponent, player), opponent & !sq("e5"));
+    }
+
+    #[test]
+    fn phased_eval_follows_the_endgame_config_on_a_full_board() {
+        // Only disc counts are weighed: against them in the opening
+        // config, as the tuned defaults do, and for them in the endgame.
+        let discs_only = EvalCfg {
+            corner_value: 0,
+            edge_value: 0,
+            antiedge_value: 0,
+            anticorner_value: 0,
+            disc_values: [0; 3],
+            mobility_values: [0; 3],
+            ..DEFAULT_CFG
+        };
+        let opening = EvalCfg {
+            disc_values: [-50; 3],
+            ..discs_only
+        };
+        let endgame = EvalCfg {
+            disc_values: [10; 3],
+            ..discs_only
+        };
+        // 62 discs, Black 18 up.
+        let black = (1u64 << 40) - 1;
+        let white = ((1u64 << 62) - 1) & !black;
+        let early = eval_position_with_cfg(white, black, false, opening);
+        let late = eval_position_with_cfg(white, black, false, endgame);
+        assert_eq!((early, late), (-900, 180));
+        let phased = eval_phased(white, black, false, opening, endgame);
+        assert!(phased > 0 && (late - phased) < (phased - early), "{}", phased);
+    }
+
+    #[test]
+    fn square_weight_tables_match_the_masks_and_rotate_with_the_board() {
+        let positional_only = EvalCfg {
+            disc_values: [0; 3],
+            mobility_values: [0; 3],
+            ..DEFAULT_CFG
+        };
+        let derived = square_weights(&DEFAULT_CFG);
+        // The masks overlap: b2 is in both the edge and anticorner masks.
+        assert_eq!(derived[0], DEFAULT_CFG.corner_value);
+        assert_eq!(derived[9], DEFAULT_CFG.edge_value + DEFAULT_CFG.anticorner_value);
+        // Corners cleared: the table is the all-corners-empty view.
+        for seed in 800..810 {
+            let (white, black, _) = random_endgame(seed, 30);
+            let (white, black) = (white & !0x8100_0000_0000_0081, black & !0x8100_0000_0000_0081);
+            assert_eq!(
+                eval_position_table(white, black, &derived),
+                eval_position_with_cfg(white, black, false, positional_only),
+                "seed {}",
+                seed
+            );
+        }
+
+        // A lopsided table, so a wrong rotation can't cancel out.
+        let mut weights = [0; 64];
+        for (sq, w) in weights.iter_mut().enumerate() {
+            *w = (sq as i32 * 7) % 23 - 11;
+        }
+        let mut rotated = [0; 64];
+        for (sq, &w) in weights.iter().enumerate() {
+            let bit = Position {
+                black: 1u64 << sq,
+                white: 0,
+                white_to_move: false,
+            };
+            rotated[rotate_position_90(&bit).black.trailing_zeros() as usize] = w;
+        }
+        for seed in 810..820 {
+            let (white, black, white_to_move) = random_endgame(seed, 40);
+            let pos = Position {
+                black,
+                white,
+                white_to_move,
+            };
+            let turned = rotate_position_90(&pos);
+            assert_eq!(
+                eval_position_table(turned.white, turned.black, &rotated),
+                eval_position_table(white, black, &weights),
+                "seed {}",
+                seed
+            );
+        }
+
+        // Written like a diagram: a8 first, h1 last.
+        let text: String = (0..64)
+            .map(|i| format!("{}{}", i, if i % 8 == 7 { "\n" } else { ", " }))
+            .collect();
+        let parsed = parse_square_weights(&text).unwrap();
+        assert_eq!((parsed[56], parsed[63], parsed[0], parsed[7]), (0, 7, 56, 63));
+        assert!(parse_square_weights("1 2 3").is_err());
+    }
+
+    #[test]
+    fn owning_a_corner_lifts_its_x_and_c_square_penalties() {
+        let penalties_only = EvalCfg {
+            corner_value: 0,
+            edge_value: 0,
+            antiedge_value: -21,
+            anticorner_value: -30,
+            disc_values: [0; 3],
+            mobility_values: [0; 3],
+            ..DEFAULT_CFG
+        };
+        // Black on b2 (X) and b1 (C) next to a1; White on g7 next to h8.
+        let (b1, b2, g7) = (1u64 << 1, 1u64 << 9, 1u64 << 54);
+        let (a1, h8) = (1u64, 1u64 << 63);
+        assert_eq!(eval_position_with_cfg(g7, b1 | b2, false, penalties_only), -51 + 30);
+        // Either side taking a1 makes Black's squares safe.
+        assert_eq!(eval_position_with_cfg(g7, a1 | b1 | b2, false, penalties_only), 30);
+        assert_eq!(eval_position_with_cfg(a1 | g7, b1 | b2, false, penalties_only), 30);
+        // h8 is unaffected by a1 and vice versa.
+        assert_eq!(eval_position_with_cfg(g7 | h8, b1 | b2, false, penalties_only), -51);
+    }
+
+    #[test]
+    fn eval_configs_round_trip_through_a_file() {
+        let path = std::env::temp_dir().join(format!("eval-cfg-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let cfg = EvalCfg {
+            corner_value: 80,
+            disc_values: [-3, 0, 5],
+            frontier_value: 4,
+            pvs: false,
+            ..DEFAULT_CFG
+        };
+        cfg.save_to_file(path).unwrap();
+        assert_eq!(EvalCfg::load_from_file(path).unwrap(), cfg);
+
+        // Missing fields keep their defaults; unknown ones are refused.
+        std::fs::write(path, r#"{ "stable_value": 9 }"#).unwrap();
+        let partial = EvalCfg::load_from_file(path).unwrap();
+        let expected = EvalCfg {
+            stable_value: 9,
+            ..DEFAULT_CFG
+        };
+        assert_eq!(partial, expected);
+        std::fs::write(path, r#"{ "corner_valeu": 9 }"#).unwrap();
+        let err = EvalCfg::load_from_file(path).unwrap_err();
+        assert!(err.to_string().contains("corner_valeu"), "{}", err);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn disc_difference_eval_is_the_final_margin_on_a_full_board() {
+        let mut full_boards = 0;
+        for seed in 820..840 {
+            let (mut white, mut black, mut white_to_move) = random_endgame(seed, 12);
+            loop {
+                let legal = check_game_status(white, black, white_to_move);
+                if legal >= u64::MAX - 3 && legal != u64::MAX {
+                    break;
+                }
+                if legal != u64::MAX {
+                    let mv = legal_moves(white, black, white_to_move).next().unwrap();
+                    (white, black) = apply_move(white, black, mv, white_to_move).unwrap();
+                }
+                white_to_move = !white_to_move;
+            }
+            if white | black != u64::MAX {
+                continue;
+            }
+            full_boards += 1;
+            let margin = black.count_ones() as i32 - white.count_ones() as i32;
+            assert_eq!(eval_disc_difference(white, black), margin);
+        }
+        assert!(full_boards > 0);
+
+        // With the mode on throughout, the search scores its leaves by
+        // disc difference alone.
+        let cfg = EvalCfg {
+            disc_only_empties: 64,
+            corner_extension: false,
+            ..DEFAULT_CFG
+        };
+        let moves = parse_transcript("f5f6e6f4e3").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let disc_only = eval_position_with_cfg(white, black, white_to_move, cfg);
+        assert_eq!(disc_only, eval_disc_difference(white, black));
+        for depth in 1..=3 {
+            let (_, eval) = search_fresh(white, black, white_to_move, depth, cfg);
+            assert_eq!(eval, minimax(white, black, white_to_move, depth, cfg), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn tempo_bonus_goes_to_the_side_to_move() {
+        let moves = parse_transcript("f5d6c3d3c4").unwrap();
+        let (white, black, _) = replay_transcript(&moves).unwrap();
+        let with_tempo = EvalCfg {
+            tempo_value: 9,
+            ..DEFAULT_CFG
+        };
+        let tempo = |white_to_move| {
+            eval_position_with_cfg(white, black, white_to_move, with_tempo)
+                - eval_position_with_cfg(white, black, white_to_move, DEFAULT_CFG)
+        };
+        assert_eq!(tempo(false), 9);
+        assert_eq!(tempo(true), -9);
+    }
+
+    #[test]
+    fn eval_breakdown_sums_to_the_eval() {
+        let every_term = EvalCfg {
+            potential_mobility_value: 2,
+            frontier_value: 3,
+            stable_value: 5,
+            tempo_value: 4,
+            ..DEFAULT_CFG
+        };
+        let disc_only = EvalCfg {
+            disc_only_empties: 20,
+            ..every_term
+        };
+        for seed in 840..860 {
+            let (white, black, white_to_move) = random_endgame(seed, 10 + (seed as u32 % 40));
+            for cfg in [DEFAULT_CFG, every_term, disc_only] {
+                let terms = eval_breakdown(white, black, white_to_move, cfg);
+                let eval = eval_position_with_cfg(white, black, white_to_move, cfg);
+                assert_eq!(terms.total, eval, "seed {}", seed);
+                assert_eq!(
+                    terms.black.sum() - terms.white.sum() + terms.tempo,
+                    eval,
+                    "seed {}",
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn eval_is_invariant_under_board_symmetries() {
+        let every_term = EvalCfg {
+            potential_mobility_value: 2,
+            frontier_value: 3,
+            stable_value: 5,
+            tempo_value: 4,
+            ..DEFAULT_CFG
+        };
+        for seed in 860..880 {
+            let (white, black, white_to_move) = random_endgame(seed, 8 + (seed as u32 % 48));
+            let pos = Position {
+                black,
+                white,
+                white_to_move,
+            };
+            let rot90 = rotate_position_90(&pos);
+            let rot180 = rotate_position_90(&rot90);
+            let images = [
+                rot90,
+                rot180,
+                rotate_position_90(&rot180),
+                flip_position_vertical(&pos),
+                flip_position_horizontal(&pos),
+            ];
+            for cfg in [DEFAULT_CFG, every_term] {
+                let eval = eval_position_with_cfg(white, black, white_to_move, cfg);
+                for (i, image) in images.iter().enumerate() {
+                    assert_eq!(
+                        eval_position_with_cfg(image.white, image.black, white_to_move, cfg),
+                        eval,
+                        "seed {} image {}",
+                        seed,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transposed_leaves_are_evaluated_once() {
+        let cache = evalcache::EvalCache::new_mb(1);
+        let evaluations = std::cell::Cell::new(0);
+        let cfg_key = eval_cfg_key(&DEFAULT_CFG);
+        let mut evals = Vec::new();
+        for line in ["d3c3c4e3", "c4c3d3e3"] {
+            let moves = parse_transcript(line).unwrap();
+            let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+            assert!(!white_to_move);
+            let key = tt::hash_position(black, white) ^ cfg_key;
+            evals.push(cache.get_or_eval::<false>(key, || {
+                evaluations.set(evaluations.get() + 1);
+                eval_position_with_cfg(white, black, white_to_move, DEFAULT_CFG)
+            }));
+        }
+        assert_eq!(evaluations.get(), 1);
+        assert_eq!(evals[0], evals[1]);
+    }
+
+    #[test]
+    fn wipeout_bonus_is_steep_but_short_of_a_win() {
+        // Black holds the bottom four ranks, White a single disc on a6.
+        let (white, black) = (1u64 << 40, 0xFFFF_FFFFu64);
+        let wipeout = EvalCfg {
+            wipeout_discs: 5,
+            wipeout_value: 100,
+            ..DEFAULT_CFG
+        };
+        let bonus = eval_position_with_cfg(white, black, false, wipeout)
+            - eval_position_with_cfg(white, black, false, DEFAULT_CFG);
+        assert_eq!(bonus, 4 * 100);
+        assert_eq!(eval_breakdown(white, black, false, wipeout).black.wipeout, 400);
+
+        let crushing = EvalCfg {
+            wipeout_value: 3000,
+            ..wipeout
+        };
+        let win_score = margin_to_eval(1) - 1;
+        let eval = eval_position_with_cfg(white, black, false, crushing);
+        assert_eq!(eval, MAX_HEURISTIC_EVAL);
+        assert!(eval < win_score);
+        // Mirrored, the bonus goes to White.
+        assert_eq!(eval_position_with_cfg(black, white, false, crushing), -MAX_HEURISTIC_EVAL);
+    }
+
+    #[test]
+    fn mask_evaluator_matches_the_cfg_eval() {
+        let cfg = EvalCfg {
+            tempo_value: 6,
+            corner_extension: false,
+            ..DEFAULT_CFG
+        };
+        let evaluator = MaskEvaluator(cfg);
+        for seed in 880..900 {
+            let (white, black, white_to_move) = random_endgame(seed, 10 + (seed as u32 % 40));
+            for side in [false, true] {
+                assert_eq!(
+                    evaluator.eval(white, black, side),
+                    eval_position_with_cfg(white, black, side, cfg),
+                    "seed {}",
+                    seed
+                );
+            }
+            assert_eq!(
+                evaluator.eval(white, black, white_to_move),
+                -evaluator.eval(black, white, !white_to_move)
+            );
+        }
+
+        // Plugged into the search, it scores leaves the way the
+        // reference minimax does.
+        let moves = parse_transcript("f5f6e6f4e3").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        for depth in 1..=3 {
+            let table = tt::TranspositionTable::new_mb(1);
+            let (_, eval) = search_with_evaluator_in(
+                white,
+                black,
+                white_to_move,
+                depth,
+                cfg,
+                &evaluator,
+                &table,
+            );
+            assert_eq!(eval, minimax(white, black, white_to_move, depth, cfg), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn shallow_tt_entries_do_not_answer_deeper_searches() {
+        let table = tt::TranspositionTable::new_mb(1);
+        let key = tt::hash_position(START_BLACK, START_WHITE);
+        table.store(key, 12, 2, tt::BOUND_EXACT, 19);
+        let entry = table.probe(key).unwrap();
+        assert!(entry.usable_at(2));
+        assert!(!entry.usable_at(4));
+        // The move is still there for ordering.
+        assert_eq!(entry.move_sq, 19);
+    }
+
+    #[test]
+    fn shallow_stores_keep_a_deeper_entry_in_the_slot() {
+        let table = tt::TranspositionTable::new_mb(1);
+        let deep = tt::hash_position(START_BLACK, START_WHITE);
+        table.store(deep, 40, 8, tt::BOUND_EXACT, 19);
+        table.store(deep, 7, 3, tt::BOUND_LOWER, 26);
+        let entry = table.probe(deep).unwrap();
+        assert_eq!((entry.score, entry.depth, entry.move_sq), (40, 8, 19));
+        // As deep or deeper replaces it.
+        table.store(deep, 12, 8, tt::BOUND_UPPER, 26);
+        let entry = table.probe(deep).unwrap();
+        assert_eq!((entry.score, entry.depth, entry.move_sq), (12, 8, 26));
+        // Same low bits, so the same slot, but another position: it takes
+        // the slot however shallow.
+        let other = deep ^ (1 << 63);
+        table.store(other, -5, 2, tt::BOUND_EXACT, 37);
+        assert!(table.probe(deep).is_none());
+        assert_eq!(table.probe(other).unwrap().score, -5);
+    }
+
+    #[test]
+    fn stale_tt_entries_are_evicted_even_when_deeper() {
+        let table = tt::TranspositionTable::new_mb(1);
+        let old = tt::hash_position(START_BLACK, START_WHITE);
+        let new = old ^ (1 << 63);
+        table.store(old, 40, 12, tt::BOUND_EXACT, 19);
+        // A new root search: the deep entry is from an older age now.
+        table.new_age();
+        table.store(new, -5, 1, tt::BOUND_UPPER, 37);
+        assert!(table.probe(old).is_none());
+        let entry = table.probe(new).unwrap();
+        assert_eq!((entry.score, entry.depth), (-5, 1));
+        // Within the same age it holds its ground against a shallower
+        // result for the same position.
+        table.store(new, 3, 0, tt::BOUND_EXACT, 19);
+        assert_eq!(table.probe(new).unwrap().depth, 1);
+    }
+
+    #[test]
+    fn tt_slot_indices_stay_inside_the_table() {
+        for mb in [0, 1, 3, 5, 16] {
+            let table = tt::TranspositionTable::new_mb(mb);
+            assert!(table.entries().is_power_of_two());
+            let mut key = mb as u64;
+            for _ in 0..10_000 {
+                key = splitmix64(key);
+                assert!(table.slot_index(key) < table.entries());
+            }
+            assert_eq!(table.slot_index(u64::MAX), table.entries() - 1);
+        }
+    }
+
+    #[test]
+    fn tt_sizes_round_down_to_a_power_of_two_that_fits() {
+        let slot = std::mem::size_of::<tt::TTSlot>();
+        for mb in [1, 3, 5, 48] {
+            let entries = tt::TranspositionTable::new_mb(mb).entries();
+            let bytes = mb * 1024 * 1024;
+            assert!(entries.is_power_of_two());
+            assert!(entries * slot <= bytes, "{} MB", mb);
+            assert!(entries * 2 * slot > bytes, "{} MB", mb);
+        }
+    }
+
+    #[test]
+    fn cleared_tt_misses_every_probe() {
+        // Matches reuse the one global table and clear it rather than
+        // allocating a fresh one; nothing may survive the clear.
+        let table = tt::TranspositionTable::new_mb(1);
+        let keys: Vec<u64> = (0..5000u64).map(splitmix64).collect();
+        for &key in &keys {
+            table.store(key, 1, 4, tt::BOUND_EXACT, 0);
+        }
+        assert!(keys.iter().any(|&key| table.probe(key).is_some()));
+        table.clear();
+        assert!(keys.iter().all(|&key| table.probe(key).is_none()));
+    }
+
+    #[test]
+    fn hashfull_tracks_tt_occupancy() {
+        let table = tt::TranspositionTable::new_mb(1);
+        assert_eq!(table.hashfull(), 0);
+        // Slot indices are the key's low bits: fill the first 1000.
+        for i in 0..1000u64 {
+            table.store(i | (1 << 40), 1, 1, tt::BOUND_EXACT, 0);
+        }
+        assert_eq!(table.hashfull(), 1000);
+        assert!(table.collides(7 | (1 << 41)));
+        assert!(!table.collides(7 | (1 << 40)));
+        assert!(!table.collides(5000));
+        table.clear();
+        for i in (0..1000u64).step_by(2) {
+            table.store(i | (1 << 40), 1, 1, tt::BOUND_EXACT, 0);
+        }
+        assert_eq!(table.hashfull(), 500);
+
+        // A counting search reports its TT traffic.
+        let moves = parse_transcript("f5d6c3d3c4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        let (_, _, stats) = search_iterative_stats(white, black, white_to_move, 5, DEFAULT_CFG);
+        assert!(stats.tt_probes > 0 && stats.tt_hits > 0, "{:?}", stats);
+        assert!(stats.tt_hits + stats.tt_collisions <= stats.tt_probes, "{:?}", stats);
+    }
+
+    #[test]
+    fn tt_bounds_cut_on_the_matching_side_of_the_window() {
+        // Entries planted in a table of its own, which no other test
+        // searches. Black to move: the us-frame is the absolute one.
+        let cfg = DEFAULT_CFG;
+        let table = tt::TranspositionTable::new_mb(1);
+        let moves = parse_transcript("f5d6c3d3c4f4").unwrap();
+        let (white, black, white_to_move) = replay_transcript(&moves).unwrap();
+        assert!(!white_to_move);
+        let key = tt::hash_position(black, white) ^ eval_cfg_key(&cfg);
+        let e6 = 44u8;
+        let search = |alpha, beta| {
+            search_moves_opt_in(white, black, false, 2, alpha, beta, 2, cfg, &table).1
+        };
+
+        // Failed high at 300: answers any window whose beta it reaches.
+        table.store(key, 300, 10, tt::BOUND_LOWER, e6);
+        assert_eq!(search(MIN_EVAL, 250), 300);
+        assert_eq!(search(-100, 300), 300);
+        // Failed low at -300: answers windows whose alpha it reaches.
+        table.store(key, -300, 11, tt::BOUND_UPPER, e6);
+        assert_eq!(search(-250, MAX_EVAL), -300);
+        assert_eq!(search(-300, 100), -300);
+        // An exact score answers every window.
+        table.store(key, 77, 12, tt::BOUND_EXACT, e6);
+        assert_eq!(search(MIN_EVAL, MAX_EVAL), 77);
+        assert_eq!(search(100, 200), 77);
+    }
+
+    #[test]
+    fn saved_tt_answers_the_same_probes_after_loading() {
+        let path = std::env::temp_dir().join(format!("tt-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let table = tt::TranspositionTable::new_mb(1);
+        let keys: Vec<u64> = (0..3000u64).map(splitmix64).collect();
+        for (i, &key) in keys.iter().enumerate() {
+            let bound = [tt::BOUND_EXACT, tt::BOUND_LOWER, tt::BOUND_UPPER][i % 3];
+            table.store(key, i as i32 - 1500, (i % 20) as i8, bound, (i % 65) as u8);
+        }
+        table.save_to_file(path).unwrap();
+        let loaded = tt::TranspositionTable::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.entries(), table.entries());
+        let answer = |t: &tt::TranspositionTable, key| {
+            t.probe(key).map(|d| (d.score, d.depth, d.bound, d.move_sq))
+        };
+        for key in keys.iter().copied().chain((5000..5100u64).map(splitmix64)) {
+            assert_eq!(answer(&loaded, key), answer(&table, key));
+        }
+        assert_eq!(loaded.hashfull(), table.hashfull());
+    }
+
+    #[test]
+    fn side_to_move_changes_the_position_hash() {
+        for seed in 900..920 {
+            let (white, black, _) = random_endgame(seed, 30);
+            // Search keys: (us, them) is (white, black) with White to move.
+            assert_ne!(tt::hash_position(white, black), tt::hash_position(black, white));
+            let pos = Position { black, white, white_to_move: false };
+            let other_side = Position { white_to_move: true, ..pos };
+            assert_ne!(canonical_hash(&pos), canonical_hash(&other_side));
+            assert_eq!(canonical_hash(&pos) ^ canonical_hash(&other_side), tt::SIDE_TO_MOVE_KEY);
+        }
+    }
+
+    #[test]
+    fn canonical_board_hash_is_shared_by_all_symmetries() {
+        for seed in 920..940 {
+            let (white, black, white_to_move) = random_endgame(seed, 40);
+            let pos = Position { black, white, white_to_move };
+            let hash = canonical_board_hash(white, black, white_to_move);
+            assert_eq!(hash, canonical_hash(&pos));
+            for image in symmetries(&pos) {
+                assert_eq!(canonical_board_hash(image.white, image.black, white_to_move), hash);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_tt_probes_never_see_torn_entries() {
+        // Every key has one payload; threads fight over 64 slots with
+        // thousands of keys, so a probe that mixed two writes would
+        // surface as a payload that does not belong to its key.
+        let table = tt::TranspositionTable::new_mb(1);
+        let key_of = |t: u64, i: u64| (splitmix64((t << 32) | i) & !0xFFFF_FFFF) | (i & 0x3F);
+        let payload = |key: u64| {
+            let h = splitmix64(key);
+            ((h % 2000) as i32 - 1000, (h >> 16) as i8 & 63, (h >> 24) as u8 % 64)
+        };
+        std::thread::scope(|scope| {
+            for t in 0..4u64 {
+                let (table, key_of, payload) = (&table, &key_of, &payload);
+                scope.spawn(move || {
+                    for i in 0..200_000u64 {
+                        let key = key_of(t, i);
+                        let (score, depth, move_sq) = payload(key);
+                        table.store(key, score, depth, tt::BOUND_EXACT, move_sq);
+                        let probe_key = key_of((t + 1) % 4, i);
+                        if let Some(d) = table.probe(probe_key) {
+                            assert_eq!((d.score, d.depth, d.move_sq), payload(probe_key));
+                            assert_eq!(d.bound, tt::BOUND_EXACT);
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// The position before `line`'s last move, and that move.
+    fn line_position(line: &str) -> (Position, u64) {
+        let moves = parse_transcript(line).unwrap();
+        let (&last, played) = moves.split_last().unwrap();
+        let (white, black, white_to_move) = replay_transcript(played).unwrap();
+        let pos = Position {
+            black,
+            white,
+            white_to_move,
+        };
+        (pos, last)
+    }
+
+    /// A book holding the last move of each of `lines` (transcripts from
+    /// the standard position) under the position it is played from, with
+    /// eval 0. A line given twice weighs 2.
+    fn book_from_lines(lines: &[&str]) -> OpeningBook {
+        let mut book = OpeningBook::default();
+        for line in lines {
+            let (pos, last) = line_position(line);
+            book.insert_position(pos, last, 0);
+        }
+        book
+    }
+
+    #[test]
+    fn binary_book_round_trips_with_identical_entries() {
+        let path = std::env::temp_dir().join(format!("book-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut book = book_from_lines(&["f5d6c3d3c4", "f5f6e6f4", "f5d6c5f4e3"]);
+        let solved = *book.entries.keys().next().unwrap();
+        book.set_outcome(&solved, GameStatus::Draw);
+
+        book.save(path, BookFormat::Binary).unwrap();
+        let loaded = OpeningBook::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded.entries.len(), book.entries.len());
+        for (pos, entry) in &book.entries {
+            let got = loaded.get(pos).unwrap();
+            assert_eq!(got.suggested_moves, entry.suggested_moves);
+            assert_eq!(got.outcome, entry.outcome);
+        }
+        assert_eq!(loaded.outcome(&solved), Some(GameStatus::Draw));
+    }
+
+    #[test]
+    fn book_entries_keep_each_moves_eval() {
+        let (white, black) = apply_move(START_WHITE, START_BLACK, sq("f5"), false).unwrap();
+        let pos = Position { black, white, white_to_move: true };
+        let mut book = OpeningBook::default();
+        book.insert_position(pos, sq("d6"), -4);
+        book.insert_position(pos, sq("f6"), -10);
+        let moves = &book.get(&pos).unwrap().suggested_moves;
+        assert_eq!(moves, &[BookMove::new(sq("d6"), -4), BookMove::new(sq("f6"), -10)]);
+        // Re-inserting refreshes the eval in place and counts the
+        // selection.
+        book.insert_position(pos, sq("d6"), 2);
+        let moves = &book.get(&pos).unwrap().suggested_moves;
+        let d6 = BookMove { mv: sq("d6"), eval: 2, weight: 2 };
+        assert_eq!(moves, &[d6, BookMove::new(sq("f6"), -10)]);
+
+        // Books from before evals list bare moves.
+        let old: BookEntry = serde_json::from_str(r#"{ "suggested_moves": [524288] }"#).unwrap();
+        assert_eq!(old.suggested_moves, [BookMove::new(524288, 0)]);
+        let new = serde_json::to_string(&book.get(&pos).unwrap()).unwrap();
+        let reloaded: BookEntry = serde_json::from_str(&new).unwrap();
+        assert_eq!(&reloaded.suggested_moves, moves);
+    }
+
+    #[test]
+    fn book_visits_count_in_the_canonical_entry() {
+        let (white, black) = apply_move(START_WHITE, START_BLACK, sq("f5"), false).unwrap();
+        let pos = Position { black, white, white_to_move: true };
+        let mut book = OpeningBook::default();
+        book.insert_canonical(pos, sq("d6"), 0);
+        let canonical = canonical_position(&pos);
+        let weight = |book: &OpeningBook| book.get(&canonical).unwrap().suggested_moves[0].weight;
+        assert_eq!(weight(&book), 1);
+
+        // Every symmetric image of `pos` lands on the one stored entry.
+        let images = symmetries(&pos);
+        for (k, image) in images.iter().enumerate() {
+            let mv = book.get_canonical(image).unwrap().suggested_moves[0].mv;
+            assert_eq!(book.record_visit(image, mv), Some(canonical));
+            assert_eq!(weight(&book), 2 + k as u32);
+        }
+        assert_eq!(book.record_visit(&pos, sq("f6")), None);
+        assert_eq!(weight(&book), 1 + images.len() as u32);
+
+        // So does re-selecting the move.
+        book.insert_canonical(pos, sq("d6"), 4);
+        assert_eq!(weight(&book), 2 + images.len() as u32);
+        assert_eq!(book.prune(3), 0);
+    }
+
+    #[test]
+    fn book_moves_are_drawn_in_proportion_to_their_weights() {
+        let mut entry = BookEntry {
+            suggested_moves: (0..4).map(|sq| BookMove::new(1 << sq, 0)).collect(),
+            outcome: None,
+        };
+        let counts = |entry: &BookEntry, seed| {
+            let mut rng = Rng64::new(seed);
+            let mut counts = [0u32; 4];
+            for _ in 0..4000 {
+                counts[entry.pick_move(&mut rng).unwrap().trailing_zeros() as usize] += 1;
+            }
+            counts
+        };
+        let even = counts(&entry, 818);
+        assert_eq!(even, counts(&entry, 818));
+        assert!(even.iter().all(|&n| (850..1150).contains(&n)), "{:?}", even);
+
+        entry.suggested_moves[0].weight = 5;
+        entry.suggested_moves[3].weight = 0;
+        let skewed = counts(&entry, 818);
+        assert_eq!(skewed[3], 0);
+        assert!((2700..3000).contains(&skewed[0]), "{:?}", skewed);
+
+        entry.suggested_moves.clear();
+        assert_eq!(entry.pick_move(&mut Rng64::new(818)), None);
+    }
+
+    #[test]
+    fn pruning_drops_exactly_the_light_book_entries() {
+        // Position i holds moves with the weights in row i; only the
+        // heaviest move counts, and a moveless entry weighs 0.
+        let weights: [&[u32]; 5] = [&[1], &[3], &[1, 2], &[5, 1], &[]];
+        let pos = |i: u64| Position {
+            black: 1 << i,
+            white: 2 << i,
+            white_to_move: false,
+        };
+        let mut book = OpeningBook::default();
+        for (i, row) in weights.iter().enumerate() {
+            let suggested_moves = row
+                .iter()
+                .map(|&weight| BookMove { mv: 1 << 40, eval: 0, weight })
+                .collect();
+            let entry = BookEntry {
+                suggested_moves,
+                outcome: None,
+            };
+            book.entries.insert(pos(i as u64), entry);
+        }
+        let kept: Vec<(Position, Vec<BookMove>)> = [1, 2, 3]
+            .map(|i| (pos(i), book.get(&pos(i)).unwrap().suggested_moves.clone()))
+            .to_vec();
+        assert_eq!(book.prune(0), 0);
+        assert_eq!(book.prune(2), 2);
+        assert_eq!(book.entries.len(), kept.len());
+        for (p, moves) in kept {
+            assert_eq!(book.get(&p).unwrap().suggested_moves, moves);
+        }
+    }
+
+    #[test]
+    fn pruning_never_writes_an_empty_book() {
+        let path = std::env::temp_dir().join(format!("book-prune-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let book = book_from_lines(&["f5d6c3d3c4", "f5f6e6f4", "f5d6c5f4e3", "f5d6c3d3c4"]);
+        book.save(path, BookFormat::Json).unwrap();
+        let saved = std::fs::read(path).unwrap();
+
+        assert!(prune_book_file(path, 3, BookFormat::Json).is_err());
+        assert_eq!(std::fs::read(path).unwrap(), saved);
+        assert_eq!(prune_book_file(path, 2, BookFormat::Json), Ok((2, 3)));
+        let pruned = OpeningBook::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(pruned.entries.len(), 1);
+        assert_eq!(pruned.entries.values().next().unwrap().suggested_moves[0].weight, 2);
+    }
+
+    #[test]
+    fn book_stats_count_a_small_book() {
+        let start = Position {
+            black: START_BLACK,
+            white: START_WHITE,
+            white_to_move: false,
+        };
+        let book = book_from_lines(&["f5", "d3", "c4", "e6", "f5d6", "f5f6", "f5f4"]);
+        let stats = book.stats();
+        assert_eq!(stats.positions, 2);
+        assert_eq!(stats.black_to_move, 1);
+        assert_eq!(stats.white_to_move, 1);
+        assert_eq!(stats.max_moves, 4);
+        assert_eq!(stats.avg_moves, 3.5);
+        assert_eq!(stats.by_discs.get(&4), Some(&1));
+        assert_eq!(stats.by_discs.get(&5), Some(&1));
+        assert_eq!(book.get(&start).unwrap().suggested_moves.len(), 4);
+        assert_eq!(OpeningBook::default().stats(), BookStats::default());
+    }
+
+    #[test]
+    fn book_exports_its_lines_as_transcripts() {
+        // Black's f5 with two stored replies, one of which the book
+        // answers; the line through f6 stops where the book does.
+        let book = book_from_lines(&["f5", "f5d6", "f5f6", "f5d6c3"]);
+        assert_eq!(book.export_lines(), ["f5d6c3", "f5f6"]);
+        for line in book.export_lines() {
+            assert!(replay_transcript(&parse_transcript(&line).unwrap()).is_ok());
+        }
+        assert!(OpeningBook::default().export_lines().is_empty());
+    }
+
+    #[test]
+    fn one_canonical_book_entry_answers_every_symmetric_query() {
+        let (pos, last) = line_position("f5d6c3d3");
+        let Position {
+            white,
+            black,
+            white_to_move,
+        } = pos;
+        let mut book = OpeningBook::default();
+        book.insert_canonical(pos, last, 7);
+        assert_eq!(book.entries.len(), 1);
+
+        let (w, b) = apply_move(white, black, last, white_to_move).unwrap();
+        let after = Position {
+            black: b,
+            white: w,
+            white_to_move,
+        };
+        for (image, image_after) in symmetries(&pos).iter().zip(symmetries(&after)) {
+            let entry = book.get_canonical(image).unwrap();
+            assert_eq!(entry.suggested_moves.len(), 1);
+            assert_eq!(entry.suggested_moves[0].eval, 7);
+            // The returned move must reach the same image of the result.
+            let mv = entry.suggested_moves[0].mv;
+            let (w, b) = apply_move(image.white, image.black, mv, white_to_move).unwrap();
+            assert_eq!((b, w), (image_after.black, image_after.white));
+        }
+    }
+
+    #[test]
+    fn version_1_books_migrate_and_newer_books_are_refused() {
+        let path = std::env::temp_dir().join(format!("book-v1-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        // Version 1: the bare entry map, with bare moves.
+        let key = format!("{},{},0", START_BLACK, START_WHITE);
+        std::fs::write(path, format!(r#"{{ "{}": {{ "suggested_moves": [524288] }} }}"#, key))
+            .unwrap();
+        let mut book = OpeningBook::load_from_file(path).unwrap();
+        let start = Position::parse(&key).unwrap();
+        assert_eq!(book.get(&start).unwrap().suggested_moves, [BookMove::new(524288, 0)]);
+        assert_eq!(book.meta, BookMeta::default());
+
+        // Saved again it gains the header, and the metadata survives.
+        book.meta.search_depth = 9;
+        book.meta.created = "2024-01-01T00:00:00+00:00".to_string();
+        for format in [BookFormat::Json, BookFormat::Binary] {
+            book.save(path, format).unwrap();
+            let reloaded = OpeningBook::load_from_file(path).unwrap();
+            assert_eq!(reloaded.meta, book.meta);
+            assert_eq!(reloaded.entries.len(), 1);
+        }
+        let text = serde_json::to_string(&book).unwrap();
+        assert!(text.starts_with(&format!(r#"{{"format_version":{}"#, BOOK_FORMAT_VERSION)));
+
+        let newer = text.replacen(
+            &format!(":{},", BOOK_FORMAT_VERSION),
+            &format!(":{},", BOOK_FORMAT_VERSION + 1),
+            1,
+        );
+        std::fs::write(path, newer).unwrap();
+        let err = OpeningBook::load_from_file(path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert!(err.to_string().contains("is newer than supported"), "{}", err);
+    }
+
+    #[test]
+    fn book_validation_catches_illegal_moves() {
+        let lines = ["f5", "f5d6", "f5d6c3"];
+        let mut book = book_from_lines(&lines);
+        let positions = lines.map(|line| line_position(line).0);
+        assert!(book.validate().is_empty());
+
+        // a1 is not a legal reply to f5, and Black has moves after f5 d6,
+        // so a pass is not legal there.
+        book.insert_position(positions[1], 1, 0);
+        let pass_entry = BookEntry {
+            suggested_moves: vec![BookMove::new(u64::MAX, 0)],
+            outcome: None,
+        };
+        book.entries.insert(positions[2], pass_entry);
+        let mut expected = vec![positions[1], positions[2]];
+        expected.sort_by_key(|p| (p.black, p.white, p.white_to_move));
+        assert_eq!(book.validate(), expected);
+    }
+
+    #[test]
+    fn replaying_the_book_log_rebuilds_the_book() {
+        let dir = std::env::temp_dir();
+        let log_path = dir.join(format!("book-{}.json.log", std::process::id()));
+        let log_path = log_path.to_str().unwrap();
+        let _ = std::fs::remove_file(log_path);
+        let mut log = BookLog::open(log_path).unwrap();
+
+        // What generation does per new position: insert, then log the
+        // stored (canonical) entry.
+        let mut book = OpeningBook::default();
+        let mut record = |book: &mut OpeningBook, line: &str, eval| {
+            let (pos, last) = line_position(line);
+            book.insert_canonical(pos, last, eval);
+            let canonical = canonical_position(&pos);
+            log.append(&canonical, book.get(&canonical).unwrap()).unwrap();
+        };
+        record(&mut book, "f5", 0);
+        record(&mut book, "f5d6", -3);
+        record(&mut book, "f5d6c3", 2);
+        record(&mut book, "f5f6", -8);
+        record(&mut book, "f5d6", -1);
+
+        let mut rebuilt = OpeningBook::default();
+        assert_eq!(rebuilt.replay_log(log_path).unwrap(), 5);
+        assert_eq!(rebuilt.entries.len(), book.entries.len());
+        for (pos, entry) in &book.entries {
+            assert_eq!(rebuilt.get(pos).unwrap().suggested_moves, entry.suggested_moves);
+        }
+
+        // After a save the log starts over.
+        log.clear().unwrap();
+        assert_eq!(OpeningBook::default().replay_log(log_path).unwrap(), 0);
+        drop(log);
+        std::fs::remove_file(log_path).unwrap();
+        assert_eq!(OpeningBook::default().replay_log(log_path).unwrap(), 0);
+    }
+
+    #[test]
+    fn probcut_level_zero_matches_the_exact_solver() {
+        for seed in 1000..1030 {
+            let (white, black, white_to_move) = random_endgame(seed, 10);
+            let table = tt::TranspositionTable::new_mb(1);
+            let (mv, eval) = solve_selective(white, black, white_to_move, 0, DEFAULT_CFG, &table);
+            let (_, margin) = solve_endgame(white, black, white_to_move);
+            assert_eq!(eval.signum(), margin.signum(), "seed {}", seed);
+            // The move it picks keeps that result, if not the margin.
+            let (w, b) = apply_move(white, black, mv, white_to_move).unwrap();
+            let after = solve_endgame(w, b, !white_to_move).1;
+            assert_eq!(after.signum(), margin.signum(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn probcut_rarely_gets_the_result_wrong() {
+        // Won, drawn or lost for the side to move, against the exact
+        // search, over positions deep enough for the cut to fire.
+        let positions: Vec<(u64, u64, bool)> =
+            (1100..1140).map(|seed| random_endgame(seed, 12)).collect();
+        let solve = |&(white, black, white_to_move): &(u64, u64, bool), level| {
+            let table = tt::TranspositionTable::new_mb(1);
+            solve_selective(white, black, white_to_move, level, DEFAULT_CFG, &table).1.signum()
+        };
+        let exact: Vec<i32> = positions.iter().map(|p| solve(p, 0)).collect();
+        for level in 1..=3 {
+            let wrong = positions
+                .iter()
+                .zip(&exact)
+                .filter(|&(p, &e)| solve(p, level) != e)
+                .count();
+            assert!(wrong * 10 <= positions.len(), "level {}: {} wrong", level, wrong);
+        }
+    }
 }
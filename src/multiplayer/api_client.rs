@@ -1,9 +1,45 @@
+use std::collections::BTreeMap;
 use std::{thread, time};
 
 use crate::multiplayer::model::*;
 
 use crate::cli::args::*;
 
+/// Per-endpoint count of failed API calls that were retried, for
+/// diagnosing flaky servers. Every failure is logged as it happens with
+/// its endpoint and attempt number; [`RetryStats::report`] prints the
+/// totals at the end of a game. The retry policy itself is unchanged.
+#[derive(Debug, Default)]
+pub struct RetryStats {
+    retries: BTreeMap<&'static str, u32>,
+}
+
+impl RetryStats {
+    /// Record failed attempt number `attempt` (1-based) of a call to
+    /// `endpoint` that is about to be retried.
+    pub fn record(&mut self, endpoint: &'static str, attempt: u32, error: &ureq::Error) {
+        *self.retries.entry(endpoint).or_insert(0) += 1;
+        println!(
+            "API call failed, retrying: endpoint={} attempt={} error={}",
+            endpoint, attempt, error
+        );
+    }
+
+    pub fn report(&self) {
+        if self.retries.is_empty() {
+            println!("API retries: none");
+            return;
+        }
+        let total: u32 = self.retries.values().sum();
+        let per_endpoint: Vec<String> = self
+            .retries
+            .iter()
+            .map(|(endpoint, n)| format!("{}={}", endpoint, n))
+            .collect();
+        println!("API retries: total={} {}", total, per_endpoint.join(" "));
+    }
+}
+
 pub fn find_games_to_join(args: &Args) -> Result<Vec<String>, ureq::Error> {
     let mut res: Vec<String> = Vec::new();
     let api_endpoint: String = args.api_url.clone() + "reversi/v1/game_list";
@@ -79,15 +115,23 @@ pub fn get_game_status(args: &Args, game_uuid: String) -> Result<GameStatusResul
     Ok(status.result)
 }
 
-pub fn wait_for_response(args: &Args, game_uuid: String, my_color: String) -> GameStatusResult {
+pub fn wait_for_response(
+    args: &Args,
+    game_uuid: String,
+    my_color: String,
+    stats: &mut RetryStats,
+) -> GameStatusResult {
+    let mut attempt = 0;
     loop {
         let curr_result: GameStatusResult;
         match get_game_status(args, game_uuid.clone()) {
             Ok(g) => {
                 curr_result = g;
+                attempt = 0;
             }
             Err(e) => {
-                println!("Failed to fetch game status, retrying: {}", e);
+                attempt += 1;
+                stats.record("game_status", attempt, &e);
                 thread::sleep(time::Duration::from_millis(1000));
                 continue;
             }
@@ -102,15 +146,22 @@ pub fn wait_for_response(args: &Args, game_uuid: String, my_color: String) -> Ga
     }
 }
 
-pub fn wait_for_joining_player(args: &Args, game_uuid: String) -> GameStatusResult {
+pub fn wait_for_joining_player(
+    args: &Args,
+    game_uuid: String,
+    stats: &mut RetryStats,
+) -> GameStatusResult {
+    let mut attempt = 0;
     loop {
         let curr_result: GameStatusResult;
         match get_game_status(args, game_uuid.clone()) {
             Ok(g) => {
                 curr_result = g;
+                attempt = 0;
             }
             Err(e) => {
-                println!("Failed to fetch game status, retrying: {}", e);
+                attempt += 1;
+                stats.record("game_status", attempt, &e);
                 thread::sleep(time::Duration::from_millis(1000));
                 continue;
             }
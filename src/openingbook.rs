@@ -1,10 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, Read, Write};
 
 use serde::de::{MapAccess, Visitor};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
+use crate::tt::{hash_position, SIDE_TO_MOVE_KEY};
+use crate::utils::{format_move, Move, Rng64, START_BLACK, START_WHITE};
+use reversi_tools::position::{apply_move, check_game_status, compute_moves};
+
 pub type MoveMask = u64;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
@@ -14,44 +19,392 @@ pub struct Position {
     pub white_to_move: bool,
 }
 
+impl Position {
+    /// Book-key form `"black,white,wtm"`, also accepted by [`Position::parse`].
+    pub fn to_key(&self) -> String {
+        format!("{},{},{}", self.black, self.white, self.white_to_move as u8)
+    }
+
+    /// Parse a position from either the book-key form `"black,white,wtm"`
+    /// (decimal bitboards, `wtm` is 0/1) or a 64-character board diagram
+    /// in `print_board` order (rank 8 first, `x` = black, `o` = white,
+    /// `.`/`-` = empty) optionally followed by a side-to-move character
+    /// (`x`/`b` or `o`/`w`, default black). Whitespace in the diagram is
+    /// ignored so a pasted `print_board` block parses as-is.
+    pub fn parse(s: &str) -> Result<Position, String> {
+        let s = s.trim();
+        if s.contains(',') {
+            let parts: Vec<&str> = s.split(',').collect();
+            if parts.len() != 3 {
+                return Err(format!("invalid key format: {:?}", s));
+            }
+            let black = parts[0]
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("bad black bitboard {:?}: {}", parts[0], e))?;
+            let white = parts[1]
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| format!("bad white bitboard {:?}: {}", parts[1], e))?;
+            let white_to_move = parts[2]
+                .trim()
+                .parse::<u8>()
+                .map_err(|e| format!("bad side to move {:?}: {}", parts[2], e))?;
+            if black & white != 0 {
+                return Err("black and white bitboards overlap".to_string());
+            }
+            return Ok(Position {
+                black,
+                white,
+                white_to_move: white_to_move != 0,
+            });
+        }
+
+        let cells: Vec<char> = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '=')
+            .collect();
+        if cells.len() != 64 && cells.len() != 65 {
+            return Err(format!(
+                "expected 64 board cells (+ optional side to move), got {}",
+                cells.len()
+            ));
+        }
+        let mut black = 0u64;
+        let mut white = 0u64;
+        for (i, c) in cells[..64].iter().enumerate() {
+            let bit = 1u64 << ((7 - i / 8) * 8 + i % 8);
+            match c {
+                'x' | 'X' | '*' => black |= bit,
+                'o' | 'O' => white |= bit,
+                '.' | '-' => {}
+                _ => return Err(format!("unexpected board character {:?}", c)),
+            }
+        }
+        let white_to_move = match cells.get(64) {
+            None | Some('x') | Some('X') | Some('b') | Some('B') => false,
+            Some('o') | Some('O') | Some('w') | Some('W') => true,
+            Some(c) => return Err(format!("unexpected side-to-move character {:?}", c)),
+        };
+        Ok(Position {
+            black,
+            white,
+            white_to_move,
+        })
+    }
+}
+
+/// Proven result of a book position with best play from both sides.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum GameStatus {
+    BlackWon,
+    WhiteWon,
+    Draw,
+}
+
+/// A stored move with the eval the generator found for it (absolute,
+/// positive favours Black) and its selection weight.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredBookMove")]
+pub struct BookMove {
+    pub mv: MoveMask,
+    pub eval: i32,
+    pub weight: u32,
+}
+
+impl BookMove {
+    pub fn new(mv: MoveMask, eval: i32) -> Self {
+        Self { mv, eval, weight: 1 }
+    }
+}
+
+/// Books written before moves carried evals list bare move masks; those
+/// load with eval 0 and weight 1.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredBookMove {
+    Bare(MoveMask),
+    Full { mv: MoveMask, eval: i32, weight: u32 },
+}
+
+impl From<StoredBookMove> for BookMove {
+    fn from(stored: StoredBookMove) -> Self {
+        match stored {
+            StoredBookMove::Bare(mv) => BookMove::new(mv, 0),
+            StoredBookMove::Full { mv, eval, weight } => BookMove { mv, eval, weight },
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BookEntry {
-    pub suggested_moves: Vec<MoveMask>,
+    pub suggested_moves: Vec<BookMove>,
+    /// Set when generation searched the position to the end of the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<GameStatus>,
+}
+
+impl BookEntry {
+    /// A stored move drawn with probability proportional to its weight
+    /// (the first move if every weight is zero); `None` if the entry has
+    /// no moves.
+    pub fn pick_move(&self, rng: &mut Rng64) -> Option<MoveMask> {
+        let first = self.suggested_moves.first()?;
+        let total: u64 = self.suggested_moves.iter().map(|m| m.weight as u64).sum();
+        if total == 0 {
+            return Some(first.mv);
+        }
+        let mut r = rng.next_u64() % total;
+        for m in &self.suggested_moves {
+            if r < m.weight as u64 {
+                return Some(m.mv);
+            }
+            r -= m.weight as u64;
+        }
+        unreachable!("draw below the total weight")
+    }
+}
+
+/// Summary of a book's contents (`--book-stats`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookStats {
+    pub positions: usize,
+    /// Positions per disc count (4 is the start position).
+    pub by_discs: BTreeMap<u32, usize>,
+    pub avg_moves: f64,
+    pub max_moves: usize,
+    pub black_to_move: usize,
+    pub white_to_move: usize,
+}
+
+impl fmt::Display for BookStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "positions: {}", self.positions)?;
+        writeln!(
+            f,
+            "to move: {} black, {} white",
+            self.black_to_move, self.white_to_move
+        )?;
+        writeln!(
+            f,
+            "moves per position: {:.2} average, {} max",
+            self.avg_moves, self.max_moves
+        )?;
+        write!(f, "positions by disc count:")?;
+        for (discs, count) in &self.by_discs {
+            write!(f, "\n  {:2}: {}", discs, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// How a book was generated. Fields are 0 or empty when unknown, as for
+/// books written before the header existed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookMeta {
+    /// `--search-depth`, `--full-depth` and `--k-partial-depth` of the
+    /// last generation run.
+    pub search_depth: u32,
+    pub full_depth: u32,
+    pub partial_depth: u32,
+    /// When generation first wrote the book (RFC 3339).
+    pub created: String,
 }
 
 #[derive(Default, Debug)]
 pub struct OpeningBook {
     pub entries: HashMap<Position, BookEntry>,
+    pub meta: BookMeta,
 }
 
 impl OpeningBook {
-    pub fn insert_position(&mut self, pos: Position, move_mask: MoveMask) {
-        self.entries
-            .entry(pos)
-            .and_modify(|entry| {
-                if !entry.suggested_moves.contains(&move_mask) {
-                    entry.suggested_moves.push(move_mask);
-                }
-            })
-            .or_insert_with(|| BookEntry {
-                suggested_moves: vec![move_mask],
-            });
+    /// Add `move_mask` to `pos`'s moves with `eval` and weight 1, or
+    /// refresh the eval of a move already stored and count the new
+    /// selection in its weight.
+    pub fn insert_position(&mut self, pos: Position, move_mask: MoveMask, eval: i32) {
+        let entry = self.entries.entry(pos).or_insert_with(|| BookEntry {
+            suggested_moves: Vec::new(),
+            outcome: None,
+        });
+        match entry.suggested_moves.iter_mut().find(|m| m.mv == move_mask) {
+            Some(m) => {
+                m.eval = eval;
+                m.weight = m.weight.saturating_add(1);
+            }
+            None => entry.suggested_moves.push(BookMove::new(move_mask, eval)),
+        }
+    }
+
+    /// Count another visit of `move_mask` from `pos` in its weight. The
+    /// move is looked up in the entry stored for `pos` itself, or else
+    /// in its canonical image's. Returns the key of the updated entry,
+    /// or `None` if the book does not hold the move.
+    pub fn record_visit(&mut self, pos: &Position, move_mask: MoveMask) -> Option<Position> {
+        let (key, mv) = if self.entries.contains_key(pos) {
+            (*pos, move_mask)
+        } else {
+            let k = canonical_symmetry(pos);
+            (symmetries(pos)[k], transform_move(move_mask, k))
+        };
+        let m = self
+            .entries
+            .get_mut(&key)?
+            .suggested_moves
+            .iter_mut()
+            .find(|m| m.mv == mv)?;
+        m.weight = m.weight.saturating_add(1);
+        Some(key)
     }
 
     pub fn get(&self, pos: &Position) -> Option<&BookEntry> {
         self.entries.get(pos)
     }
 
-    pub fn insert_all_rotations(&mut self, pos: Position, move_mask: MoveMask) {
-        let mut p = pos;
-        let mut m = move_mask;
-        for _ in 0..4 {
-            self.insert_position(p, m);
-            p = rotate_position_90(&p);
-            m = rotate_move_90(m);
-            self.insert_position(flip_position_vertical(&p), flip_move_vertical(m));
-            self.insert_position(flip_position_horizontal(&p), flip_move_horizontal(m));
+    /// `pos`'s entry with its moves oriented for `pos`: the entry stored
+    /// for `pos` itself, or else the one stored for its canonical image
+    /// (see `insert_canonical`) with the moves mapped back.
+    pub fn get_canonical(&self, pos: &Position) -> Option<BookEntry> {
+        if let Some(entry) = self.get(pos) {
+            return Some(entry.clone());
+        }
+        let k = canonical_symmetry(pos);
+        let mut entry = self.get(&symmetries(pos)[k])?.clone();
+        for m in &mut entry.suggested_moves {
+            m.mv = untransform_move(m.mv, k);
+        }
+        Some(entry)
+    }
+
+    /// Store `move_mask` for `pos` under its canonical image only, so one
+    /// entry answers all eight symmetric positions through
+    /// `get_canonical`.
+    pub fn insert_canonical(&mut self, pos: Position, move_mask: MoveMask, eval: i32) {
+        let k = canonical_symmetry(&pos);
+        self.insert_position(symmetries(&pos)[k], transform_move(move_mask, k), eval);
+    }
+
+    /// Proven result of `pos`, if the book has one. Looked up through
+    /// the canonical form, so any symmetric image of a solved position
+    /// answers.
+    pub fn outcome(&self, pos: &Position) -> Option<GameStatus> {
+        self.get(pos)
+            .or_else(|| self.get(&canonical_position(pos)))
+            .and_then(|e| e.outcome)
+    }
+
+    /// Record a proven result on every stored symmetric image of `pos`
+    /// (insert its move first; positions without an entry are skipped).
+    pub fn set_outcome(&mut self, pos: &Position, outcome: GameStatus) {
+        for image in symmetries(pos) {
+            if let Some(entry) = self.entries.get_mut(&image) {
+                entry.outcome = Some(outcome);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BookStats {
+        let mut stats = BookStats {
+            positions: self.entries.len(),
+            ..BookStats::default()
+        };
+        let mut total_moves = 0;
+        for (pos, entry) in &self.entries {
+            let discs = (pos.black | pos.white).count_ones();
+            *stats.by_discs.entry(discs).or_insert(0) += 1;
+            total_moves += entry.suggested_moves.len();
+            stats.max_moves = stats.max_moves.max(entry.suggested_moves.len());
+            if pos.white_to_move {
+                stats.white_to_move += 1;
+            } else {
+                stats.black_to_move += 1;
+            }
+        }
+        if stats.positions > 0 {
+            stats.avg_moves = total_moves as f64 / stats.positions as f64;
+        }
+        stats
+    }
+
+    /// The book's lines from the standard position as transcripts
+    /// (`f5d6c3...`). Every stored move is followed, the first one
+    /// first, and a line ends at the first position the book does not
+    /// cover. Passes are left implicit, as `parse_transcript` expects.
+    pub fn export_lines(&self) -> Vec<String> {
+        let start = Position {
+            black: START_BLACK,
+            white: START_WHITE,
+            white_to_move: false,
+        };
+        let mut lines = Vec::new();
+        self.collect_lines(start, &mut String::new(), &mut lines);
+        lines
+    }
+
+    fn collect_lines(&self, mut pos: Position, line: &mut String, lines: &mut Vec<String>) {
+        if check_game_status(pos.white, pos.black, pos.white_to_move) == u64::MAX {
+            pos.white_to_move = !pos.white_to_move;
+        }
+        let mut extended = false;
+        if let Some(entry) = self.get_canonical(&pos) {
+            for m in &entry.suggested_moves {
+                if let Ok((white, black)) =
+                    apply_move(pos.white, pos.black, m.mv, pos.white_to_move)
+                {
+                    let len = line.len();
+                    line.push_str(&format_move(Move::Play(m.mv)));
+                    let next = Position {
+                        black,
+                        white,
+                        white_to_move: !pos.white_to_move,
+                    };
+                    self.collect_lines(next, line, lines);
+                    line.truncate(len);
+                    extended = true;
+                }
+            }
         }
+        if !extended && !line.is_empty() {
+            lines.push(line.clone());
+        }
+    }
+
+    /// Positions with a suggested move that is not legal there, sorted.
+    /// A pass (`u64::MAX`, stored when generation found no move) is
+    /// legal only where the side to move has no move.
+    pub fn validate(&self) -> Vec<Position> {
+        let mut bad: Vec<Position> = self
+            .entries
+            .iter()
+            .filter(|(pos, entry)| {
+                let (me, opp) = if pos.white_to_move {
+                    (pos.white, pos.black)
+                } else {
+                    (pos.black, pos.white)
+                };
+                let legal = compute_moves(me, opp);
+                entry.suggested_moves.iter().any(|m| {
+                    if m.mv == u64::MAX {
+                        legal != 0
+                    } else {
+                        m.mv.count_ones() != 1 || m.mv & legal == 0
+                    }
+                })
+            })
+            .map(|(pos, _)| *pos)
+            .collect();
+        bad.sort_by_key(|p| (p.black, p.white, p.white_to_move));
+        bad
+    }
+
+    /// Drop the positions whose heaviest move weighs less than
+    /// `min_weight`. Returns how many were removed.
+    pub fn prune(&mut self, min_weight: u32) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| {
+            entry.suggested_moves.iter().map(|m| m.weight).max().unwrap_or(0) >= min_weight
+        });
+        before - self.entries.len()
     }
 
     // Example serialization/deserialization
@@ -65,10 +418,300 @@ impl OpeningBook {
 
     pub fn load_from_file(path: &str) -> std::io::Result<Self> {
         let file = std::fs::File::open(path)?;
-        let reader = std::io::BufReader::new(file);
+        let mut reader = std::io::BufReader::new(file);
+        if reader.fill_buf()?.starts_with(BOOK_MAGIC) {
+            return Self::read_binary(&mut reader);
+        }
         let book = serde_json::from_reader(reader)?;
         Ok(book)
     }
+
+    /// Apply the records of a `BookLog` at `path` in order, each
+    /// replacing its position's entry. A missing log is empty. Returns
+    /// the number of records applied.
+    pub fn replay_log(&mut self, path: &str) -> std::io::Result<usize> {
+        let file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut applied = 0;
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (key, entry): (String, BookEntry) = serde_json::from_str(&line)?;
+            let pos = Position::parse(&key)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            self.entries.insert(pos, entry);
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    /// Write the book in the binary format: a header (magic, format
+    /// version, entry count, then `meta` as length-prefixed JSON) and
+    /// then per position its black and white
+    /// bitboards, side to move, outcome (0 = unknown, then `BlackWon`,
+    /// `WhiteWon`, `Draw`), move count and moves (mask, eval, weight),
+    /// all little-endian.
+    /// Positions are sorted so the same book always gives the same file.
+    /// `load_from_file` recognises the format by its magic.
+    pub fn save_binary(&self, path: &str) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let mut out = std::io::BufWriter::new(file);
+        println!("Saving current book state to file {}", path);
+        let mut positions: Vec<&Position> = self.entries.keys().collect();
+        positions.sort_by_key(|p| (p.black, p.white, p.white_to_move));
+        out.write_all(BOOK_MAGIC)?;
+        out.write_all(&BOOK_FORMAT_VERSION.to_le_bytes())?;
+        out.write_all(&(positions.len() as u64).to_le_bytes())?;
+        let meta = serde_json::to_vec(&self.meta)?;
+        out.write_all(&(meta.len() as u32).to_le_bytes())?;
+        out.write_all(&meta)?;
+        for pos in positions {
+            let entry = &self.entries[pos];
+            out.write_all(&pos.black.to_le_bytes())?;
+            out.write_all(&pos.white.to_le_bytes())?;
+            let outcome = match entry.outcome {
+                None => 0u8,
+                Some(GameStatus::BlackWon) => 1,
+                Some(GameStatus::WhiteWon) => 2,
+                Some(GameStatus::Draw) => 3,
+            };
+            out.write_all(&[pos.white_to_move as u8, outcome])?;
+            out.write_all(&(entry.suggested_moves.len() as u16).to_le_bytes())?;
+            for m in &entry.suggested_moves {
+                out.write_all(&m.mv.to_le_bytes())?;
+                out.write_all(&m.eval.to_le_bytes())?;
+                out.write_all(&m.weight.to_le_bytes())?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Save in `format`.
+    pub fn save(&self, path: &str, format: BookFormat) -> std::io::Result<()> {
+        match format {
+            BookFormat::Json => self.save_to_file(path),
+            BookFormat::Binary => self.save_binary(path),
+        }
+    }
+
+    fn read_binary(input: &mut impl Read) -> std::io::Result<Self> {
+        fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+            let mut buf = [0u8; 8];
+            input.read_exact(&mut buf)?;
+            Ok(u64::from_le_bytes(buf))
+        }
+        let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+        let mut header = [0u8; 8];
+        input.read_exact(&mut header)?;
+        let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if version == 0 || version > BOOK_FORMAT_VERSION {
+            return Err(invalid(newer_version_error(version)));
+        }
+        let count = read_u64(input)?;
+        let mut book = OpeningBook::default();
+        // Version 3 added the metadata.
+        if version >= 3 {
+            let mut len = [0u8; 4];
+            input.read_exact(&mut len)?;
+            let mut meta = vec![0u8; u32::from_le_bytes(len) as usize];
+            input.read_exact(&mut meta)?;
+            book.meta = serde_json::from_slice(&meta)?;
+        }
+        for _ in 0..count {
+            let black = read_u64(input)?;
+            let white = read_u64(input)?;
+            let mut fields = [0u8; 4];
+            input.read_exact(&mut fields)?;
+            let outcome = match fields[1] {
+                0 => None,
+                1 => Some(GameStatus::BlackWon),
+                2 => Some(GameStatus::WhiteWon),
+                3 => Some(GameStatus::Draw),
+                other => return Err(invalid(format!("bad outcome code {}", other))),
+            };
+            let moves = u16::from_le_bytes([fields[2], fields[3]]);
+            let mut suggested_moves = Vec::with_capacity(moves as usize);
+            for _ in 0..moves {
+                let mut m = BookMove::new(read_u64(input)?, 0);
+                // Version 1 stored bare moves.
+                if version >= 2 {
+                    let mut extra = [0u8; 8];
+                    input.read_exact(&mut extra)?;
+                    m.eval = i32::from_le_bytes(extra[..4].try_into().unwrap());
+                    m.weight = u32::from_le_bytes(extra[4..].try_into().unwrap());
+                }
+                suggested_moves.push(m);
+            }
+            let pos = Position {
+                black,
+                white,
+                white_to_move: fields[0] != 0,
+            };
+            book.entries.insert(
+                pos,
+                BookEntry {
+                    suggested_moves,
+                    outcome,
+                },
+            );
+        }
+        Ok(book)
+    }
+}
+
+/// Append-only record of book changes kept next to the book during
+/// generation, so each new position is persisted without rewriting the
+/// whole book. Each line is a JSON `[key, entry]` pair holding a
+/// position's full entry; `OpeningBook::replay_log` over the last saved
+/// book rebuilds the in-memory one. Replaying a record the book already
+/// has is harmless, so the log only needs clearing after a save.
+pub struct BookLog {
+    out: std::io::BufWriter<std::fs::File>,
+}
+
+impl BookLog {
+    /// The log kept for the book at `book_path`.
+    pub fn path_for(book_path: &str) -> String {
+        format!("{}.log", book_path)
+    }
+
+    /// Open `path` for appending, keeping records already there.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self {
+            out: std::io::BufWriter::new(file),
+        })
+    }
+
+    /// Record `pos`'s current entry.
+    pub fn append(&mut self, pos: &Position, entry: &BookEntry) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.out, &(pos.to_key(), entry))?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+
+    /// Drop every record, once the book they apply to has been saved.
+    pub fn clear(&mut self) -> std::io::Result<()> {
+        self.out.flush()?;
+        self.out.get_ref().set_len(0)
+    }
+}
+
+const BOOK_MAGIC: &[u8; 4] = b"RVBK";
+
+/// Bumped whenever either book encoding changes. JSON books without a
+/// header are version 1; books from a newer version are refused.
+pub const BOOK_FORMAT_VERSION: u32 = 3;
+
+fn newer_version_error(version: u32) -> String {
+    format!(
+        "book format version {} is newer than supported {}; regenerate the book or upgrade",
+        version, BOOK_FORMAT_VERSION
+    )
+}
+
+/// On-disk book format (`--book-format`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookFormat {
+    /// Pretty-printed JSON keyed by `Position::to_key`; the interchange
+    /// format.
+    Json,
+    /// Compact fixed-width records, see `OpeningBook::save_binary`.
+    Binary,
+}
+
+impl std::str::FromStr for BookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(BookFormat::Json),
+            "binary" => Ok(BookFormat::Binary),
+            _ => Err(format!("unknown book format {:?}", s)),
+        }
+    }
+}
+
+/// The eight dihedral images of `pos` (four rotations, each optionally
+/// mirrored), identity first.
+pub fn symmetries(pos: &Position) -> [Position; 8] {
+    let mut out = [*pos; 8];
+    let mut p = *pos;
+    for i in 0..4 {
+        out[2 * i] = p;
+        out[2 * i + 1] = flip_position_horizontal(&p);
+        p = rotate_position_90(&p);
+    }
+    out
+}
+
+/// Index into `symmetries(pos)` of the canonical image.
+fn canonical_symmetry(pos: &Position) -> usize {
+    let images = symmetries(pos);
+    (0..8)
+        .min_by_key(|&k| (images[k].black, images[k].white))
+        .unwrap()
+}
+
+/// Map a move on a position to the same move on its `k`th image in
+/// `symmetries`.
+fn transform_move(m: MoveMask, k: usize) -> MoveMask {
+    let mut m = m;
+    for _ in 0..k / 2 {
+        m = rotate_move_90(m);
+    }
+    if k % 2 == 1 {
+        flip_move_horizontal(m)
+    } else {
+        m
+    }
+}
+
+/// Inverse of `transform_move`.
+fn untransform_move(m: MoveMask, k: usize) -> MoveMask {
+    let mut m = if k % 2 == 1 { flip_move_horizontal(m) } else { m };
+    for _ in 0..(4 - k / 2) % 4 {
+        m = rotate_move_90(m);
+    }
+    m
+}
+
+/// Canonical representative of `pos`'s symmetry class: the image with
+/// the smallest `(black, white)` pair. Side to move is left untouched.
+pub fn canonical_position(pos: &Position) -> Position {
+    symmetries(pos)
+        .into_iter()
+        .min_by_key(|p| (p.black, p.white))
+        .unwrap()
+}
+
+/// Hash of the canonical form of `pos`, so all eight symmetric images
+/// (with the same side to move) share one value.
+pub fn canonical_hash(pos: &Position) -> u64 {
+    let c = canonical_position(pos);
+    let side = if c.white_to_move {
+        SIDE_TO_MOVE_KEY
+    } else {
+        0
+    };
+    hash_position(c.black, c.white) ^ side
+}
+
+/// [`canonical_hash`] for callers holding bare bitboards.
+pub fn canonical_board_hash(white: u64, black: u64, white_to_move: bool) -> u64 {
+    canonical_hash(&Position {
+        black,
+        white,
+        white_to_move,
+    })
 }
 
 fn rotate90(b: u64) -> u64 {
@@ -113,10 +756,6 @@ pub fn flip_position_horizontal(pos: &Position) -> Position {
     }
 }
 
-fn flip_move_vertical(m: MoveMask) -> MoveMask {
-    flip_vertical(m)
-}
-
 fn flip_move_horizontal(m: MoveMask) -> MoveMask {
     flip_horizontal(m)
 }
@@ -147,20 +786,76 @@ fn reverse_byte(mut b: u8) -> u8 {
     b
 }
 
-impl Serialize for OpeningBook {
+/// Book entries keyed by `Position::to_key`, since JSON object keys
+/// must be strings.
+struct KeyedEntries<'a>(&'a HashMap<Position, BookEntry>);
+
+impl Serialize for KeyedEntries<'_> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
-        for (pos, entry) in &self.entries {
-            let key = format!("{},{},{}", pos.black, pos.white, pos.white_to_move as u8);
-            map.serialize_entry(&key, entry)?;
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (pos, entry) in self.0 {
+            map.serialize_entry(&pos.to_key(), entry)?;
         }
         map.end()
     }
 }
 
+impl Serialize for OpeningBook {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("format_version", &BOOK_FORMAT_VERSION)?;
+        map.serialize_entry("meta", &self.meta)?;
+        map.serialize_entry("entries", &KeyedEntries(&self.entries))?;
+        map.end()
+    }
+}
+
+fn parse_key<E: serde::de::Error>(key: &str) -> Result<Position, E> {
+    if !key.contains(',') {
+        return Err(E::custom("invalid key format"));
+    }
+    Position::parse(key).map_err(E::custom)
+}
+
+/// Deserialized counterpart of `KeyedEntries`.
+struct ParsedEntries(HashMap<Position, BookEntry>);
+
+impl<'de> Deserialize<'de> for ParsedEntries {
+    fn deserialize<D>(deserializer: D) -> Result<ParsedEntries, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct EntriesVisitor;
+
+        impl<'de> Visitor<'de> for EntriesVisitor {
+            type Value = ParsedEntries;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map from string -> BookEntry")
+            }
+
+            fn visit_map<M>(self, mut access: M) -> Result<ParsedEntries, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                let mut entries = HashMap::new();
+                while let Some((key, entry)) = access.next_entry::<String, BookEntry>()? {
+                    entries.insert(parse_key(&key)?, entry);
+                }
+                Ok(ParsedEntries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
 impl<'de> Deserialize<'de> for OpeningBook {
     fn deserialize<D>(deserializer: D) -> Result<OpeningBook, D::Error>
     where
@@ -172,7 +867,7 @@ impl<'de> Deserialize<'de> for OpeningBook {
             type Value = OpeningBook;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a map from string -> BookEntry")
+                formatter.write_str("a book with a format_version header, or a version 1 map")
             }
 
             fn visit_map<M>(self, mut access: M) -> Result<OpeningBook, M::Error>
@@ -181,21 +876,25 @@ impl<'de> Deserialize<'de> for OpeningBook {
             {
                 let mut book = OpeningBook::default();
 
-                while let Some((key, entry)) = access.next_entry::<String, BookEntry>()? {
-                    let parts: Vec<&str> = key.split(',').collect();
-                    if parts.len() != 3 {
-                        return Err(serde::de::Error::custom("invalid key format"));
+                // Version 1 books are the bare entry map, so position keys
+                // at the top level are read as entries.
+                while let Some(key) = access.next_key::<String>()? {
+                    match key.as_str() {
+                        "format_version" => {
+                            let version: u32 = access.next_value()?;
+                            if version > BOOK_FORMAT_VERSION {
+                                return Err(serde::de::Error::custom(newer_version_error(
+                                    version,
+                                )));
+                            }
+                        }
+                        "meta" => book.meta = access.next_value()?,
+                        "entries" => book.entries = access.next_value::<ParsedEntries>()?.0,
+                        _ => {
+                            let pos = parse_key(&key)?;
+                            book.entries.insert(pos, access.next_value()?);
+                        }
                     }
-                    let black = parts[0].parse::<u64>().map_err(serde::de::Error::custom)?;
-                    let white = parts[1].parse::<u64>().map_err(serde::de::Error::custom)?;
-                    let white_to_move_num =
-                        parts[2].parse::<u8>().map_err(serde::de::Error::custom)?;
-                    let pos = Position {
-                        black,
-                        white,
-                        white_to_move: white_to_move_num != 0,
-                    };
-                    book.entries.insert(pos, entry);
                 }
 
                 Ok(book)
@@ -0,0 +1,76 @@
+//! Game-level time management for `--time-budget`.
+//!
+//! Each side gets a total budget for the whole game. Rather than splitting
+//! it evenly per move, the `TimeManager` weights moves by phase: midgame
+//! moves (where the eval matters most and the tree is widest) get
+//! `midgame_weight` times an opening or late-midgame move, and a
+//! fraction of the clock is held back for the endgame, where from
+//! `solve_empties` on the search runs to the end of the game. The
+//! allocation is recomputed from the time actually left before every
+//! move, so overruns and quick book moves even out over the game.
+
+use std::time::Duration;
+
+use crate::engine::Phase;
+
+/// Shape of the allocation curve (see module docs).
+#[derive(Clone, Copy, Debug)]
+pub struct TimeCurve {
+    /// Relative weight of a midgame move; opening and endgame moves
+    /// weigh 1.
+    pub midgame_weight: f64,
+    /// Fraction of the remaining time reserved for the endgame while
+    /// more than `solve_empties` squares are empty.
+    pub endgame_reserve: f64,
+    /// Empties at which the search switches to solving to the end.
+    pub solve_empties: u32,
+}
+
+pub struct TimeManager {
+    remaining: Duration,
+    curve: TimeCurve,
+}
+
+impl TimeManager {
+    pub fn new(total: Duration, curve: TimeCurve) -> Self {
+        Self {
+            remaining: total,
+            curve,
+        }
+    }
+
+    fn weight(&self, empties: u32) -> f64 {
+        match Phase::from_empties(empties) {
+            Phase::Midgame => self.curve.midgame_weight,
+            _ => 1.0,
+        }
+    }
+
+    /// Time to spend on the move at `empties` empty squares.
+    pub fn allocate(&self, empties: u32) -> Duration {
+        if empties <= self.curve.solve_empties {
+            // The first solve is by far the most expensive; later moves
+            // mostly hit the TT. Give it half and keep the rest.
+            return self.remaining / 2;
+        }
+        let available = self
+            .remaining
+            .mul_f64(1.0 - self.curve.endgame_reserve.clamp(0.0, 1.0));
+        // Our moves are every other ply from here down to the solve.
+        let ours: f64 = (self.curve.solve_empties + 1..=empties)
+            .rev()
+            .step_by(2)
+            .map(|e| self.weight(e))
+            .sum();
+        available.mul_f64(self.weight(empties) / ours.max(1.0))
+    }
+
+    /// Charge `used` against the remaining budget.
+    pub fn spend(&mut self, used: Duration) {
+        self.remaining = self.remaining.saturating_sub(used);
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+}
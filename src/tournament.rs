@@ -0,0 +1,156 @@
+//! Round-robin tournament between eval configs, resumable across runs.
+//!
+//! Every pair of configs plays one [`run_match`] over the shared position
+//! set. A full tournament at tuning depths can run for hours, so each
+//! completed pairing is appended to a results file as one JSON object per
+//! line as soon as it finishes. On restart the file is read back and
+//! pairings already present (same configs, depth and position count) are
+//! skipped; the final cross-table is built from the file contents, so it
+//! also covers pairings played by earlier, interrupted runs.
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::EvalCfg;
+use crate::openingbook::Position;
+use crate::tune::run_match;
+
+/// One finished pairing. Configs are identified by the coefficient
+/// strings they were given as, so a results file stays meaningful when
+/// the tournament is re-run with configs in a different order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PairingResult {
+    pub first: String,
+    pub second: String,
+    pub depth: u32,
+    pub positions: usize,
+    /// `first`-minus-`second` score as returned by [`run_match`].
+    pub score: i32,
+}
+
+/// Read all results from `path`. A missing file is an empty tournament;
+/// unparseable lines (e.g. a line cut short by a crash) are reported and
+/// skipped so the pairing simply gets replayed.
+pub fn load_results(path: &str) -> Vec<PairingResult> {
+    let file = match std::fs::File::open(path) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+    let mut results = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                eprintln!("tournament: stopped reading {} at line {}: {}", path, i + 1, e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<PairingResult>(&line) {
+            Ok(r) => results.push(r),
+            Err(e) => eprintln!("tournament: skipping bad line {} of {}: {}", i + 1, path, e),
+        }
+    }
+    results
+}
+
+fn append_result(path: &str, result: &PairingResult) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(result)?)?;
+    file.flush()
+}
+
+fn find<'a>(
+    results: &'a [PairingResult],
+    first: &str,
+    second: &str,
+    depth: u32,
+    positions: usize,
+) -> Option<&'a PairingResult> {
+    results.iter().find(|r| {
+        r.first == first && r.second == second && r.depth == depth && r.positions == positions
+    })
+}
+
+/// Play every pairing of `configs` not already recorded in
+/// `results_path` (no persistence if the path is empty) and return the
+/// results relevant to this tournament.
+pub fn run_tournament(
+    configs: &[(String, EvalCfg)],
+    depth: u32,
+    positions: &[Position],
+    results_path: &str,
+) -> Vec<PairingResult> {
+    let mut results = if results_path.is_empty() {
+        Vec::new()
+    } else {
+        load_results(results_path)
+    };
+    for (i, (first, first_cfg)) in configs.iter().enumerate() {
+        for (second, second_cfg) in &configs[i + 1..] {
+            let n = positions.len();
+            let played = find(&results, first, second, depth, n)
+                .map(|r| r.score)
+                .or_else(|| find(&results, second, first, depth, n).map(|r| -r.score));
+            if let Some(score) = played {
+                println!(
+                    "tournament: [{}] vs [{}] already played ({:+}), skipping",
+                    first, second, score
+                );
+                continue;
+            }
+            let score = run_match(*first_cfg, *second_cfg, depth, positions);
+            println!("tournament: [{}] vs [{}] = {:+}", first, second, score);
+            let result = PairingResult {
+                first: first.clone(),
+                second: second.clone(),
+                depth,
+                positions: positions.len(),
+                score,
+            };
+            if !results_path.is_empty() {
+                if let Err(e) = append_result(results_path, &result) {
+                    eprintln!("tournament: failed to record result in {}: {}", results_path, e);
+                }
+            }
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// Print the cross-table: row `i`, column `j` is config `i`'s score
+/// against config `j`, followed by each config's total.
+pub fn print_cross_table(
+    configs: &[(String, EvalCfg)],
+    results: &[PairingResult],
+    depth: u32,
+    positions: usize,
+) {
+    println!("\ntournament cross-table (depth {}, {} positions):", depth, positions);
+    for (i, (name, _)) in configs.iter().enumerate() {
+        let mut row = String::new();
+        let mut total = 0;
+        for (j, (other, _)) in configs.iter().enumerate() {
+            let cell = if i == j {
+                None
+            } else if let Some(r) = find(results, name, other, depth, positions) {
+                Some(r.score)
+            } else {
+                find(results, other, name, depth, positions).map(|r| -r.score)
+            };
+            match cell {
+                Some(v) => {
+                    total += v;
+                    row += &format!(" {:+7}", v);
+                }
+                None => row += "       -",
+            }
+        }
+        println!("{:2}:{} | total {:+7}  [{}]", i, row, total, name);
+    }
+}
@@ -16,15 +16,25 @@
 //! than the incremental update was when the engine was last tried with
 //! Zobrist hashing.
 
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::utils::splitmix64;
 
+// What a stored score says about the true one. The search stores a
+// lower bound on a beta cutoff (the node failed high) and an upper bound
+// when no move beat alpha (it failed low); a probe cuts off with a lower
+// bound at or above beta and with an upper bound at or below alpha.
 pub const BOUND_NONE: u8 = 0;
 pub const BOUND_EXACT: u8 = 1;
 pub const BOUND_LOWER: u8 = 2; // true score >= stored
 pub const BOUND_UPPER: u8 = 3; // true score <= stored
 
+// `save_to_file` header.
+const TT_FILE_MAGIC: &[u8; 4] = b"RVTT";
+const TT_FILE_VERSION: u32 = 1;
+
 // Sentinel "no recorded best move" value. Legal move square indices are
 // 0..=63; anything else is treated as absent.
 pub const NO_MOVE_SQ: u8 = 64;
@@ -38,6 +48,16 @@ pub struct TTData {
     pub move_sq: u8,
 }
 
+impl TTData {
+    /// Whether the stored score can stand in for a search with `depth`
+    /// plies remaining: it must carry a bound and come from a search at
+    /// least that deep. Shallower entries are still good for their move.
+    #[inline(always)]
+    pub fn usable_at(&self, depth: u32) -> bool {
+        self.bound != BOUND_NONE && self.depth as i32 >= depth as i32
+    }
+}
+
 /// One 16-byte slot. Declared 16-byte aligned so each slot stays within a
 /// single cache line pair.
 #[repr(align(16))]
@@ -62,11 +82,17 @@ pub struct TranspositionTable {
 }
 
 impl TranspositionTable {
+    /// The largest power-of-two number of slots that fits in `mb`
+    /// megabytes, but at least 1024.
     pub fn new_mb(mb: usize) -> Self {
         let entry_size = std::mem::size_of::<TTSlot>();
         let requested = (mb * 1024 * 1024) / entry_size;
         // Round DOWN to power-of-two so the modulo is a mask.
-        let entries = prev_power_of_two(requested).max(1024);
+        Self::with_entries(prev_power_of_two(requested).max(1024))
+    }
+
+    fn with_entries(entries: usize) -> Self {
+        assert!(entries.is_power_of_two());
         let slots: Vec<TTSlot> = (0..entries).map(|_| TTSlot::empty()).collect();
         Self {
             slots: slots.into_boxed_slice(),
@@ -89,11 +115,104 @@ impl TranspositionTable {
         self.age.store(0, Ordering::Relaxed);
     }
 
+    /// Number of slots, a power of two.
+    pub fn entries(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The slot `key` maps to: its low bits, always below `entries()`
+    /// since that is a power of two and `mask` is one less.
+    #[inline(always)]
+    pub fn slot_index(&self, key: u64) -> usize {
+        (key as usize) & self.mask
+    }
+
+    /// Write the occupied slots to `path`: a header (magic, format
+    /// version, slot count, age) and then each entry as its key and
+    /// packed data word, little-endian. Empty slots are skipped.
+    pub fn save_to_file(&self, path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(TT_FILE_MAGIC)?;
+        out.write_all(&TT_FILE_VERSION.to_le_bytes())?;
+        out.write_all(&(self.slots.len() as u64).to_le_bytes())?;
+        out.write_all(&self.age.load(Ordering::Relaxed).to_le_bytes())?;
+        for slot in self.slots.iter() {
+            let a = slot.word_a.load(Ordering::Relaxed);
+            let b = slot.word_b.load(Ordering::Relaxed);
+            if a == 0 && b == 0 {
+                continue;
+            }
+            out.write_all(&(a ^ b).to_le_bytes())?;
+            out.write_all(&b.to_le_bytes())?;
+        }
+        out.flush()
+    }
+
+    /// Read a table written by `save_to_file`, at the size it was saved.
+    pub fn load_from_file(path: &str) -> io::Result<TranspositionTable> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut input = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != TT_FILE_MAGIC {
+            return Err(invalid("not a transposition table file"));
+        }
+        let mut version = [0u8; 4];
+        input.read_exact(&mut version)?;
+        if u32::from_le_bytes(version) != TT_FILE_VERSION {
+            return Err(invalid("unsupported transposition table file version"));
+        }
+        let mut word = [0u8; 8];
+        input.read_exact(&mut word)?;
+        let entries = u64::from_le_bytes(word) as usize;
+        if !entries.is_power_of_two() || entries < 1024 {
+            return Err(invalid("bad transposition table size"));
+        }
+        input.read_exact(&mut word)?;
+        let table = Self::with_entries(entries);
+        table.age.store(u64::from_le_bytes(word), Ordering::Relaxed);
+        let mut record = [0u8; 16];
+        loop {
+            match input.read_exact(&mut record) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let key = u64::from_le_bytes(record[..8].try_into().unwrap());
+            let b = u64::from_le_bytes(record[8..].try_into().unwrap());
+            let slot = &table.slots[table.slot_index(key)];
+            slot.word_a.store(key ^ b, Ordering::Relaxed);
+            slot.word_b.store(b, Ordering::Relaxed);
+        }
+        Ok(table)
+    }
+
+    /// Whether `key`'s slot holds another position: a probe for `key`
+    /// misses because of an index collision rather than an empty slot.
+    pub fn collides(&self, key: u64) -> bool {
+        let slot = &self.slots[self.slot_index(key)];
+        let a = slot.word_a.load(Ordering::Relaxed);
+        let b = slot.word_b.load(Ordering::Relaxed);
+        (a != 0 || b != 0) && a ^ b != key
+    }
+
+    /// Occupancy in per mille, sampled from the first 1000 slots, like
+    /// the "hashfull" chess engines report.
+    pub fn hashfull(&self) -> u32 {
+        let sample = &self.slots[..self.slots.len().min(1000)];
+        let used = sample
+            .iter()
+            .filter(|s| {
+                s.word_a.load(Ordering::Relaxed) != 0 || s.word_b.load(Ordering::Relaxed) != 0
+            })
+            .count();
+        (used * 1000 / sample.len()) as u32
+    }
+
     #[inline(always)]
     pub fn probe(&self, key: u64) -> Option<TTData> {
-        let idx = (key as usize) & self.mask;
-        // Safety: `idx` is masked to be a valid index.
-        let slot = unsafe { self.slots.get_unchecked(idx) };
+        // Safety: `slot_index` is masked to be a valid index.
+        let slot = unsafe { self.slots.get_unchecked(self.slot_index(key)) };
         let a = slot.word_a.load(Ordering::Relaxed);
         let b = slot.word_b.load(Ordering::Relaxed);
         if a == 0 && b == 0 {
@@ -107,26 +226,25 @@ impl TranspositionTable {
 
     #[inline(always)]
     pub fn store(&self, key: u64, score: i32, depth: i8, bound: u8, move_sq: u8) {
-        let idx = (key as usize) & self.mask;
-        let slot = unsafe { self.slots.get_unchecked(idx) };
+        let slot = unsafe { self.slots.get_unchecked(self.slot_index(key)) };
 
         let existing_a = slot.word_a.load(Ordering::Relaxed);
         let existing_b = slot.word_b.load(Ordering::Relaxed);
         let existing_empty = existing_a == 0 && existing_b == 0;
-        let existing_key = existing_a ^ existing_b;
         let existing_depth = ((existing_b >> 16) & 0xFF) as i8;
         let existing_age = ((existing_b >> 40) & 0xFF) as u8;
+        let existing_key = existing_a ^ existing_b;
 
         let cur_age = (self.age.load(Ordering::Relaxed) & 0xFF) as u8;
 
-        // Replacement policy: empty slot wins instantly; same position
-        // always overwrites so deeper results supersede shallower; otherwise
-        // prefer replacing stale (different-age) entries, or equal-/deeper-
-        // depth entries of the current age.
-        let same_pos = !existing_empty && existing_key == key;
+        // Replacement policy: empty slot wins instantly; stale (different-
+        // age) entries and ones for another position mapping to the slot
+        // always go; an entry of the current age for this position is only
+        // replaced by a result at least as deep, so a shallow re-search
+        // can't clobber an expensive one.
         let replace = existing_empty
-            || same_pos
             || existing_age != cur_age
+            || existing_key != key
             || depth as i16 >= existing_depth as i16;
 
         if !replace {
@@ -187,17 +305,40 @@ pub fn hash_position(us: u64, them: u64) -> u64 {
     a ^ b.rotate_left(17)
 }
 
+/// Xored into hashes taken in the absolute (black, white) frame when
+/// White is to move. Search keys need no such term: they are taken in the
+/// us/them frame, where swapping the side to move swaps the arguments.
+pub const SIDE_TO_MOVE_KEY: u64 = 0x9E37_79B9_7F4A_7C15;
+
 // --------------------------------------------------------------------------
 // Global TT singleton
 // --------------------------------------------------------------------------
 
+use std::sync::atomic::AtomicBool;
 use std::sync::OnceLock;
 
 static GLOBAL_TT: OnceLock<TranspositionTable> = OnceLock::new();
+static TT_ENABLED: AtomicBool = AtomicBool::new(true);
 
 pub const DEFAULT_TT_MB: usize = 4;
 
+/// `--tt-size-mb`: create the global table with `mb` megabytes. Only
+/// takes effect before the first search touches the table.
+pub fn init_tt(mb: usize) {
+    let _ = GLOBAL_TT.set(TranspositionTable::new_mb(mb));
+}
+
 /// Access the global transposition table, creating it on first use.
 pub fn tt() -> &'static TranspositionTable {
     GLOBAL_TT.get_or_init(|| TranspositionTable::new_mb(DEFAULT_TT_MB))
 }
+
+/// `--no-tt` turns the table off so node counts and moves can be
+/// compared against a plain alpha-beta search.
+pub fn set_tt_enabled(enabled: bool) {
+    TT_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn tt_enabled() -> bool {
+    TT_ENABLED.load(Ordering::Relaxed)
+}
@@ -31,14 +31,15 @@
 
 use rayon::prelude::*;
 use reversi_tools::position::{apply_move, check_game_status};
-use std::collections::HashMap;
+use std::collections::HashSet;
 
-use crate::engine::{find_legal_moves_alt, search_moves_opt, EvalCfg};
-use crate::openingbook::{
-    flip_position_horizontal, flip_position_vertical, rotate_position_90, Position,
+use crate::engine::{
+    effective_depth, find_legal_moves_alt, search_moves_opt, EvalCfg, DEFAULT_CFG, MAX_EVAL,
+    MIN_EVAL,
 };
+use crate::openingbook::{canonical_hash, Position};
 use crate::tt;
-use crate::utils::splitmix64;
+use crate::utils::{report_stuck_game, splitmix64, Rng64};
 
 // --------------------------------------------------------------------------
 // Position generation (shared with compare_configs)
@@ -58,31 +59,25 @@ pub fn generate_ply_positions(ply: u32) -> Vec<Position> {
         white_to_move: false,
     };
     let mut queue: Vec<Position> = Vec::new();
-    let mut dedup_cache: HashMap<Position, bool> = HashMap::new();
+    // Canonical hashes of the symmetry classes already expanded - one
+    // insert per position instead of one per symmetric image.
+    let mut expanded: HashSet<u64> = HashSet::new();
     queue.push(starting_pos);
     for _ in 0..ply {
         let mut next_queue: Vec<Position> = Vec::new();
         for pos in queue {
-            if dedup_cache.contains_key(&pos) {
+            if !expanded.insert(canonical_hash(&pos)) {
                 continue;
             }
             let next_moves = find_legal_moves_alt(pos.white, pos.black, pos.white_to_move);
             for next_move in next_moves {
                 let new_pos_opt = apply_move(pos.white, pos.black, next_move, pos.white_to_move);
                 if let Ok((w, b)) = new_pos_opt {
-                    let new_pos: Position = Position {
+                    next_queue.push(Position {
                         black: b,
                         white: w,
                         white_to_move: !pos.white_to_move,
-                    };
-                    let mut p = pos.clone();
-                    for _ in 0..4 {
-                        dedup_cache.insert(p, true);
-                        dedup_cache.insert(flip_position_vertical(&p), true);
-                        dedup_cache.insert(flip_position_horizontal(&p), true);
-                        p = rotate_position_90(&p);
-                    }
-                    next_queue.push(new_pos);
+                    });
                 }
             }
         }
@@ -161,7 +156,8 @@ pub fn run_match(
 
 /// Single-game self-play between two EvalCfgs. Returns +1 if black
 /// wins, -1 if white wins, 0 if draw. `black_cfg` is used when it's
-/// black's turn, `white_cfg` on white's. No I/O.
+/// black's turn, `white_cfg` on white's. No I/O, except for
+/// `report_stuck_game` when the game breaks an engine invariant.
 fn play_game_from_position_silent(
     black_cfg: EvalCfg,
     white_cfg: EvalCfg,
@@ -171,6 +167,8 @@ fn play_game_from_position_silent(
     let mut white = pos.white;
     let mut black = pos.black;
     let mut white_to_move = pos.white_to_move;
+    let start = (pos.white, pos.black, pos.white_to_move);
+    let mut history: Vec<u64> = Vec::new();
     const BLACK_WON: u64 = u64::MAX - 1;
     const WHITE_WON: u64 = u64::MAX - 2;
     const DRAWN_GAME: u64 = u64::MAX - 3;
@@ -184,23 +182,48 @@ fn play_game_from_position_silent(
             DRAWN_GAME => return 0,
             _ => {
                 let curr_cfg = if white_to_move { white_cfg } else { black_cfg };
+                let depth = effective_depth(white, black, depth);
                 let (best_move, _) = search_moves_opt(
                     white,
                     black,
                     white_to_move,
                     depth,
-                    -20000,
-                    20000,
+                    MIN_EVAL,
+                    MAX_EVAL,
                     depth,
                     curr_cfg,
                 );
+                let discs = (white | black).count_ones();
                 match apply_move(white, black, best_move, white_to_move) {
                     Ok((w, b)) => {
+                        history.push(best_move);
+                        if (w | b).count_ones() <= discs {
+                            report_stuck_game(
+                                "disc count did not increase",
+                                start,
+                                &history,
+                                w,
+                                b,
+                                !white_to_move,
+                            );
+                            return 0;
+                        }
                         white = w;
                         black = b;
                         white_to_move = !white_to_move;
                     }
-                    Err(_) => return 0,
+                    Err(_) => {
+                        history.push(best_move);
+                        report_stuck_game(
+                            "engine chose an illegal move",
+                            start,
+                            &history,
+                            white,
+                            black,
+                            white_to_move,
+                        );
+                        return 0;
+                    }
                 }
             }
         }
@@ -213,16 +236,19 @@ fn play_game_from_position_silent(
 
 /// Number of tunable scalar parameters in [`EvalCfg`]. Matches the
 /// field enumeration in [`cfg_to_vec`] / [`vec_to_cfg`]; bumping
-/// this requires updating both marshalers and the parser in
-/// `main.rs::parse_coefs_or_default`.
-pub const TUNE_DIM: usize = 10;
+/// this requires updating both marshalers and the field list in the
+/// docs of `main.rs::parse_coefs_or_default`.
+pub const TUNE_DIM: usize = 16;
 
 /// Marshal [`EvalCfg`] to/from a fixed-length `f64` vector so the
 /// optimizer can work in a uniform parameter space. Parameter order:
 /// corner, edge, antiedge, anticorner, disc[opening],
 /// disc[midgame], disc[endgame], mobility[opening],
-/// mobility[midgame], mobility[endgame].
-fn cfg_to_vec(cfg: &EvalCfg) -> [f64; TUNE_DIM] {
+/// mobility[midgame], mobility[endgame], potential_mobility, parity,
+/// parity_empties, frontier, stable, tempo.
+/// New terms are appended so existing coefficient strings keep their
+/// meaning.
+pub fn cfg_to_vec(cfg: &EvalCfg) -> [f64; TUNE_DIM] {
     [
         cfg.corner_value as f64,
         cfg.edge_value as f64,
@@ -234,10 +260,16 @@ fn cfg_to_vec(cfg: &EvalCfg) -> [f64; TUNE_DIM] {
         cfg.mobility_values[0] as f64,
         cfg.mobility_values[1] as f64,
         cfg.mobility_values[2] as f64,
+        cfg.potential_mobility_value as f64,
+        cfg.parity_value as f64,
+        cfg.parity_empties as f64,
+        cfg.frontier_value as f64,
+        cfg.stable_value as f64,
+        cfg.tempo_value as f64,
     ]
 }
 
-fn vec_to_cfg(v: &[f64; TUNE_DIM]) -> EvalCfg {
+pub fn vec_to_cfg(v: &[f64; TUNE_DIM]) -> EvalCfg {
     EvalCfg {
         corner_value: v[0].round() as i32,
         edge_value: v[1].round() as i32,
@@ -253,32 +285,18 @@ fn vec_to_cfg(v: &[f64; TUNE_DIM]) -> EvalCfg {
             v[8].round() as i32,
             v[9].round() as i32,
         ],
-    }
-}
-
-/// Tiny uniform RNG built on splitmix64. Sufficient for generating
-/// perturbation vectors; we don't need cryptographic randomness or a
-/// high-quality distribution here.
-struct Rng64(u64);
-
-impl Rng64 {
-    fn new(seed: u64) -> Self {
-        // Avoid the all-zero fixed point of some LCG-family PRNGs by
-        // forcing a non-zero mix of the seed.
-        Self(splitmix64(seed ^ 0xA5A5_5A5A_DEAD_BEEF))
-    }
-
-    fn next_u64(&mut self) -> u64 {
-        self.0 = splitmix64(self.0);
-        self.0
-    }
-
-    /// Uniform in [-1.0, 1.0).
-    fn unit(&mut self) -> f64 {
-        // 53-bit precision mantissa from the upper bits, mapped to
-        // [0,1), then shifted to [-1,1).
-        let x = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
-        2.0 * x - 1.0
+        potential_mobility_value: v[10].round() as i32,
+        parity_value: v[11].round() as i32,
+        parity_empties: v[12].round() as i32,
+        frontier_value: v[13].round() as i32,
+        stable_value: v[14].round() as i32,
+        disc_only_empties: DEFAULT_CFG.disc_only_empties,
+        tempo_value: v[15].round() as i32,
+        wipeout_discs: DEFAULT_CFG.wipeout_discs,
+        wipeout_value: DEFAULT_CFG.wipeout_value,
+        corner_extension: DEFAULT_CFG.corner_extension,
+        pvs: DEFAULT_CFG.pvs,
+        max_heuristic_eval: DEFAULT_CFG.max_heuristic_eval,
     }
 }
 
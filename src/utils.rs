@@ -1,3 +1,13 @@
+use std::fmt;
+
+use reversi_tools::position::{
+    apply_move_unchecked, check_game_status, move_to_algebraic, move_to_bitmap,
+};
+
+/// Standard starting position bitboards.
+pub const START_BLACK: u64 = 0x0000000810000000;
+pub const START_WHITE: u64 = 0x0000001008000000;
+
 pub fn print_board(white: u64, black: u64, last_move: u64, flips: u64, mark_last_move: bool) {
     let mut res: String = "========\n".to_string();
     for i in 0..8 {
@@ -26,6 +36,48 @@ pub fn print_board(white: u64, black: u64, last_move: u64, flips: u64, mark_last
     println!("{}", res);
 }
 
+/// Animated terminal replay of `moves` from the standard position. Each
+/// ply clears the screen and redraws the board with the move and the
+/// discs it flipped highlighted, then waits `delay_ms`; passes get a frame
+/// of their own. Stops with an error at the first illegal move.
+pub fn animate_replay(moves: &[u64], delay_ms: u64) -> Result<(), String> {
+    let frame = |caption: &str, white: u64, black: u64, last_move: u64, flips: u64| {
+        print!("\x1b[2J\x1b[H");
+        println!("{}", caption);
+        print_board(white, black, last_move, flips, last_move != 0);
+        println!("Black: {}, white: {}", black.count_ones(), white.count_ones());
+        std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+    };
+    let (mut white, mut black, mut white_to_move) = (START_WHITE, START_BLACK, false);
+    frame("Start", white, black, 0, 0);
+    for (i, &mv) in moves.iter().enumerate() {
+        let color = |white_to_move: bool| if white_to_move { "White" } else { "Black" };
+        if check_game_status(white, black, white_to_move) == u64::MAX {
+            frame(&format!("{} passes", color(white_to_move)), white, black, 0, 0);
+            white_to_move = !white_to_move;
+        }
+        let (w, b) = apply_move_verbose(white, black, mv, white_to_move).map_err(|e| {
+            format!("move #{} ({}): {}", i + 1, move_to_algebraic(mv).unwrap_or_default(), e)
+        })?;
+        let flips = if white_to_move { w & black } else { b & white };
+        frame(
+            &format!(
+                "Ply {}: {} plays {}",
+                i + 1,
+                color(white_to_move),
+                move_to_algebraic(mv).unwrap_or_default()
+            ),
+            w,
+            b,
+            mv,
+            flips,
+        );
+        (white, black) = (w, b);
+        white_to_move = !white_to_move;
+    }
+    Ok(())
+}
+
 pub fn apply_move_verbose(
     white: u64,
     black: u64,
@@ -112,3 +164,258 @@ pub fn splitmix64(mut x: u64) -> u64 {
     x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
     x ^ (x >> 31)
 }
+
+/// Tiny uniform RNG built on splitmix64. Sufficient for generating
+/// perturbation vectors and picking among moves; we don't need
+/// cryptographic randomness or a high-quality distribution here.
+pub struct Rng64(u64);
+
+impl Rng64 {
+    pub fn new(seed: u64) -> Self {
+        // Avoid the all-zero fixed point of some LCG-family PRNGs by
+        // forcing a non-zero mix of the seed.
+        Self(splitmix64(seed ^ 0xA5A5_5A5A_DEAD_BEEF))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = splitmix64(self.0);
+        self.0
+    }
+
+    /// Uniform in [-1.0, 1.0).
+    pub fn unit(&mut self) -> f64 {
+        // 53-bit precision mantissa from the upper bits, mapped to
+        // [0,1), then shifted to [-1,1).
+        let x = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        2.0 * x - 1.0
+    }
+
+    /// Uniform in [0, n); `n` must be non-zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+// --------------------------------------------------------------------------
+// Move notation
+// --------------------------------------------------------------------------
+
+/// A move as exchanged with the reversi server or written in transcripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Move {
+    /// Single-bit move mask.
+    Play(u64),
+    Pass,
+    Resign,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveParseError {
+    Empty,
+    /// Not `pass`/`resign` and not two characters long.
+    BadFormat(String),
+    /// Two characters, but not a square between `a1` and `h8`.
+    BadSquare(String),
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveParseError::Empty => write!(f, "empty move string"),
+            MoveParseError::BadFormat(s) => {
+                write!(f, "{:?} is neither a square, \"pass\" nor \"resign\"", s)
+            }
+            MoveParseError::BadSquare(s) => write!(f, "{:?} is not a square from a1 to h8", s),
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+/// Parse `pass`, `resign` or an algebraic square (case-insensitive,
+/// surrounding whitespace ignored). Never panics, so it is safe to feed
+/// untrusted server input.
+pub fn parse_move(s: &str) -> Result<Move, MoveParseError> {
+    let t = s.trim().to_ascii_lowercase();
+    match t.as_str() {
+        "" => return Err(MoveParseError::Empty),
+        "pass" => return Ok(Move::Pass),
+        "resign" => return Ok(Move::Resign),
+        _ => {}
+    }
+    let b = t.as_bytes();
+    if b.len() != 2 {
+        return Err(MoveParseError::BadFormat(s.to_string()));
+    }
+    if !(b'a'..=b'h').contains(&b[0]) || !(b'1'..=b'8').contains(&b[1]) {
+        return Err(MoveParseError::BadSquare(s.to_string()));
+    }
+    match move_to_bitmap(t.as_str()).unwrap_or_default() {
+        0 => Err(MoveParseError::BadSquare(s.to_string())),
+        bit => Ok(Move::Play(bit)),
+    }
+}
+
+/// Inverse of [`parse_move`]: the server's wire form of a move.
+pub fn format_move(m: Move) -> String {
+    match m {
+        Move::Play(bit) => move_to_algebraic(bit).unwrap_or_default(),
+        Move::Pass => "pass".to_string(),
+        Move::Resign => "resign".to_string(),
+    }
+}
+
+/// Final-score line for a finished game: both disc counts and Black's
+/// margin, then the verdict of `status` (a `check_game_status` code for
+/// the final board). A game-over status that contradicts the disc counts
+/// is flagged rather than silently trusted.
+pub fn final_result(white: u64, black: u64, status: u64) -> String {
+    let (b, w) = (black.count_ones(), white.count_ones());
+    let (verdict, consistent) = match status {
+        s if s == u64::MAX - 1 => ("black won", b > w),
+        s if s == u64::MAX - 2 => ("white won", w > b),
+        s if s == u64::MAX - 3 => ("draw", b == w),
+        _ => ("no game-over status on the board", true),
+    };
+    let mut line = format!(
+        "Black {} - white {} (margin {:+}): {}",
+        b,
+        w,
+        b as i32 - w as i32,
+        verdict
+    );
+    if !consistent {
+        line += " [status disagrees with the disc counts!]";
+    }
+    line
+}
+
+/// Self-play sanity failure - a move that did not add discs to the board,
+/// or an engine move the board rejects. Both mean a movegen or terminal
+/// detection bug, so the game is logged in full (start position, moves
+/// played, current position). Panics in debug builds; in release the
+/// caller is expected to abandon the game.
+pub fn report_stuck_game(
+    reason: &str,
+    start: (u64, u64, bool),
+    history: &[u64],
+    white: u64,
+    black: u64,
+    white_to_move: bool,
+) {
+    let transcript: Vec<String> = history
+        .iter()
+        .map(|&mv| move_to_algebraic(mv).unwrap_or_default())
+        .collect();
+    let msg = format!(
+        "self-play game stuck: {}; start w {} b {} wtm {}; moves: {}; now w {} b {} wtm {}",
+        reason,
+        start.0,
+        start.1,
+        start.2,
+        transcript.join(" "),
+        white,
+        black,
+        white_to_move
+    );
+    if cfg!(debug_assertions) {
+        panic!("{}", msg);
+    }
+    eprintln!("{}", msg);
+}
+
+// --------------------------------------------------------------------------
+// Transcripts
+// --------------------------------------------------------------------------
+
+/// Split an Othello transcript such as `"f5d6c3"` into move bitmaps.
+/// Whitespace and commas between moves are ignored and coordinates are
+/// case-insensitive.
+pub fn parse_transcript(line: &str) -> Result<Vec<u64>, String> {
+    let chars: Vec<char> = line
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ',')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    if chars.len() % 2 != 0 {
+        return Err(format!("transcript {:?} has an odd number of characters", line));
+    }
+    let mut moves = Vec::with_capacity(chars.len() / 2);
+    for pair in chars.chunks(2) {
+        let coord: String = pair.iter().collect();
+        match parse_move(&coord) {
+            Ok(Move::Play(bit)) => moves.push(bit),
+            Ok(other) => {
+                return Err(format!("unexpected {:?} in transcript {:?}", other, line));
+            }
+            Err(e) => return Err(format!("{} in transcript {:?}", e, line)),
+        }
+    }
+    Ok(moves)
+}
+
+/// Why [`apply_moves`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// Neither side had a legal move left.
+    GameOver,
+    /// Not a single square, or not a legal move for the side to move.
+    Illegal,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::GameOver => write!(f, "game is already over"),
+            MoveError::Illegal => write!(f, "illegal move"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Apply `moves` to the given position, inserting passes whenever the
+/// side to move has no legal move. Each move is checked against the
+/// legal-move mask once and then played with `apply_move_unchecked`. A
+/// pass owed by the final side to move is applied too, so the returned
+/// side always has a move unless the game is over. On failure, returns
+/// the index of the offending move.
+pub fn apply_moves(
+    white: u64,
+    black: u64,
+    is_white: bool,
+    moves: &[u64],
+) -> Result<(u64, u64, bool), (usize, MoveError)> {
+    let (mut white, mut black, mut white_to_move) = (white, black, is_white);
+    for (i, &mv) in moves.iter().enumerate() {
+        let mut legal = check_game_status(white, black, white_to_move);
+        if legal == u64::MAX {
+            white_to_move = !white_to_move;
+            legal = check_game_status(white, black, white_to_move);
+        }
+        if legal >= u64::MAX - 3 {
+            return Err((i, MoveError::GameOver));
+        }
+        if mv.count_ones() != 1 || legal & mv == 0 {
+            return Err((i, MoveError::Illegal));
+        }
+        (white, black) = apply_move_unchecked(white, black, mv, white_to_move);
+        white_to_move = !white_to_move;
+    }
+    if check_game_status(white, black, white_to_move) == u64::MAX {
+        white_to_move = !white_to_move;
+    }
+    Ok((white, black, white_to_move))
+}
+
+/// Replay `moves` from the standard starting position; see [`apply_moves`].
+pub fn replay_transcript(moves: &[u64]) -> Result<(u64, u64, bool), String> {
+    apply_moves(START_WHITE, START_BLACK, false, moves).map_err(|(i, e)| {
+        format!(
+            "move #{} ({}): {}",
+            i + 1,
+            move_to_algebraic(moves[i]).unwrap_or_default(),
+            e
+        )
+    })
+}